@@ -0,0 +1,97 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Reading how long ago a path was last modified or accessed.
+
+use FileTime;
+use std::convert::TryFrom;
+use std::io;
+use std::path::Path;
+use std::time::{Duration, SystemTime};
+use sys;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Which of a path's timestamps to read.
+pub enum TimestampKind {
+    /// The access timestamp.
+    Accessed,
+    /// The modification timestamp.
+    Modified,
+    /// The change timestamp, i.e. when the path's metadata was last changed.
+    Changed,
+    /// The creation timestamp.
+    Created,
+}
+
+impl TimestampKind {
+    #[inline]
+    /// Returns the name of this kind of timestamp, for use in messages.
+    pub(crate) fn name(self) -> &'static str {
+        match self {
+            TimestampKind::Accessed => "access",
+            TimestampKind::Modified => "modification",
+            TimestampKind::Changed => "change",
+            TimestampKind::Created => "creation",
+        }
+    }
+}
+
+#[inline]
+/// Returns the time since a path was last modified, following symbolic links.
+///
+/// Timestamps in the future have an age of zero.
+pub fn age<P: AsRef<Path>>(path: P) -> io::Result<Duration> {
+    age_of(path, TimestampKind::Modified, true)
+}
+
+#[inline]
+/// Returns the time since a path was last modified, without following symbolic links.
+///
+/// Timestamps in the future have an age of zero.
+pub fn age_nofollow<P: AsRef<Path>>(path: P) -> io::Result<Duration> {
+    age_of(path, TimestampKind::Modified, false)
+}
+
+#[inline]
+/// Returns the time since one of a path's timestamps, optionally following symbolic links.
+///
+/// Timestamps in the future have an age of zero.
+pub fn age_of<P: AsRef<Path>>(
+    path: P,
+    kind: TimestampKind,
+    follow_symlinks: bool,
+) -> io::Result<Duration> {
+    age_at(path.as_ref(), kind, follow_symlinks, SystemTime::now())
+}
+
+/// Returns the time between one of a path's timestamps and `now`.
+///
+/// Fails with an error of kind `Unsupported` if the creation timestamp is requested but the
+/// platform or filesystem does not record it.
+pub fn age_at(
+    path: &Path,
+    kind: TimestampKind,
+    follow_symlinks: bool,
+    now: SystemTime,
+) -> io::Result<Duration> {
+    let times = sys::read_times(path, follow_symlinks)?;
+    let time = match kind {
+        TimestampKind::Accessed => times.accessed(),
+        TimestampKind::Modified => times.modified(),
+        TimestampKind::Changed => times.changed(),
+        TimestampKind::Created => times.created().ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::Unsupported,
+                "creation timestamps are not recorded for this path",
+            )
+        })?,
+    };
+    let nanos = (FileTime::from(now).unix_nanos() - time.unix_nanos()).max(0);
+    let seconds = u64::try_from(nanos / 1_000_000_000).unwrap_or(u64::MAX);
+    let subsec = u32::try_from(nanos % 1_000_000_000).unwrap_or_default();
+    Ok(Duration::new(seconds, subsec))
+}
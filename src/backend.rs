@@ -0,0 +1,217 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Updating paths through something other than the operating system's filesystem calls.
+
+use {Builder, CreationTarget, FileTime, Timestamp, Timestamps};
+use std::fmt::{self, Debug, Formatter};
+use std::fs::{self, OpenOptions};
+use std::io;
+use std::path::Path;
+use sys::{self, EncodedPath, FileTimes};
+
+/// A way of opening, creating and setting the timestamps of paths, used by `Builder::backend`
+/// instead of the operating system.
+///
+/// A backend only carries out the steps it is asked to; deciding which steps to take is left to
+/// the builder. This allows paths that the operating system cannot reach directly, such as
+/// those of a FUSE client library or an object storage gateway, to be updated with the same
+/// options as local paths, and allows tests to substitute a double for the filesystem.
+///
+/// Errors are given the path and operation they came from, as described for `Error`, and are
+/// otherwise returned to the caller unchanged.
+pub trait Backend: Debug + Send + Sync {
+    /// Returns an error if the backend cannot set one of the timestamps, before any path is
+    /// opened or created.
+    ///
+    /// By default, every timestamp is assumed to be supported.
+    fn check(&self, times: &NewTimes) -> io::Result<()> {
+        let _ = times;
+        Ok(())
+    }
+
+    /// Checks that a path exists and that its timestamps may be set.
+    ///
+    /// This must fail with an error of kind `NotFound` if the path does not exist, so that it is
+    /// created if the builder has a creation target. A symbolic link whose target does not
+    /// exist must only be found if it is not followed, so that the builder can tell it apart from
+    /// a missing path.
+    fn open(&self, path: &Path, follow_symlinks: bool) -> io::Result<()>;
+
+    /// Creates an empty file at a path that did not exist.
+    ///
+    /// If the path was created by someone else in the meantime, this should fail with an error
+    /// of kind `AlreadyExists`, so that the path is reported as updated instead. If the path is a
+    /// symbolic link whose target does not exist, the target is created instead; the builder
+    /// only asks for this if `DanglingSymlinks::CreateTarget` applies to the path.
+    fn create(&self, path: &Path) -> io::Result<()>;
+
+    /// Creates a directory whose parent exists, for `Builder::create_parents`.
+    fn create_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Removes an empty directory created by `create_dir`, for `Builder::rollback_parents`.
+    fn remove_dir(&self, path: &Path) -> io::Result<()>;
+
+    /// Sets the timestamps of an existing path, leaving those that are `None` unchanged.
+    fn set_times(&self, path: &Path, times: &NewTimes, follow_symlinks: bool) -> io::Result<()>;
+
+    /// Reads the timestamps of an existing path.
+    fn read_times(&self, path: &Path, follow_symlinks: bool) -> io::Result<Timestamps>;
+}
+
+#[derive(Clone, Copy)]
+/// The timestamps a backend is asked to set on a path.
+///
+/// The builder has already clamped these and read the current time for any set to it.
+pub struct NewTimes<'a> {
+    /// The new access timestamp, if any.
+    accessed: Option<FileTime>,
+    /// The new modification timestamp, if any.
+    modified: Option<FileTime>,
+    /// The new creation timestamp, if any.
+    created: Option<FileTime>,
+    /// The new change timestamp, if any.
+    changed: Option<FileTime>,
+    /// The builder the timestamps came from, whose other options `Native` applies.
+    options: &'a Builder,
+}
+
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+/// A backend that uses the operating system, as a builder does when it is not given one.
+///
+/// Timestamps are set with the other options of the builder, such as `sync_file` or
+/// `share_mode`, just as they are without a backend. A builder without a backend opens each path
+/// only once, though, so this is slower than using no backend at all. It is meant for backends
+/// that wrap the operating system, such as test doubles that record calls or inject failures.
+pub struct Native;
+
+impl<'a> NewTimes<'a> {
+    #[inline]
+    /// Returns the timestamps a builder sets, reading the current time if necessary.
+    pub(crate) fn from_builder(builder: &'a Builder) -> Self {
+        let now = builder.now();
+        let at = |time: Option<Timestamp>| {
+            time.map(|t| match t {
+                Timestamp::At(t) => t,
+                Timestamp::Now => now,
+            })
+        };
+        #[cfg(feature = "change-time")]
+        let changed = at(builder.changed_time());
+        #[cfg(not(feature = "change-time"))]
+        let changed = None;
+        Self {
+            accessed: at(builder.accessed_time()),
+            modified: at(builder.modified_time()),
+            created: at(builder.created_time()),
+            changed,
+            options: builder,
+        }
+    }
+
+    #[inline]
+    /// Returns the new access timestamp, if any.
+    pub fn accessed(&self) -> Option<FileTime> {
+        self.accessed
+    }
+
+    #[inline]
+    /// Returns the new modification timestamp, if any.
+    pub fn modified(&self) -> Option<FileTime> {
+        self.modified
+    }
+
+    #[inline]
+    /// Returns the new creation timestamp, if any.
+    pub fn created(&self) -> Option<FileTime> {
+        self.created
+    }
+
+    #[inline]
+    /// Returns the new change timestamp, if any.
+    ///
+    /// This is only ever set if the `change-time` feature is enabled.
+    pub fn changed(&self) -> Option<FileTime> {
+        self.changed
+    }
+}
+
+impl<'a> Debug for NewTimes<'a> {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("NewTimes")
+            .field("accessed", &self.accessed)
+            .field("modified", &self.modified)
+            .field("created", &self.created)
+            .field("changed", &self.changed)
+            .finish()
+    }
+}
+
+impl Native {
+    /// Returns a builder that sets exactly the given timestamps on an existing path, with the
+    /// other options of the builder they came from.
+    fn options(times: &NewTimes, follow_symlinks: bool) -> Builder {
+        let mut options = times.options.clone();
+        options.accessed = times.accessed.map(Timestamp::At);
+        options.modified = times.modified.map(Timestamp::At);
+        options.created = times.created;
+        #[cfg(feature = "change-time")]
+        {
+            options.changed = times.changed;
+        }
+        options.follow_symlinks = follow_symlinks;
+        options.creation_target = CreationTarget::None;
+        options.backend = None;
+        options
+    }
+}
+
+impl Backend for Native {
+    fn check(&self, times: &NewTimes) -> io::Result<()> {
+        FileTimes::from_builder(&Self::options(times, true)).check()
+    }
+
+    fn open(&self, path: &Path, follow_symlinks: bool) -> io::Result<()> {
+        if follow_symlinks {
+            fs::metadata(path).map(|_| ())
+        } else {
+            fs::symlink_metadata(path).map(|_| ())
+        }
+    }
+
+    fn create(&self, path: &Path) -> io::Result<()> {
+        // Exclusive creation refuses to follow symbolic links, so it cannot create their targets.
+        let dangling = fs::symlink_metadata(path).is_ok_and(|m| m.file_type().is_symlink());
+        OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .create_new(!dangling)
+            .open(path)
+            .map(|_| ())
+    }
+
+    fn create_dir(&self, path: &Path) -> io::Result<()> {
+        fs::create_dir(path)
+    }
+
+    fn remove_dir(&self, path: &Path) -> io::Result<()> {
+        fs::remove_dir(path)
+    }
+
+    fn set_times(&self, path: &Path, times: &NewTimes, follow_symlinks: bool) -> io::Result<()> {
+        let options = Self::options(times, follow_symlinks);
+        let raw = EncodedPath::new(path)?;
+        options
+            .touch_sys(path, raw.as_raw(), &FileTimes::from_builder(&options))
+            .map(|_| ())
+    }
+
+    fn read_times(&self, path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+        sys::read_times(path, follow_symlinks)
+    }
+}
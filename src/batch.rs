@@ -0,0 +1,237 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Shared machinery for operations on multiple paths.
+
+use {Builder, CancellationToken, ErrorPolicy, Operation, Outcome, Progress, ProgressSink, Report,
+     ReportEntry};
+use dedup::Deduplicator;
+use error;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, MutexGuard};
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::{Instant, SystemTime};
+use sys::{self, FileTimes};
+use throttle::Throttle;
+
+/// Applies a builder's options to a sequence of paths, collecting the results into a report.
+///
+/// A batch may be shared between threads.
+pub struct Batch<'a> {
+    /// The builder whose options are applied.
+    builder: &'a Builder,
+    /// The timestamps to apply, converted once for the whole batch.
+    times: FileTimes,
+    /// Remembers which files were already updated, if deduplication is enabled.
+    dedup: Mutex<Deduplicator>,
+    /// The results so far.
+    entries: Mutex<Vec<ReportEntry>>,
+    /// Whether a failure has stopped the batch.
+    stopped: AtomicBool,
+    /// Allows the batch to be stopped from elsewhere.
+    cancellation: Option<CancellationToken>,
+    /// Receives progress snapshots, if any.
+    progress: Option<ProgressSink>,
+    /// How many paths have been processed so far.
+    processed: AtomicUsize,
+    /// How many of the processed paths could not be updated.
+    failed: AtomicUsize,
+    /// When progress was last reported.
+    last_progress: Mutex<Instant>,
+    /// Limits how quickly paths are updated.
+    throttle: Throttle,
+    /// When the batch started.
+    start: Instant,
+    /// The time that the ages of paths are measured against.
+    now: SystemTime,
+}
+
+impl<'a> Batch<'a> {
+    #[inline]
+    /// Starts a new batch.
+    pub fn new(builder: &'a Builder) -> Self {
+        let in_flight = max_in_flight(builder.max_in_flight, sys::max_concurrent_operations());
+        Self {
+            builder,
+            times: FileTimes::from_builder(builder),
+            dedup: Mutex::new(Deduplicator::new()),
+            entries: Mutex::new(Vec::new()),
+            stopped: AtomicBool::new(false),
+            cancellation: builder.cancellation.clone(),
+            progress: builder.progress.clone(),
+            processed: AtomicUsize::new(0),
+            failed: AtomicUsize::new(0),
+            last_progress: Mutex::new(Instant::now()),
+            throttle: Throttle::new(builder.ops_per_second, in_flight),
+            start: Instant::now(),
+            now: SystemTime::now(),
+        }
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path.
+    ///
+    /// Returns whether the batch should continue.
+    pub fn touch(&self, path: &Path) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
+        let result = self.apply(path);
+        self.push(path.to_path_buf(), result)
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path, returning the result instead of recording it.
+    ///
+    /// The batch is still stopped if the update failed and the error policy says so, and the
+    /// result still counts towards the batch's progress.
+    pub fn apply(&self, path: &Path) -> io::Result<Outcome> {
+        let builder = self.builder;
+        self.apply_with(path, |times| builder.touch_with_times(path, times))
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path using a custom function.
+    ///
+    /// Returns whether the batch should continue.
+    pub fn touch_with<F>(&self, path: &Path, f: F) -> bool
+    where
+        F: FnOnce(&FileTimes) -> io::Result<Outcome>,
+    {
+        if self.is_stopped() {
+            return false;
+        }
+        let result = self.apply_with(path, f);
+        self.push(path.to_path_buf(), result)
+    }
+
+    /// Updates the timestamps for a path using a custom function, returning the result instead
+    /// of recording it.
+    fn apply_with<F>(&self, path: &Path, f: F) -> io::Result<Outcome>
+    where
+        F: FnOnce(&FileTimes) -> io::Result<Outcome>,
+    {
+        let builder = self.builder;
+        let follow = builder.follows(path);
+        let result = if !builder.age.matches(path, follow, self.now)
+            || builder.deduplicate && lock(&self.dedup).is_duplicate(path, follow)
+        {
+            Ok(Outcome::Skipped)
+        } else {
+            let _permit = self.throttle.acquire();
+            f(&self.times)
+        };
+        match result {
+            Ok(Outcome::Created | Outcome::CreatedSymlinkTarget) if builder.deduplicate => {
+                lock(&self.dedup).record_created(path, follow)
+            }
+            _ => (),
+        }
+        self.note(path, result.is_err());
+        result.map_err(error::context(Operation::SetTimes, path))
+    }
+
+    #[cfg(feature = "rayon")]
+    #[inline]
+    /// Records results that were returned by `apply`.
+    pub fn extend<I: IntoIterator<Item = ReportEntry>>(&self, entries: I) {
+        lock(&self.entries).extend(entries)
+    }
+
+    #[inline]
+    /// Records a failure that occurred before a path could be updated.
+    ///
+    /// Returns whether the batch should continue.
+    pub fn fail(&self, path: PathBuf, err: io::Error) -> bool {
+        if self.is_stopped() {
+            return false;
+        }
+        self.note(&path, true);
+        let err = error::context(Operation::Open, &path)(err);
+        self.push(path, Err(err))
+    }
+
+    #[inline]
+    /// Returns whether a failure or cancellation has stopped the batch.
+    pub fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::Relaxed) || self.is_cancelled()
+    }
+
+    #[inline]
+    /// Returns whether the batch has been cancelled.
+    fn is_cancelled(&self) -> bool {
+        self.cancellation
+            .as_ref()
+            .is_some_and(CancellationToken::is_cancelled)
+    }
+
+    #[inline]
+    /// Finishes the batch, returning its report.
+    pub fn finish(self) -> Report {
+        let cancelled = self.is_cancelled();
+        let entries = self.entries
+            .into_inner()
+            .unwrap_or_else(|e| e.into_inner());
+        Report::new(entries, self.start.elapsed(), cancelled)
+    }
+
+    #[inline]
+    /// Records the result for a path.
+    ///
+    /// Returns whether the batch should continue.
+    fn push(&self, path: PathBuf, result: io::Result<Outcome>) -> bool {
+        lock(&self.entries).push(ReportEntry::new(path, result));
+        !self.is_stopped()
+    }
+
+    /// Takes note of a processed path, stopping the batch or reporting progress as needed.
+    fn note(&self, path: &Path, failed: bool) {
+        let processed = self.processed.fetch_add(1, Ordering::Relaxed) + 1;
+        let failed = if failed {
+            if self.builder.error_policy == ErrorPolicy::FailFast {
+                self.stopped.store(true, Ordering::Relaxed);
+            }
+            self.failed.fetch_add(1, Ordering::Relaxed) + 1
+        } else {
+            self.failed.load(Ordering::Relaxed)
+        };
+        let sink = match self.progress {
+            Some(ref sink) => sink,
+            None => return,
+        };
+        // If another thread is already reporting progress, this snapshot would be redundant.
+        if let Ok(mut last) = self.last_progress.try_lock() {
+            if last.elapsed() >= sink.interval() {
+                *last = Instant::now();
+                sink.report(&Progress::new(processed, failed, path, self.start.elapsed()));
+            }
+        }
+    }
+}
+
+#[inline]
+/// Returns the maximum number of paths a builder may update at once, given the number it asked
+/// for and the number that the process's file descriptor or handle limit allows.
+///
+/// This never exceeds the latter, so that large parallel operations are spread out instead of
+/// failing with `EMFILE`.
+pub fn max_in_flight(requested: Option<usize>, limit: Option<usize>) -> Option<usize> {
+    match (requested, limit) {
+        (Some(n), Some(limit)) if n > 0 => Some(n.min(limit)),
+        (Some(n), None) if n > 0 => Some(n),
+        (_, limit) => limit,
+    }
+}
+
+#[inline]
+/// Locks a mutex, ignoring poisoning.
+///
+/// The data guarded by a batch's mutexes remains consistent even if a thread panics.
+pub fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
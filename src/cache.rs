@@ -0,0 +1,116 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Remembering prepared paths for programs that update the same few paths over and over.
+
+use PreparedPath;
+use std::collections::HashMap;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Debug)]
+/// A prepared path, along with when it was last used.
+struct Entry {
+    /// The prepared path.
+    path: PreparedPath,
+    /// The value of the cache's clock when the path was last used.
+    last_used: u64,
+}
+
+#[derive(Clone, Debug)]
+/// A cache of prepared paths, which forgets the least recently used path once it is full.
+///
+/// This suits watchdogs and heartbeats that keep updating the same small set of paths through
+/// `Builder::touch_cached`, without having to keep a `PreparedPath` for each of them. Finding the
+/// least recently used path takes time proportional to the capacity, so the capacity should be
+/// small.
+///
+/// Only the converted paths are remembered, not open files or handles, so a path that is
+/// replaced or renamed between updates is still resolved again each time.
+pub struct PathCache {
+    /// The most paths to remember at once.
+    capacity: usize,
+    /// The paths remembered so far.
+    entries: HashMap<PathBuf, Entry>,
+    /// Counts how many times a path has been looked up.
+    clock: u64,
+}
+
+impl PathCache {
+    #[inline]
+    /// Creates a new, empty cache that remembers up to `capacity` paths.
+    ///
+    /// A capacity of zero is treated as one.
+    pub fn new(capacity: usize) -> Self {
+        let capacity = capacity.max(1);
+        Self {
+            capacity,
+            entries: HashMap::with_capacity(capacity),
+            clock: 0,
+        }
+    }
+
+    /// Returns a path prepared for updating, preparing and remembering it if necessary.
+    ///
+    /// Fails like `PreparedPath::new` if the path has to be prepared.
+    pub fn get<P: AsRef<Path>>(&mut self, path: P) -> io::Result<&PreparedPath> {
+        let path = path.as_ref();
+        self.clock += 1;
+        if !self.entries.contains_key(path) {
+            let prepared = PreparedPath::new(path)?;
+            if self.entries.len() >= self.capacity {
+                self.evict();
+            }
+            let _ = self.entries.insert(
+                path.to_path_buf(),
+                Entry {
+                    path: prepared,
+                    last_used: 0,
+                },
+            );
+        }
+        // The entry was either already present or has just been inserted.
+        let entry = self.entries.get_mut(path).expect("cached path is missing");
+        entry.last_used = self.clock;
+        Ok(&entry.path)
+    }
+
+    #[inline]
+    /// Returns how many paths are remembered.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    /// Returns whether no paths are remembered.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    /// Returns whether a path is remembered, without counting as a use of it.
+    pub fn contains<P: AsRef<Path>>(&self, path: P) -> bool {
+        self.entries.contains_key(path.as_ref())
+    }
+
+    #[inline]
+    /// Forgets every path.
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Forgets the least recently used path.
+    fn evict(&mut self) {
+        let oldest = self.entries
+            .iter()
+            .min_by_key(|&(_, entry)| entry.last_used)
+            .map(|(path, _)| path.clone());
+        if let Some(path) = oldest {
+            let _ = self.entries.remove(&path);
+        }
+    }
+}
@@ -0,0 +1,39 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Cancellation of operations on multiple paths.
+
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+
+#[derive(Clone, Debug, Default)]
+/// A handle for stopping an operation on multiple paths from another thread.
+///
+/// Clones of a token share the same state, so cancelling one clone cancels them all.
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    #[inline]
+    /// Creates a new token that has not been cancelled.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    /// Requests that any operation using this token stops as soon as possible.
+    ///
+    /// Paths that are already being updated are finished, but no further paths are started.
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed)
+    }
+
+    #[inline]
+    /// Returns whether this token has been cancelled.
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
@@ -0,0 +1,115 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Updating paths beneath a `cap-std` directory, without ambient authority.
+
+use {Builder, CreationTarget, DanglingSymlinks, Operation, Outcome, Timestamp, TimestampKind,
+     TimestampOutOfRange, UnsupportedTimestamp, WouldOpen};
+use cap_fs_ext::{DirExt, FollowSymlinks, OpenOptionsFollowExt, OpenOptionsMaybeDirExt,
+                 SystemTimeSpec};
+use cap_std::fs::{Dir, OpenOptions};
+use cap_std::time::SystemTime;
+use error::context;
+use std::io;
+use std::path::Path;
+use strict;
+
+/// Updates the timestamps for a path beneath a directory, using the options given to a builder.
+pub(crate) fn touch(builder: &Builder, dir: &Dir, path: &Path) -> io::Result<Outcome> {
+    #[cfg(feature = "change-time")]
+    {
+        if builder.changed_time().is_some() {
+            return Err(UnsupportedTimestamp::new(TimestampKind::Changed).into());
+        }
+    }
+    if builder.created_time().is_some() {
+        return Err(UnsupportedTimestamp::new(TimestampKind::Created).into());
+    }
+    // `cap-std` opens every path it sets the timestamps of.
+    if builder.existing_only {
+        return Err(WouldOpen::new("touch_cap").into());
+    }
+    strict::check(builder)?;
+    builder.retrying(|| touch_once(builder, dir, path))
+}
+
+/// Makes a single attempt at updating a path beneath a directory.
+fn touch_once(builder: &Builder, dir: &Dir, path: &Path) -> io::Result<Outcome> {
+    // `SystemTimeSpec` cannot be copied, so the timestamps are converted for every call.
+    let set_times = || {
+        let accessed = into_spec(builder.accessed_time())?;
+        let modified = into_spec(builder.modified_time())?;
+        if builder.follow_symlinks {
+            dir.set_times(path, accessed, modified)
+        } else {
+            dir.set_symlink_times(path, accessed, modified)
+        }
+    };
+    let is_symlink = || {
+        dir.symlink_metadata(path)
+            .is_ok_and(|m| m.file_type().is_symlink())
+    };
+    // Finishes updating a path that already existed.
+    let updated = || {
+        if builder.sync_file && (builder.follow_symlinks || !is_symlink()) {
+            let mut options = OpenOptions::new();
+            let _ = options.read(true).maybe_dir(true);
+            dir.open_with(path, &options)
+                .map_err(context(Operation::Open, path))?
+                .sync_all()
+                .map_err(context(Operation::Sync, path))?;
+        }
+        Ok(Outcome::Updated)
+    };
+    let e = match set_times() {
+        Ok(()) => return updated(),
+        Err(e) => e,
+    };
+    match builder.creation_target {
+        CreationTarget::File if e.kind() == io::ErrorKind::NotFound => (),
+        _ => return Err(context(Operation::SetTimes, path)(e)),
+    }
+    // Symbolic links are only left dangling here if they are followed.
+    let dangling = is_symlink();
+    let mut options = OpenOptions::new();
+    // Creating the path exclusively makes opening it fail if another process creates it first,
+    // so that it is reported as updated rather than created. Dangling symbolic links are always
+    // refused by exclusive creation.
+    let _ = options.write(true).create(true).create_new(!dangling);
+    if builder.dangling_symlinks == DanglingSymlinks::Refuse {
+        let _ = options.follow(FollowSymlinks::No);
+    }
+    let file = match dir.open_with(path, &options) {
+        Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && !dangling => {
+            set_times().map_err(context(Operation::SetTimes, path))?;
+            return updated();
+        }
+        result => result.map_err(context(Operation::Create, path))?,
+    };
+    set_times().map_err(context(Operation::SetTimes, path))?;
+    if builder.sync_file {
+        file.sync_all().map_err(context(Operation::Sync, path))?;
+    }
+    if dangling {
+        Ok(Outcome::CreatedSymlinkTarget)
+    } else {
+        Ok(Outcome::Created)
+    }
+}
+
+#[inline]
+/// Converts a timestamp into one that `cap-std` can set.
+fn into_spec(time: Option<Timestamp>) -> io::Result<Option<SystemTimeSpec>> {
+    match time {
+        Some(Timestamp::At(t)) => match t.to_system_time() {
+            Some(t) => Ok(Some(SystemTimeSpec::Absolute(SystemTime::from_std(t)))),
+            None => Err(TimestampOutOfRange::new(t).into()),
+        },
+        Some(Timestamp::Now) => Ok(Some(SystemTimeSpec::SymbolicNow)),
+        None => Ok(None),
+    }
+}
@@ -0,0 +1,68 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Comparing timestamps that may have been stored with different precision.
+//!
+//! A timestamp copied from one filesystem to another is rounded to the precision of the
+//! destination, so two timestamps that should be equal may differ slightly. These functions
+//! treat timestamps within a given tolerance of each other as equal.
+
+use FileTime;
+use std::convert::TryFrom;
+use std::time::Duration;
+
+/// The resolution of NTFS timestamps.
+pub const NTFS_RESOLUTION: Duration = Duration::from_nanos(100);
+/// The resolution of HFS+ timestamps.
+pub const HFS_PLUS_RESOLUTION: Duration = Duration::from_secs(1);
+/// The resolution of FAT modification timestamps.
+pub const FAT_RESOLUTION: Duration = Duration::from_secs(2);
+
+#[inline]
+/// Returns the difference between two timestamps in nanoseconds, and the tolerance in
+/// nanoseconds.
+fn difference(a: FileTime, b: FileTime, tolerance: Duration) -> (i128, i128) {
+    let tolerance = i128::try_from(tolerance.as_nanos()).unwrap_or(i128::MAX);
+    (a.unix_nanos() - b.unix_nanos(), tolerance)
+}
+
+#[inline]
+/// Returns whether `a` is later than `b` by more than `tolerance`.
+///
+/// This accepts the same types as `Builder::accessed`. For example, to check whether a file
+/// has changed since it was copied to a FAT filesystem, compare its modification timestamps with
+/// a tolerance of `FAT_RESOLUTION`.
+pub fn is_newer_than<A, B>(a: A, b: B, tolerance: Duration) -> bool
+where
+    A: Into<FileTime>,
+    B: Into<FileTime>,
+{
+    let (difference, tolerance) = difference(a.into(), b.into(), tolerance);
+    difference > tolerance
+}
+
+#[inline]
+/// Returns whether `a` is earlier than `b` by more than `tolerance`.
+pub fn is_older_than<A, B>(a: A, b: B, tolerance: Duration) -> bool
+where
+    A: Into<FileTime>,
+    B: Into<FileTime>,
+{
+    let (difference, tolerance) = difference(a.into(), b.into(), tolerance);
+    difference < -tolerance
+}
+
+#[inline]
+/// Returns whether `a` and `b` are no more than `tolerance` apart.
+pub fn is_same_time<A, B>(a: A, b: B, tolerance: Duration) -> bool
+where
+    A: Into<FileTime>,
+    B: Into<FileTime>,
+{
+    let (difference, tolerance) = difference(a.into(), b.into(), tolerance);
+    difference.abs() <= tolerance
+}
@@ -0,0 +1,81 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Conversions between `FileTime` and the timestamp formats used by operating systems.
+//!
+//! These are the conversions nudge itself applies, so that other tools can store and compare
+//! timestamps exactly as nudge does. They do not depend on the current platform.
+//!
+//! # Rounding
+//!
+//! * `SystemTime` is converted with `FileTime::from` and `FileTime::to_system_time`. Both are
+//!   lossless: every `SystemTime` survives the round trip, as does every `FileTime` that
+//!   `SystemTime` can represent.
+//! * Nanoseconds since the Unix epoch, as returned by `FileTime::unix_nanos`, are also lossless.
+//! * `timespec`, as used on Unix, has the same precision as `FileTime`. `to_timespec` is always
+//!   exact, and `from_timespec` is exact for any normalised `timespec`.
+//! * Windows ticks count 100-nanosecond intervals since January 1, 1601 UTC. `to_windows_ticks`
+//!   rounds towards negative infinity, i.e. to the latest tick that is not later than the
+//!   timestamp, and `from_windows_ticks` is exact. A timestamp therefore never moves forwards
+//!   through a round trip, and moves back by less than one tick.
+
+use FileTime;
+use std::convert::TryFrom;
+
+/// The number of seconds from the Windows epoch (January 1, 1601 UTC) to the Unix epoch.
+pub const WINDOWS_EPOCH_OFFSET: i64 = 11_644_473_600;
+
+/// The number of nanoseconds in one Windows tick.
+pub const NANOS_PER_WINDOWS_TICK: u32 = 100;
+
+/// The number of nanoseconds in one second.
+const NANOS_PER_SEC: i128 = 1_000_000_000;
+
+#[inline]
+/// Returns the seconds and nanoseconds of a `timespec` for a timestamp.
+///
+/// The seconds are rounded towards negative infinity, and the nanoseconds count forwards from
+/// them, so the nanoseconds are always less than one second and never negative. Whether the
+/// seconds fit in the platform's `time_t` is left to the caller.
+pub fn to_timespec(time: FileTime) -> (i64, i64) {
+    (time.unix_seconds(), i64::from(time.nanoseconds()))
+}
+
+#[inline]
+/// Creates a timestamp from the seconds and nanoseconds of a `timespec`.
+///
+/// Nanoseconds beyond one second carry over into the seconds, and negative nanoseconds borrow
+/// from them, so any `timespec` is accepted. Timestamps beyond the range of `FileTime` are
+/// clamped to it.
+pub fn from_timespec(seconds: i64, nanoseconds: i64) -> FileTime {
+    FileTime::from_unix_nanos(i128::from(seconds) * NANOS_PER_SEC + i128::from(nanoseconds))
+}
+
+#[inline]
+/// Converts a timestamp into 100-nanosecond intervals since the Windows epoch, rounding towards
+/// negative infinity.
+///
+/// Returns `None` if the timestamp is before the Windows epoch, or too late to be counted in an
+/// `i64`.
+pub fn to_windows_ticks(time: FileTime) -> Option<i64> {
+    let ticks_per_sec = NANOS_PER_SEC / i128::from(NANOS_PER_WINDOWS_TICK);
+    let seconds = i128::from(time.unix_seconds()) + i128::from(WINDOWS_EPOCH_OFFSET);
+    let ticks = seconds * ticks_per_sec +
+        i128::from(time.nanoseconds() / NANOS_PER_WINDOWS_TICK);
+    i64::try_from(ticks).ok().filter(|&t| t >= 0)
+}
+
+#[inline]
+/// Creates a timestamp from 100-nanosecond intervals since the Windows epoch.
+///
+/// Negative values, which Windows does not use for timestamps, count backwards from the epoch.
+pub fn from_windows_ticks(ticks: i64) -> FileTime {
+    FileTime::from_unix_nanos(
+        i128::from(ticks) * i128::from(NANOS_PER_WINDOWS_TICK) -
+            i128::from(WINDOWS_EPOCH_OFFSET) * NANOS_PER_SEC,
+    )
+}
@@ -0,0 +1,77 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Detection of duplicate paths in operations on multiple paths.
+
+use std::collections::HashSet;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use sys::{self, FileId};
+
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+/// Identifies what a path refers to.
+enum Key {
+    /// An existing file, identified independently of the path used to reach it.
+    File(FileId),
+    /// A path that does not exist yet, in canonical form where possible.
+    Path(PathBuf),
+}
+
+#[derive(Debug, Default)]
+/// Remembers which files have already been seen.
+pub struct Deduplicator {
+    /// The files seen so far.
+    seen: HashSet<Key>,
+}
+
+impl Deduplicator {
+    #[inline]
+    /// Creates a new, empty deduplicator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    #[inline]
+    /// Records a path, returning whether it refers to a file that was already seen.
+    ///
+    /// Existing files are compared by identity, so hard links and symbolic links (if followed)
+    /// to the same file are considered duplicates. Other paths are compared after canonicalising
+    /// their parent directories.
+    pub fn is_duplicate(&mut self, path: &Path, follow_symlinks: bool) -> bool {
+        let key = match sys::file_id(path, follow_symlinks) {
+            Ok(id) => Key::File(id),
+            Err(_) => Key::Path(canonical_path(path)),
+        };
+        !self.seen.insert(key)
+    }
+
+    #[inline]
+    /// Records a path that was created after it was checked by `is_duplicate`.
+    ///
+    /// This ensures that later paths reaching the new file by other means are also detected.
+    pub fn record_created(&mut self, path: &Path, follow_symlinks: bool) {
+        if let Ok(id) = sys::file_id(path, follow_symlinks) {
+            let _ = self.seen.insert(Key::File(id));
+        }
+    }
+}
+
+/// Canonicalises a path that may not exist, by canonicalising its parent directory instead.
+fn canonical_path(path: &Path) -> PathBuf {
+    let parent = match path.parent() {
+        Some(p) if p.as_os_str().is_empty() => Path::new("."),
+        Some(p) => p,
+        None => return path.to_path_buf(),
+    };
+    match (fs::canonicalize(parent), path.file_name()) {
+        (Ok(p), Some(name)) => p.join(name),
+        _ => env::current_dir()
+            .map(|d| d.join(path))
+            .unwrap_or_else(|_| path.to_path_buf()),
+    }
+}
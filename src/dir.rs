@@ -0,0 +1,36 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Updating paths relative to an open directory.
+
+use std::io;
+use std::path::Path;
+use sys::DirHandle;
+
+/// An open directory, relative to which paths can be updated using `Builder::touch_at`.
+///
+/// Relative paths are resolved from the directory itself rather than from its path, so they are
+/// unaffected if the directory is moved, and a sandboxed program can be given a directory to
+/// work in instead of access to absolute paths. On Unix, this holds a file descriptor for the
+/// directory. On Windows, timestamps cannot be updated relative to a handle, so the directory is
+/// instead kept open in a way that prevents it from being moved or deleted, and paths are
+/// resolved from the path it was opened with.
+pub struct Dir(DirHandle);
+
+impl Dir {
+    #[inline]
+    /// Opens an existing directory.
+    pub fn open<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        DirHandle::open(path.as_ref()).map(Dir)
+    }
+
+    #[inline]
+    /// Returns the underlying directory handle.
+    pub(crate) fn handle(&self) -> &DirHandle {
+        &self.0
+    }
+}
@@ -0,0 +1,183 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Describing which path and operation an error came from.
+
+use Failure;
+use std::error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// The step of updating a path that failed.
+pub enum Operation {
+    /// Opening an existing path.
+    Open,
+    /// Creating a missing path or one of its parent directories.
+    Create,
+    /// Setting the timestamps of a path.
+    SetTimes,
+    /// Synchronising a path or its parent directory to storage.
+    Sync,
+    /// Closing a path after updating it.
+    Close,
+}
+
+#[derive(Debug)]
+/// An error, together with the path and operation it came from.
+///
+/// Every error returned while updating a path is converted into an `io::Error` of the same kind
+/// as the original, from which this can be recovered using `Error::of`. The original error may
+/// have been reported by the operating system, or have a type of its own, such as `Failure`,
+/// which can be recovered using `Error::downcast_ref`.
+pub struct Error {
+    /// The step that failed.
+    operation: Operation,
+    /// The path that was being updated.
+    path: PathBuf,
+    /// The original error.
+    source: io::Error,
+}
+
+/// Returns a function that adds a path and operation to errors, for use with `map_err`.
+///
+/// Errors that already have a path and operation are returned unchanged, so that the step that
+/// actually failed is the one reported.
+pub(crate) fn context<'a>(
+    operation: Operation,
+    path: &'a Path,
+) -> impl FnOnce(io::Error) -> io::Error + 'a {
+    move |err| {
+        if Error::of(&err).is_some() {
+            err
+        } else {
+            Error::new(operation, path, err).into()
+        }
+    }
+}
+
+#[derive(Debug)]
+/// An error returned when a path cannot be passed to the operating system.
+///
+/// This is converted into an `io::Error` of kind `InvalidInput`, which `Failure::of` tells
+/// apart from other invalid input.
+pub(crate) struct InvalidPath(&'static str);
+
+#[inline]
+/// Returns an error of kind `InvalidInput` for a path that cannot be passed to the operating
+/// system.
+pub(crate) fn invalid_path(reason: &'static str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidInput, InvalidPath(reason))
+}
+
+#[inline]
+/// Returns the original error that an error wraps together with a path and operation, or the
+/// error itself.
+pub(crate) fn original(err: &io::Error) -> &io::Error {
+    Error::of(err).map_or(err, Error::io_error)
+}
+
+impl Operation {
+    #[inline]
+    /// Describes the operation, to be followed by a path.
+    fn describe(self) -> &'static str {
+        match self {
+            Operation::Open => "open",
+            Operation::Create => "create",
+            Operation::SetTimes => "set the timestamps of",
+            Operation::Sync => "synchronise",
+            Operation::Close => "close",
+        }
+    }
+}
+
+impl Error {
+    #[inline]
+    /// Creates a new error for an operation on a path.
+    pub(crate) fn new(operation: Operation, path: &Path, source: io::Error) -> Self {
+        Self {
+            operation,
+            path: path.to_path_buf(),
+            source,
+        }
+    }
+
+    /// Recovers the path and operation that an error came from, if known.
+    pub fn of(err: &io::Error) -> Option<&Self> {
+        err.get_ref().and_then(|e| e.downcast_ref::<Self>())
+    }
+
+    /// Recovers an error of a type of its own, such as `Failure` or `WouldOpen`, whether or not
+    /// it was given a path and operation.
+    pub fn downcast_ref<T: error::Error + 'static>(err: &io::Error) -> Option<&T> {
+        original(err).get_ref().and_then(|e| e.downcast_ref::<T>())
+    }
+
+    #[inline]
+    /// Returns the step that failed.
+    pub fn operation(&self) -> Operation {
+        self.operation
+    }
+
+    #[inline]
+    /// Returns why the operation failed.
+    pub fn failure(&self) -> Failure {
+        Failure::of(&self.source)
+    }
+
+    #[inline]
+    /// Returns the path that was being updated.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    /// Returns the original error, such as one reported by the operating system.
+    pub fn io_error(&self) -> &io::Error {
+        &self.source
+    }
+
+    #[inline]
+    /// Returns the original error, discarding the path and operation.
+    pub fn into_io_error(self) -> io::Error {
+        self.source
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // The original error is left to `source`, so that it is not printed twice in a chain.
+        write!(
+            f,
+            "could not {} `{}`",
+            self.operation.describe(),
+            self.path.display()
+        )
+    }
+}
+
+impl error::Error for Error {
+    fn source(&self) -> Option<&(dyn error::Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+impl Display for InvalidPath {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.0)
+    }
+}
+
+impl error::Error for InvalidPath {}
+
+impl From<Error> for io::Error {
+    #[inline]
+    fn from(err: Error) -> Self {
+        io::Error::new(err.source.kind(), err)
+    }
+}
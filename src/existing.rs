@@ -0,0 +1,71 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Guaranteeing that only the timestamps of existing paths are ever set.
+
+use {Builder, CreationTarget};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// An error returned when an option or method would open or create a path, which
+/// `Builder::existing_only` forbids.
+///
+/// This is converted into an `io::Error` of kind `InvalidInput`, from which it can be recovered
+/// using `Error::downcast_ref`.
+pub struct WouldOpen {
+    /// The option or method that would open or create a path.
+    cause: &'static str,
+}
+
+/// Checks that a builder never opens or creates a path if `existing_only` was given.
+pub(crate) fn check(builder: &Builder) -> io::Result<()> {
+    if !builder.existing_only {
+        return Ok(());
+    }
+    let cause = match builder.creation_target {
+        CreationTarget::File => "creation_target",
+        CreationTarget::None if builder.sync_file => "sync_file",
+        CreationTarget::None if builder.sync_parent_dir => "sync_parent_dir",
+        CreationTarget::None => return Ok(()),
+    };
+    Err(WouldOpen::new(cause).into())
+}
+
+impl WouldOpen {
+    #[inline]
+    /// Creates a new error for an option or method that would open or create a path.
+    pub(crate) fn new(cause: &'static str) -> Self {
+        Self { cause }
+    }
+
+    #[inline]
+    /// Returns the name of the option or method that would open or create a path.
+    pub fn cause(&self) -> &'static str {
+        self.cause
+    }
+}
+
+impl Display for WouldOpen {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "`{}` would open or create a path, which `existing_only` forbids",
+            self.cause
+        )
+    }
+}
+
+impl Error for WouldOpen {}
+
+impl From<WouldOpen> for io::Error {
+    #[inline]
+    fn from(err: WouldOpen) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
@@ -0,0 +1,167 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Classifying why updating a path failed.
+
+use error::{self, InvalidPath};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use sys;
+use {TimestampMismatch, TimestampOutOfRange, TimestampTooPrecise, UnsupportedTimestamp};
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// Why updating a path failed, as far as deciding whether to skip it, retry it later or give up
+/// on a batch is concerned.
+///
+/// This is obtained from an `io::Error` using `Failure::of`, without matching on messages that
+/// differ between platforms and locales, and recognises the errors this crate returns even when
+/// their `io::ErrorKind` is too general to tell them apart. Failures that are detected by
+/// examining the path, such as `Immutable`, are also returned as an `io::Error` from which the
+/// `Failure` can be recovered using `Error::downcast_ref`.
+pub enum Failure {
+    /// The filesystem is mounted read-only (`EROFS`).
+    ReadOnlyFilesystem,
+    /// The storage media is write-protected (`ERROR_WRITE_PROTECT` on Windows).
+    WriteProtected,
+    /// The filesystem has no space left for a path to be created (`ENOSPC`).
+    NoSpace,
+    /// The user's disk quota does not allow a path to be created (`EDQUOT`).
+    QuotaExceeded,
+    /// The file is flagged as immutable or append-only, which stops even its owner from setting
+    /// its timestamps until the flag is cleared.
+    Immutable,
+    /// The path, or one of its parent directories, does not exist.
+    NotFound,
+    /// The user may not set the timestamps of the path.
+    PermissionDenied,
+    /// A timestamp cannot be represented on this platform.
+    TimestampOutOfRange,
+    /// A timestamp or option is not supported on this platform or filesystem.
+    UnsupportedOnPlatform,
+    /// The path cannot be passed to the operating system, such as one containing a NUL
+    /// character or one that is too long.
+    InvalidPath,
+    /// The path is a symbolic link that was not followed, or that points to a path that cannot
+    /// be created.
+    IsSymlink,
+    /// The path, or one of its parents, is not the kind of item the operation needs, such as a
+    /// file where a directory was expected.
+    WrongItemType,
+    /// The timestamps of the path differ from the ones expected, such as when a filesystem
+    /// stores a different timestamp than the one requested.
+    Conflict,
+    /// Any other failure.
+    Other,
+}
+
+impl Failure {
+    /// Classifies an error returned while updating a path.
+    pub fn of(err: &io::Error) -> Self {
+        let err = error::original(err);
+        if let Some(inner) = err.get_ref() {
+            if let Some(failure) = inner.downcast_ref::<Failure>() {
+                return *failure;
+            } else if inner.is::<TimestampOutOfRange>() {
+                return Failure::TimestampOutOfRange;
+            } else if inner.is::<UnsupportedTimestamp>() || inner.is::<TimestampTooPrecise>() {
+                return Failure::UnsupportedOnPlatform;
+            } else if inner.is::<InvalidPath>() {
+                return Failure::InvalidPath;
+            } else if inner.is::<TimestampMismatch>() {
+                return Failure::Conflict;
+            }
+        }
+        if let Some(code) = err.raw_os_error() {
+            if sys::is_symlink_error(code) {
+                return Failure::IsSymlink;
+            }
+            match sys::failure(code) {
+                Failure::Other => {}
+                failure => return failure,
+            }
+        }
+        match err.kind() {
+            io::ErrorKind::NotFound => Failure::NotFound,
+            io::ErrorKind::PermissionDenied => Failure::PermissionDenied,
+            io::ErrorKind::Unsupported => Failure::UnsupportedOnPlatform,
+            io::ErrorKind::InvalidFilename => Failure::InvalidPath,
+            io::ErrorKind::IsADirectory | io::ErrorKind::NotADirectory => Failure::WrongItemType,
+            _ => Failure::Other,
+        }
+    }
+
+    #[inline]
+    /// Returns whether the failure applies to every path on the same filesystem, rather than
+    /// only to the path being updated.
+    ///
+    /// A batch operation is unlikely to get any further once one of these occurs, so it may as
+    /// well be aborted.
+    pub fn is_filesystem_wide(&self) -> bool {
+        match *self {
+            Failure::ReadOnlyFilesystem |
+            Failure::WriteProtected |
+            Failure::NoSpace |
+            Failure::QuotaExceeded => true,
+            Failure::Immutable |
+            Failure::NotFound |
+            Failure::PermissionDenied |
+            Failure::TimestampOutOfRange |
+            Failure::UnsupportedOnPlatform |
+            Failure::InvalidPath |
+            Failure::IsSymlink |
+            Failure::WrongItemType |
+            Failure::Conflict |
+            Failure::Other => false,
+        }
+    }
+}
+
+impl Display for Failure {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(match *self {
+            Failure::ReadOnlyFilesystem => "the filesystem is read-only",
+            Failure::WriteProtected => "the media is write-protected",
+            Failure::NoSpace => "no space is left on the filesystem",
+            Failure::QuotaExceeded => "the disk quota has been exceeded",
+            Failure::Immutable => "the file is immutable or append-only",
+            Failure::NotFound => "the path does not exist",
+            Failure::PermissionDenied => "permission to update the path was denied",
+            Failure::TimestampOutOfRange => "a timestamp cannot be represented on this platform",
+            Failure::UnsupportedOnPlatform => "a timestamp or option is not supported",
+            Failure::InvalidPath => "the path cannot be passed to the operating system",
+            Failure::IsSymlink => "the path is a symbolic link",
+            Failure::WrongItemType => "the path is not the kind of item expected",
+            Failure::Conflict => "the timestamps of the path differ from the ones expected",
+            Failure::Other => "the path could not be updated",
+        })
+    }
+}
+
+impl Error for Failure {}
+
+impl From<Failure> for io::Error {
+    #[inline]
+    fn from(err: Failure) -> Self {
+        let kind = match err {
+            Failure::ReadOnlyFilesystem | Failure::WriteProtected => {
+                io::ErrorKind::ReadOnlyFilesystem
+            }
+            Failure::NoSpace => io::ErrorKind::StorageFull,
+            Failure::QuotaExceeded => io::ErrorKind::QuotaExceeded,
+            Failure::Immutable | Failure::PermissionDenied => io::ErrorKind::PermissionDenied,
+            Failure::NotFound => io::ErrorKind::NotFound,
+            Failure::TimestampOutOfRange | Failure::IsSymlink => io::ErrorKind::InvalidInput,
+            Failure::UnsupportedOnPlatform => io::ErrorKind::Unsupported,
+            Failure::InvalidPath => io::ErrorKind::InvalidFilename,
+            Failure::WrongItemType => io::ErrorKind::NotADirectory,
+            Failure::Conflict => io::ErrorKind::InvalidData,
+            Failure::Other => io::ErrorKind::Other,
+        };
+        io::Error::new(kind, err)
+    }
+}
@@ -0,0 +1,213 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A platform-independent representation of filesystem timestamps.
+
+#[cfg(feature = "chrono")]
+use chrono::{DateTime, TimeZone};
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use sys;
+#[cfg(feature = "time")]
+use time::OffsetDateTime;
+
+/// The number of nanoseconds in one second.
+const NANOS_PER_SEC: u32 = 1_000_000_000;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, Ord, PartialEq, PartialOrd)]
+/// A filesystem timestamp, measured from the Unix epoch (January 1, 1970 UTC) with nanosecond
+/// precision.
+///
+/// Unlike `SystemTime`, timestamps before the Unix epoch are represented directly: the number
+/// of seconds is negative, and the number of nanoseconds always counts forwards from it. For
+/// example, half a second before the epoch is -1 seconds plus 500,000,000 nanoseconds.
+pub struct FileTime {
+    /// Whole seconds since the Unix epoch, rounded towards negative infinity.
+    seconds: i64,
+    /// Nanoseconds since the start of the second, always less than one second.
+    nanoseconds: u32,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// An error indicating that a timestamp cannot be stored by the current platform.
+///
+/// This is returned inside an `io::Error` of kind `InvalidInput`, from which it can be recovered
+/// using `Error::downcast_ref`. No path is changed when this error occurs.
+pub struct TimestampOutOfRange {
+    /// The timestamp that could not be stored.
+    time: FileTime,
+}
+
+impl FileTime {
+    #[inline]
+    /// Creates a timestamp from whole seconds since the Unix epoch and additional nanoseconds.
+    ///
+    /// Nanoseconds beyond one second carry over into `seconds`.
+    pub fn from_unix_time(seconds: i64, nanoseconds: u32) -> Self {
+        Self {
+            seconds: seconds.saturating_add(i64::from(nanoseconds / NANOS_PER_SEC)),
+            nanoseconds: nanoseconds % NANOS_PER_SEC,
+        }
+    }
+
+    #[inline]
+    /// Returns the current time.
+    pub fn now() -> Self {
+        Self::from(SystemTime::now())
+    }
+
+    #[inline]
+    /// Returns the current time from a coarse clock, which is cheaper to read than `now` but
+    /// only advances every few milliseconds.
+    ///
+    /// This uses `CLOCK_REALTIME_COARSE` on Linux and FreeBSD, and `GetSystemTimeAsFileTime` on
+    /// Windows. Elsewhere, this is the same as `now`.
+    pub fn now_coarse() -> Self {
+        sys::coarse_now()
+    }
+
+    #[inline]
+    /// Returns the whole seconds since the Unix epoch, rounded towards negative infinity.
+    pub fn unix_seconds(&self) -> i64 {
+        self.seconds
+    }
+
+    #[inline]
+    /// Returns the nanoseconds since the start of the second.
+    pub fn nanoseconds(&self) -> u32 {
+        self.nanoseconds
+    }
+
+    #[inline]
+    /// Creates a timestamp from nanoseconds since the Unix epoch.
+    ///
+    /// Timestamps beyond the range of `FileTime` are clamped to it.
+    pub fn from_unix_nanos(nanos: i128) -> Self {
+        let min = Self::from_unix_time(i64::MIN, 0).unix_nanos();
+        let max = Self::from_unix_time(i64::MAX, NANOS_PER_SEC - 1).unix_nanos();
+        let nanos = nanos.clamp(min, max);
+        let per_sec = i128::from(NANOS_PER_SEC);
+        Self {
+            seconds: i64::try_from(nanos.div_euclid(per_sec)).unwrap_or_default(),
+            nanoseconds: u32::try_from(nanos.rem_euclid(per_sec)).unwrap_or_default(),
+        }
+    }
+
+    #[inline]
+    /// Returns the nanoseconds since the Unix epoch.
+    pub fn unix_nanos(&self) -> i128 {
+        i128::from(self.seconds) * i128::from(NANOS_PER_SEC) + i128::from(self.nanoseconds)
+    }
+
+    #[inline]
+    /// Converts this timestamp into a `SystemTime`, or returns `None` if it is out of range.
+    pub fn to_system_time(&self) -> Option<SystemTime> {
+        if self.seconds >= 0 {
+            let since = Duration::new(self.seconds.unsigned_abs(), self.nanoseconds);
+            UNIX_EPOCH.checked_add(since)
+        } else {
+            let before = Duration::from_secs(self.seconds.unsigned_abs());
+            UNIX_EPOCH
+                .checked_sub(before)
+                .and_then(|t| t.checked_add(Duration::new(0, self.nanoseconds)))
+        }
+    }
+
+    #[cfg(feature = "time")]
+    #[inline]
+    /// Converts this timestamp into an `OffsetDateTime` in UTC, or returns `None` if it is out of
+    /// range.
+    ///
+    /// This method is only available if the `time` feature is enabled.
+    pub fn to_offset_date_time(&self) -> Option<OffsetDateTime> {
+        OffsetDateTime::from_unix_timestamp_nanos(self.unix_nanos()).ok()
+    }
+}
+
+impl TimestampOutOfRange {
+    // rustix represents every timestamp with 64-bit seconds, so nothing is out of range there.
+    #[cfg(any(not(all(unix, feature = "rustix")), feature = "cap"))]
+    #[inline]
+    /// Creates a new error for a timestamp.
+    pub(crate) fn new(time: FileTime) -> Self {
+        Self { time }
+    }
+
+    #[inline]
+    /// Returns the timestamp that could not be stored.
+    pub fn time(&self) -> FileTime {
+        self.time
+    }
+}
+
+impl Display for TimestampOutOfRange {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "timestamp {}.{:09} seconds from the Unix epoch is out of range for this platform",
+            self.time.seconds, self.time.nanoseconds
+        )
+    }
+}
+
+impl Error for TimestampOutOfRange {}
+
+impl From<TimestampOutOfRange> for io::Error {
+    #[inline]
+    fn from(err: TimestampOutOfRange) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
+
+impl From<SystemTime> for FileTime {
+    #[inline]
+    fn from(time: SystemTime) -> Self {
+        match time.duration_since(UNIX_EPOCH) {
+            Ok(d) => Self {
+                seconds: i64::try_from(d.as_secs()).unwrap_or(i64::MAX),
+                nanoseconds: d.subsec_nanos(),
+            },
+            Err(e) => {
+                // Round the seconds down, so that the nanoseconds still count forwards.
+                let d = e.duration();
+                let seconds = i64::try_from(d.as_secs()).map_or(i64::MIN, |s| -s);
+                if d.subsec_nanos() == 0 {
+                    Self {
+                        seconds,
+                        nanoseconds: 0,
+                    }
+                } else {
+                    Self {
+                        seconds: seconds.saturating_sub(1),
+                        nanoseconds: NANOS_PER_SEC - d.subsec_nanos(),
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(feature = "chrono")]
+impl<Tz: TimeZone> From<DateTime<Tz>> for FileTime {
+    #[inline]
+    fn from(time: DateTime<Tz>) -> Self {
+        // Leap seconds are represented by nanoseconds beyond one second, so they carry over into
+        // the following second.
+        Self::from_unix_time(time.timestamp(), time.timestamp_subsec_nanos())
+    }
+}
+
+#[cfg(feature = "time")]
+impl From<OffsetDateTime> for FileTime {
+    #[inline]
+    fn from(time: OffsetDateTime) -> Self {
+        Self::from_unix_time(time.unix_timestamp(), time.nanosecond())
+    }
+}
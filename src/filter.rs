@@ -0,0 +1,244 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Selection of paths during batch and recursive operations.
+
+use age::{age_at, TimestampKind};
+#[cfg(feature = "glob")]
+use globset::{Glob, GlobSet, GlobSetBuilder};
+use std::ffi::OsString;
+use std::fs::Metadata;
+#[cfg(feature = "glob")]
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Which types of path to update during a recursive operation.
+pub enum EntryTypes {
+    /// Update every path.
+    All,
+    /// Only update paths that are not directories.
+    ///
+    /// This includes symbolic links that are not followed, and special files such as named pipes.
+    Files,
+    /// Only update directories.
+    Directories,
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// Decides which paths to update based on their current modification timestamps.
+pub struct AgeFilter {
+    /// If set, only paths last modified at least this long ago are updated.
+    older_than: Option<Duration>,
+    /// If set, only paths last modified less than this long ago are updated.
+    newer_than: Option<Duration>,
+}
+
+#[derive(Clone, Debug)]
+/// Decides which paths to update during a recursive operation.
+pub struct Filter {
+    /// Which types of path to update.
+    types: EntryTypes,
+    /// If not empty, only paths with one of these extensions are updated.
+    extensions: Vec<OsString>,
+    #[cfg(feature = "glob")]
+    /// If not empty, only paths matching one of these patterns are updated.
+    globs: Vec<Glob>,
+    #[cfg(feature = "glob")]
+    /// The compiled form of `globs`.
+    glob_set: GlobSet,
+    #[cfg(feature = "glob")]
+    /// Paths matching one of these patterns are neither updated nor descended into.
+    exclude_globs: Vec<Glob>,
+    #[cfg(feature = "glob")]
+    /// The compiled form of `exclude_globs`.
+    exclude_glob_set: GlobSet,
+    /// Paths equal to one of these are neither updated nor descended into.
+    exclude_paths: Vec<PathBuf>,
+}
+
+#[cfg(feature = "glob")]
+#[inline]
+/// Compiles a glob pattern, reporting invalid patterns as I/O errors.
+fn compile_glob(pattern: &str) -> io::Result<Glob> {
+    Glob::new(pattern).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+#[cfg(feature = "glob")]
+/// Compiles a list of glob patterns into a single matcher.
+fn compile_glob_set(globs: &[Glob]) -> io::Result<GlobSet> {
+    let mut builder = GlobSetBuilder::new();
+    for glob in globs {
+        let _ = builder.add(glob.clone());
+    }
+    builder
+        .build()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))
+}
+
+impl Filter {
+    #[inline]
+    /// Sets which types of path to update.
+    pub fn set_types(&mut self, types: EntryTypes) {
+        self.types = types;
+    }
+
+    #[inline]
+    /// Adds an extension to the list of extensions to update.
+    pub fn add_extension(&mut self, extension: OsString) {
+        self.extensions.push(extension);
+    }
+
+    #[cfg(feature = "glob")]
+    /// Adds a glob pattern to the list of patterns to update.
+    pub fn add_glob(&mut self, pattern: &str) -> io::Result<()> {
+        self.globs.push(compile_glob(pattern)?);
+        match compile_glob_set(&self.globs) {
+            Ok(set) => {
+                self.glob_set = set;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = self.globs.pop();
+                Err(e)
+            }
+        }
+    }
+
+    #[cfg(feature = "glob")]
+    /// Adds a glob pattern to the list of patterns to exclude.
+    pub fn add_exclude_glob(&mut self, pattern: &str) -> io::Result<()> {
+        self.exclude_globs.push(compile_glob(pattern)?);
+        match compile_glob_set(&self.exclude_globs) {
+            Ok(set) => {
+                self.exclude_glob_set = set;
+                Ok(())
+            }
+            Err(e) => {
+                let _ = self.exclude_globs.pop();
+                Err(e)
+            }
+        }
+    }
+
+    #[inline]
+    /// Adds a path, relative to the root of the tree, to the list of paths to exclude.
+    pub fn add_exclude_path(&mut self, path: PathBuf) {
+        self.exclude_paths.push(path);
+    }
+
+    #[inline]
+    /// Returns whether a path, relative to the root of the tree, should be skipped entirely.
+    pub fn is_excluded(&self, relative: &Path) -> bool {
+        self.exclude_paths.iter().any(|p| p == relative) || self.is_excluded_by_glob(relative)
+    }
+
+    #[cfg(feature = "glob")]
+    #[inline]
+    /// Returns whether a path, relative to the root of the tree, matches a pattern to exclude.
+    fn is_excluded_by_glob(&self, relative: &Path) -> bool {
+        !self.exclude_globs.is_empty() && self.exclude_glob_set.is_match(relative)
+    }
+
+    #[cfg(not(feature = "glob"))]
+    #[inline]
+    /// Returns whether a path, relative to the root of the tree, matches a pattern to exclude.
+    fn is_excluded_by_glob(&self, _: &Path) -> bool {
+        false
+    }
+
+    #[cfg(feature = "glob")]
+    #[inline]
+    /// Returns whether a path, relative to the root of the tree, matches a pattern to update.
+    fn matches_globs(&self, relative: &Path) -> bool {
+        self.globs.is_empty() || self.glob_set.is_match(relative)
+    }
+
+    #[cfg(not(feature = "glob"))]
+    #[inline]
+    /// Returns whether a path, relative to the root of the tree, matches a pattern to update.
+    fn matches_globs(&self, _: &Path) -> bool {
+        true
+    }
+
+    /// Returns whether a path should be updated.
+    ///
+    /// `relative` is the path relative to the root of the tree, which glob patterns are matched
+    /// against.
+    pub fn matches(&self, path: &Path, relative: &Path, metadata: &Metadata) -> bool {
+        let type_matches = match self.types {
+            EntryTypes::All => true,
+            EntryTypes::Files => !metadata.is_dir(),
+            EntryTypes::Directories => metadata.is_dir(),
+        };
+        type_matches &&
+            (self.extensions.is_empty() ||
+                path.extension().is_some_and(|ext| self.extensions.iter().any(|e| e == ext))) &&
+            self.matches_globs(relative)
+    }
+}
+
+impl AgeFilter {
+    #[inline]
+    /// Sets the minimum age of paths to update.
+    pub fn set_older_than(&mut self, age: Option<Duration>) {
+        self.older_than = age;
+    }
+
+    #[inline]
+    /// Sets the maximum age of paths to update.
+    pub fn set_newer_than(&mut self, age: Option<Duration>) {
+        self.newer_than = age;
+    }
+
+    #[inline]
+    /// Returns whether any age limits are set.
+    pub fn is_active(&self) -> bool {
+        self.older_than.is_some() || self.newer_than.is_some()
+    }
+
+    /// Returns whether a path should be updated, relative to the time `now`.
+    ///
+    /// Paths that do not exist have no age, so they always match.
+    pub fn matches(&self, path: &Path, follow_symlinks: bool, now: SystemTime) -> bool {
+        if !self.is_active() {
+            return true;
+        }
+        let age = match age_at(path, TimestampKind::Modified, follow_symlinks, now) {
+            Ok(age) => age,
+            Err(_) => return true,
+        };
+        self.older_than.is_none_or(|min| age >= min) && self.newer_than.is_none_or(|max| age < max)
+    }
+}
+
+impl Default for EntryTypes {
+    #[inline]
+    fn default() -> Self {
+        EntryTypes::All
+    }
+}
+
+impl Default for Filter {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            types: EntryTypes::default(),
+            extensions: Vec::new(),
+            #[cfg(feature = "glob")]
+            globs: Vec::new(),
+            #[cfg(feature = "glob")]
+            glob_set: GlobSet::empty(),
+            #[cfg(feature = "glob")]
+            exclude_globs: Vec::new(),
+            #[cfg(feature = "glob")]
+            exclude_glob_set: GlobSet::empty(),
+            exclude_paths: Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,69 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Running blocking operations from asynchronous code.
+
+use std::future::Future;
+use std::io;
+use std::panic;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio::task::{self, JoinHandle};
+
+/// A future that resolves to the result of a function run on tokio's blocking thread pool.
+///
+/// Like an `async fn`, nothing happens until the future is first polled.
+pub(crate) enum Blocking<T, F> {
+    /// The function, waiting to be started.
+    Idle(Option<F>),
+    /// The function, running on the blocking thread pool.
+    Running(JoinHandle<io::Result<T>>),
+}
+
+#[inline]
+/// Returns a future that runs a function on tokio's blocking thread pool, so that it does not
+/// hold up the runtime's other tasks.
+pub(crate) fn spawn<T, F>(f: F) -> Blocking<T, F>
+where
+    F: FnOnce() -> io::Result<T> + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    Blocking::Idle(Some(f))
+}
+
+impl<T, F> Future for Blocking<T, F>
+where
+    F: FnOnce() -> io::Result<T> + Send + Unpin + 'static,
+    T: Send + 'static,
+{
+    type Output = io::Result<T>;
+
+    fn poll(self: Pin<&mut Self>, cx: &mut Context) -> Poll<Self::Output> {
+        let this = self.get_mut();
+        loop {
+            let handle = match *this {
+                Blocking::Idle(ref mut f) => {
+                    let f = f.take().expect("polled a finished future");
+                    task::spawn_blocking(f)
+                }
+                Blocking::Running(ref mut handle) => {
+                    return match Pin::new(handle).poll(cx) {
+                        Poll::Ready(Ok(result)) => Poll::Ready(result),
+                        // A panic in the function is passed on, as if it had been called
+                        // directly.
+                        Poll::Ready(Err(e)) if e.is_panic() => panic::resume_unwind(e.into_panic()),
+                        Poll::Ready(Err(_)) => Poll::Ready(Err(io::Error::other(
+                            "the runtime shut down before the operation could finish",
+                        ))),
+                        Poll::Pending => Poll::Pending,
+                    };
+                }
+            };
+            *this = Blocking::Running(handle);
+        }
+    }
+}
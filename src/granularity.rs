@@ -0,0 +1,394 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Compensating for filesystems that store timestamps coarsely.
+//!
+//! FAT-family filesystems, which are still common on removable drives, store modification
+//! timestamps to the nearest 2 seconds and access timestamps only as a date. A timestamp set on
+//! such a filesystem silently changes, which is otherwise only discovered when it is compared
+//! with the original later.
+
+use {Backend, Builder, FileTime, Operation, Timestamp, TimestampKind, Timestamps};
+use error;
+#[cfg(test)]
+use std::cell::Cell;
+use std::convert::TryFrom;
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::Path;
+use std::time::Duration;
+use sys;
+
+/// The resolution of FAT creation timestamps, and of exFAT creation and modification timestamps.
+const CENTISECOND: Duration = Duration::from_millis(10);
+/// The resolution of FAT modification timestamps and exFAT access timestamps.
+const TWO_SECONDS: Duration = Duration::from_secs(2);
+/// The resolution of FAT access timestamps.
+const DAY: Duration = Duration::from_secs(86_400);
+
+#[cfg(test)]
+thread_local! {
+    /// The kind of filesystem that `granularity` assumes on the current thread instead of
+    /// examining the path, so that coarse filesystems can be tested without mounting one.
+    pub static ASSUMED_FILESYSTEM: Cell<Option<Filesystem>> = const { Cell::new(None) };
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// A kind of filesystem, as far as the precision of its timestamps is concerned.
+pub enum Filesystem {
+    /// FAT12, FAT16 or FAT32.
+    Fat,
+    /// exFAT.
+    ExFat,
+    /// Any other filesystem, which is assumed to store timestamps as precisely as the platform.
+    Other,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How precisely a filesystem stores each kind of timestamp, as returned by `granularity`.
+pub struct Granularity {
+    /// The resolution of access timestamps.
+    accessed: Duration,
+    /// The resolution of modification timestamps.
+    modified: Duration,
+    /// The resolution of creation timestamps.
+    created: Duration,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How to deal with filesystems that store timestamps less precisely than requested.
+pub enum GranularityMode {
+    /// Let the filesystem round timestamps without reporting it.
+    Ignore,
+    /// Round timestamps down to what the filesystem can store before setting them.
+    Round,
+    /// Read timestamps back after setting them, failing if the filesystem stored different ones.
+    Verify,
+    /// Fail without setting any timestamp if the filesystem cannot store one of them exactly,
+    /// then verify them as `Verify` does.
+    ///
+    /// No filesystem can store a timestamp more precisely than the platform can set it, so on
+    /// Windows this also fails for any timestamp that is not a multiple of 100 nanoseconds.
+    Exact,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// An error returned when a filesystem stored a different timestamp than the one requested.
+///
+/// This is converted into an `io::Error` of kind `Other`, from which it can be recovered using
+/// `Error::downcast_ref`.
+pub struct TimestampMismatch {
+    /// The timestamp that was changed.
+    kind: TimestampKind,
+    /// The timestamp that was requested.
+    requested: FileTime,
+    /// The timestamp that was stored.
+    stored: FileTime,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// An error returned when a timestamp is more precise than the filesystem can store.
+///
+/// This is converted into an `io::Error` of kind `Unsupported`, from which it can be recovered
+/// using `Error::downcast_ref`.
+pub struct TimestampTooPrecise {
+    /// The timestamp that cannot be stored.
+    kind: TimestampKind,
+    /// The timestamp that was requested.
+    requested: FileTime,
+    /// The timestamp that the filesystem would store instead.
+    storable: FileTime,
+}
+
+#[inline]
+/// Rounds a timestamp down to a multiple of a resolution since the Unix epoch.
+pub fn round_down(time: FileTime, resolution: Duration) -> FileTime {
+    let resolution = i128::try_from(resolution.as_nanos()).unwrap_or(i128::MAX);
+    if resolution <= 1 {
+        return time;
+    }
+    FileTime::from_unix_nanos(time.unix_nanos().div_euclid(resolution) * resolution)
+}
+
+/// Returns how precisely the filesystem containing a path stores each kind of timestamp.
+///
+/// Only FAT-family filesystems are recognised, on Linux and Windows; every other filesystem is
+/// assumed to store timestamps as precisely as the platform allows, which is 1 nanosecond on
+/// Unix and 100 nanoseconds on Windows. If the path does not exist, its parent directory is
+/// examined instead.
+pub fn granularity<P: AsRef<Path>>(path: P) -> io::Result<Granularity> {
+    #[cfg(test)]
+    {
+        if let Some(filesystem) = ASSUMED_FILESYSTEM.with(Cell::get) {
+            return Ok(Granularity::of(filesystem));
+        }
+    }
+    let path = path.as_ref();
+    let filesystem = match sys::filesystem(path) {
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => match path.parent() {
+            Some(dir) if dir.as_os_str().is_empty() => sys::filesystem(Path::new(".")),
+            Some(dir) => sys::filesystem(dir),
+            None => sys::filesystem(path),
+        },
+        result => result,
+    }?;
+    Ok(Granularity::of(filesystem))
+}
+
+/// Checks that the timestamps a builder set on a path were stored exactly, apart from the
+/// rounding that every filesystem on the platform does.
+///
+/// If the path was updated through a backend, its timestamps are read back through it, and must
+/// match exactly.
+pub(crate) fn verify(
+    builder: &Builder,
+    path: &Path,
+    backend: Option<&dyn Backend>,
+) -> io::Result<()> {
+    let (stored, resolution) = match backend {
+        Some(backend) => (
+            backend.read_times(path, builder.follow_symlinks),
+            Duration::from_nanos(1),
+        ),
+        None => (sys::read_times(path, builder.follows(path)), sys::RESOLUTION),
+    };
+    let stored = stored.map_err(error::context(Operation::Open, path))?;
+    verify_times(builder, &stored, resolution)
+}
+
+/// Checks that the timestamps a builder set were stored exactly, apart from rounding down to a
+/// resolution.
+pub(crate) fn verify_times(
+    builder: &Builder,
+    stored: &Timestamps,
+    resolution: Duration,
+) -> io::Result<()> {
+    let checks = [
+        (TimestampKind::Accessed, builder.accessed_time(), Some(stored.accessed())),
+        (TimestampKind::Modified, builder.modified_time(), Some(stored.modified())),
+        (TimestampKind::Created, builder.created_time(), stored.created()),
+    ];
+    for &(kind, requested, stored) in &checks {
+        match (requested, stored) {
+            (Some(Timestamp::At(requested)), Some(stored))
+                if round_down(requested, resolution) != stored =>
+            {
+                return Err(TimestampMismatch::new(kind, requested, stored).into());
+            }
+            _ => (),
+        }
+    }
+    Ok(())
+}
+
+/// Checks that a filesystem can store the timestamps a builder sets exactly, before any of them
+/// is set.
+///
+/// FAT access dates are in local time, so whether they can be stored exactly is only known once
+/// they have been set, and is left for `verify`.
+pub(crate) fn check_precision(builder: &Builder, g: &Granularity) -> io::Result<()> {
+    let checks = [
+        (TimestampKind::Accessed, builder.accessed_time()),
+        (TimestampKind::Modified, builder.modified_time()),
+        (TimestampKind::Created, builder.created_time()),
+    ];
+    for &(kind, requested) in &checks {
+        if let Some(Timestamp::At(requested)) = requested {
+            // The platform's resolution applies first, since FAT access dates are not rounded.
+            let storable = g.round(kind, round_down(requested, sys::RESOLUTION));
+            if storable != requested {
+                return Err(TimestampTooPrecise::new(kind, requested, storable).into());
+            }
+        }
+    }
+    Ok(())
+}
+
+impl Granularity {
+    #[inline]
+    /// Returns the granularity of a kind of filesystem.
+    pub(crate) fn of(filesystem: Filesystem) -> Self {
+        match filesystem {
+            Filesystem::Fat => Self {
+                accessed: DAY,
+                modified: TWO_SECONDS,
+                created: CENTISECOND,
+            },
+            Filesystem::ExFat => Self {
+                accessed: TWO_SECONDS,
+                modified: CENTISECOND,
+                created: CENTISECOND,
+            },
+            Filesystem::Other => Self {
+                accessed: sys::RESOLUTION,
+                modified: sys::RESOLUTION,
+                created: sys::RESOLUTION,
+            },
+        }
+    }
+
+    #[inline]
+    /// Returns the resolution of access timestamps.
+    ///
+    /// FAT stores access timestamps as a date in local time, so this is a whole day there.
+    pub fn accessed(&self) -> Duration {
+        self.accessed
+    }
+
+    #[inline]
+    /// Returns the resolution of modification timestamps.
+    pub fn modified(&self) -> Duration {
+        self.modified
+    }
+
+    #[inline]
+    /// Returns the resolution of creation timestamps.
+    pub fn created(&self) -> Duration {
+        self.created
+    }
+
+    #[inline]
+    /// Rounds a timestamp down to what the filesystem can store.
+    ///
+    /// FAT access dates are in local time, which is not known here, so timestamps whose
+    /// resolution is a whole day are left for the filesystem to round.
+    pub(crate) fn round(&self, kind: TimestampKind, time: FileTime) -> FileTime {
+        let resolution = match kind {
+            TimestampKind::Accessed => self.accessed,
+            TimestampKind::Modified => self.modified,
+            TimestampKind::Created => self.created,
+            TimestampKind::Changed => sys::RESOLUTION,
+        };
+        if resolution >= DAY {
+            time
+        } else {
+            round_down(time, resolution)
+        }
+    }
+
+    #[inline]
+    /// Returns whether the filesystem stores every kind of timestamp as precisely as the
+    /// platform allows.
+    pub fn is_native(&self) -> bool {
+        *self == Self::of(Filesystem::Other)
+    }
+}
+
+impl Default for GranularityMode {
+    #[inline]
+    fn default() -> Self {
+        GranularityMode::Ignore
+    }
+}
+
+impl TimestampMismatch {
+    #[inline]
+    /// Creates a new error for a timestamp that was stored differently.
+    pub(crate) fn new(kind: TimestampKind, requested: FileTime, stored: FileTime) -> Self {
+        Self {
+            kind,
+            requested,
+            stored,
+        }
+    }
+
+    #[inline]
+    /// Returns which timestamp was stored differently.
+    pub fn kind(&self) -> TimestampKind {
+        self.kind
+    }
+
+    #[inline]
+    /// Returns the timestamp that was requested.
+    pub fn requested(&self) -> FileTime {
+        self.requested
+    }
+
+    #[inline]
+    /// Returns the timestamp that was stored instead.
+    pub fn stored(&self) -> FileTime {
+        self.stored
+    }
+}
+
+impl Display for TimestampMismatch {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} timestamp {}.{:09} was stored as {}.{:09} seconds from the Unix epoch",
+            self.kind.name(),
+            self.requested.unix_seconds(),
+            self.requested.nanoseconds(),
+            self.stored.unix_seconds(),
+            self.stored.nanoseconds()
+        )
+    }
+}
+
+impl Error for TimestampMismatch {}
+
+impl From<TimestampMismatch> for io::Error {
+    #[inline]
+    fn from(err: TimestampMismatch) -> Self {
+        io::Error::other(err)
+    }
+}
+
+impl TimestampTooPrecise {
+    #[inline]
+    /// Creates a new error for a timestamp that cannot be stored exactly.
+    pub(crate) fn new(kind: TimestampKind, requested: FileTime, storable: FileTime) -> Self {
+        Self {
+            kind,
+            requested,
+            storable,
+        }
+    }
+
+    #[inline]
+    /// Returns which timestamp cannot be stored exactly.
+    pub fn kind(&self) -> TimestampKind {
+        self.kind
+    }
+
+    #[inline]
+    /// Returns the timestamp that was requested.
+    pub fn requested(&self) -> FileTime {
+        self.requested
+    }
+
+    #[inline]
+    /// Returns the closest timestamp that the filesystem can store, which is never later than
+    /// the one requested.
+    pub fn storable(&self) -> FileTime {
+        self.storable
+    }
+}
+
+impl Display for TimestampTooPrecise {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} timestamp {}.{:09} would be stored as {}.{:09} seconds from the Unix epoch",
+            self.kind.name(),
+            self.requested.unix_seconds(),
+            self.requested.nanoseconds(),
+            self.storable.unix_seconds(),
+            self.storable.nanoseconds()
+        )
+    }
+}
+
+impl Error for TimestampTooPrecise {}
+
+impl From<TimestampTooPrecise> for io::Error {
+    #[inline]
+    fn from(err: TimestampTooPrecise) -> Self {
+        io::Error::new(io::ErrorKind::Unsupported, err)
+    }
+}
@@ -0,0 +1,50 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Lazy operations on multiple paths.
+
+use Outcome;
+use batch::Batch;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// An iterator that updates the timestamps for each path as it is reached.
+///
+/// This is returned by `Builder::touch_iter`.
+pub struct TouchIter<'a, I> {
+    /// The batch that paths are updated in.
+    batch: Batch<'a>,
+    /// The paths that have not been reached yet.
+    paths: I,
+}
+
+impl<'a, I> TouchIter<'a, I> {
+    #[inline]
+    /// Creates a new iterator over the results of updating a sequence of paths.
+    pub(crate) fn new(batch: Batch<'a>, paths: I) -> Self {
+        Self { batch, paths }
+    }
+}
+
+impl<'a, I> Iterator for TouchIter<'a, I>
+where
+    I: Iterator,
+    I::Item: AsRef<Path>,
+{
+    type Item = (PathBuf, io::Result<Outcome>);
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.batch.is_stopped() {
+            return None;
+        }
+        self.paths.next().map(|path| {
+            let path = path.as_ref();
+            (path.to_path_buf(), self.batch.apply(path))
+        })
+    }
+}
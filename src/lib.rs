@@ -13,8 +13,6 @@
 #![cfg_attr(feature = "clippy", deny(clippy_pedantic))]
 #![forbid(warnings)]
 #![forbid(anonymous_parameters)]
-#![forbid(box_pointers)]
-#![forbid(fat_ptr_transmutes)]
 #![forbid(missing_docs)]
 #![forbid(trivial_casts)]
 #![forbid(trivial_numeric_casts)]
@@ -25,20 +23,128 @@
 #![forbid(unused_results)]
 #![forbid(variant_size_differences)]
 
-#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), not(feature = "rustix")))]
 extern crate libc;
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), feature = "rustix"))]
+extern crate rustix;
 #[cfg(windows)]
-extern crate kernel32;
-#[cfg(windows)]
-extern crate winapi;
+extern crate windows_sys;
+#[cfg(feature = "cap")]
+extern crate cap_fs_ext;
+#[cfg(feature = "cap")]
+extern crate cap_std;
+#[cfg(feature = "chrono")]
+extern crate chrono;
+#[cfg(feature = "tz")]
+extern crate chrono_tz;
+#[cfg(feature = "parallel")]
+extern crate crossbeam_deque;
+#[cfg(feature = "glob")]
+extern crate globset;
+#[cfg(feature = "rayon")]
+extern crate rayon;
+extern crate smallvec;
+#[cfg(feature = "time")]
+extern crate time;
+#[cfg(feature = "gitignore")]
+extern crate ignore;
+#[cfg(feature = "tokio")]
+extern crate tokio;
+#[cfg(test)]
+#[macro_use]
+extern crate proptest;
 #[cfg(test)]
 extern crate tempdir;
 
+mod age;
+mod batch;
+mod backend;
+mod cache;
+#[cfg(feature = "cap")]
+mod cap;
+mod cancel;
+mod compare;
+pub mod convert;
+mod dedup;
+mod dir;
+mod error;
+mod existing;
+mod failure;
+mod file_time;
+mod filter;
+#[cfg(feature = "tokio")]
+mod future;
+mod granularity;
+mod iter;
+mod now;
+#[cfg(feature = "parallel")]
+mod parallel;
+#[cfg(feature = "parse")]
+pub mod parse;
+mod prepared;
+mod probe;
+mod progress;
+mod report;
+mod retry;
+mod service;
+mod strict;
 mod sys;
+mod throttle;
+mod timestamps;
+mod walk;
 
-use std::io;
-use std::path::Path;
-use std::time::SystemTime;
+pub use age::{age, age_nofollow, age_of, TimestampKind};
+pub use backend::{Backend, Native, NewTimes};
+pub use cache::PathCache;
+pub use cancel::CancellationToken;
+pub use compare::{is_newer_than, is_older_than, is_same_time, FAT_RESOLUTION,
+                  HFS_PLUS_RESOLUTION, NTFS_RESOLUTION};
+pub use dir::Dir;
+pub use error::{Error, Operation};
+pub use existing::WouldOpen;
+pub use failure::Failure;
+pub use file_time::{FileTime, TimestampOutOfRange};
+pub use filter::EntryTypes;
+pub use granularity::{granularity, Granularity, GranularityMode, TimestampMismatch,
+                      TimestampTooPrecise};
+pub use iter::TouchIter;
+pub use now::touch_now;
+pub use prepared::{PreparedPath, PreparedTimes};
+pub use probe::{probe, Capabilities};
+pub use progress::{Progress, ProgressSink};
+pub use report::{MultiError, Report, ReportEntry};
+pub use retry::RetryPolicy;
+pub use service::TouchService;
+pub use strict::NothingToDo;
+pub use timestamps::{read_times, Timestamps, UnsupportedTimestamp};
+use batch::Batch;
+use filter::AgeFilter;
+use granularity::Filesystem;
+#[cfg(feature = "rayon")]
+use rayon::ThreadPoolBuilder;
+#[cfg(feature = "rayon")]
+use rayon::iter::{IndexedParallelIterator, IntoParallelIterator, ParallelIterator};
+use sys::{DirHandle, EncodedPath, FileTimes, RawPath};
+#[cfg(feature = "parallel")]
+use walk::Tree;
+use walk::{Walk, WalkOptions};
+use std::{env, fs, io};
+use std::ffi::OsStr;
+#[cfg(unix)]
+use std::ffi::CStr;
+#[cfg(windows)]
+use std::ffi::OsString;
+#[cfg(feature = "tokio")]
+use std::future::Future;
+#[cfg(unix)]
+use std::os::unix::ffi::OsStrExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::AsFd;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStringExt;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
 
 #[derive(Clone, Debug)]
 /// A builder for updating filesystem timestamps.
@@ -46,15 +152,101 @@ pub struct Builder {
     /// The new access timestamp.
     ///
     /// If this is `None`, the access timestamp will not be modified.
-    accessed: Option<SystemTime>,
+    accessed: Option<Timestamp>,
     /// The new modification timestamp.
     ///
     /// If this is `None`, the modification timestamp will not be modified.
-    modified: Option<SystemTime>,
+    modified: Option<Timestamp>,
+    /// The new creation timestamp.
+    ///
+    /// If this is `None`, the creation timestamp will not be modified.
+    created: Option<FileTime>,
+    #[cfg(feature = "change-time")]
+    /// The new change timestamp.
+    ///
+    /// If this is `None`, the change timestamp will be left for the operating system to update.
+    changed: Option<FileTime>,
+    /// The latest timestamp that may be set; later timestamps are clamped to this.
+    latest: Option<FileTime>,
+    /// Whether to read the current time from a coarse clock.
+    coarse_clock: bool,
     /// Whether to follow symbolic links.
     follow_symlinks: bool,
+    /// Whether to follow NTFS junctions.
+    junctions: Junctions,
+    /// What to do if a followed symbolic link does not point to an existing path.
+    dangling_symlinks: DanglingSymlinks,
     /// What to create if a path does not exist.
     creation_target: CreationTarget,
+    /// Whether to create missing parent directories.
+    create_parents: bool,
+    /// Whether to remove created parent directories if a later step fails.
+    rollback_parents: bool,
+    /// Whether to synchronise the updated file to storage.
+    sync_file: bool,
+    #[cfg(windows)]
+    /// Whether to enable the backup and restore privileges and open paths with backup intent.
+    backup_privileges: bool,
+    #[cfg(windows)]
+    /// Which kinds of access other handles may have to a path while it is open.
+    share_mode: ShareMode,
+    #[cfg(windows)]
+    /// Whether to clear the read-only attribute of a path while updating it.
+    clear_readonly: bool,
+    #[cfg(windows)]
+    /// Whether to explicitly rewrite the access timestamp when it is not being changed.
+    preserve_accessed: bool,
+    #[cfg(unix)]
+    /// Whether a file may be opened for writing when it is created.
+    open_for_writing: bool,
+    #[cfg(unix)]
+    /// Whether to stop paths that are opened from having their access timestamps updated.
+    no_atime: bool,
+    /// What to do when updating a path fails during a batch operation.
+    error_policy: ErrorPolicy,
+    /// Whether to skip paths that refer to an already updated file during a batch operation.
+    deduplicate: bool,
+    /// Whether to group the paths of a batch operation by directory.
+    optimize_layout: bool,
+    /// Which parts of a directory tree to visit during a recursive operation.
+    walk: WalkOptions,
+    /// Whether to synchronise the parent directory of the updated file to storage.
+    sync_parent_dir: bool,
+    /// Whether to refuse any option that would open or create a path.
+    existing_only: bool,
+    /// Whether to refuse a configuration that would leave every path unchanged.
+    strict: bool,
+    /// Allows an operation on multiple paths to be stopped from another thread.
+    cancellation: Option<CancellationToken>,
+    /// Receives progress snapshots during an operation on multiple paths.
+    progress: Option<ProgressSink>,
+    /// The maximum number of paths to update per second during an operation on multiple paths.
+    ops_per_second: Option<u32>,
+    /// The maximum number of paths to update at once during an operation on multiple paths.
+    max_in_flight: Option<usize>,
+    /// The number of threads to use during a parallel operation, if not the default.
+    threads: Option<usize>,
+    /// How many paths a thread takes at a time during a parallel operation, if not the default.
+    chunk_size: Option<usize>,
+    /// How many paths may wait to be visited during a parallel recursive operation, if limited.
+    queue_depth: Option<usize>,
+    /// Which paths to update during an operation on multiple paths, based on their age.
+    age: AgeFilter,
+    /// How to retry updating a path that is temporarily locked, if at all.
+    retry: Option<RetryPolicy>,
+    /// How to deal with filesystems that store timestamps less precisely than requested.
+    granularity: GranularityMode,
+    /// What to update paths through, if not the operating system.
+    backend: Option<Arc<dyn Backend>>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// A timestamp to set.
+enum Timestamp {
+    /// A specific point in time.
+    At(FileTime),
+    /// The current time, as determined by the operating system as each path is updated.
+    Now,
 }
 
 #[derive(Clone, Debug)]
@@ -66,6 +258,66 @@ pub enum CreationTarget {
     File,
 }
 
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// What to do when creating through a symbolic link that does not point to an existing path.
+pub enum DanglingSymlinks {
+    /// Create the target of the symbolic link.
+    CreateTarget,
+    /// Fail instead of creating anything.
+    Refuse,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Whether to follow NTFS junctions, which Windows otherwise treats like symbolic links.
+pub enum Junctions {
+    /// Follow junctions exactly when symbolic links are followed.
+    LikeSymlinks,
+    /// Always follow junctions, even if symbolic links are not followed.
+    Follow,
+    /// Never follow junctions, even if symbolic links are followed.
+    NoFollow,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// Which kinds of access other handles may have to a path while its timestamps are updated.
+///
+/// This corresponds to the `FILE_SHARE_*` flags on Windows. Other platforms do not lock paths
+/// that are open, so it has no effect there.
+pub struct ShareMode {
+    /// Whether other handles may read the path.
+    read: bool,
+    /// Whether other handles may write to the path.
+    write: bool,
+    /// Whether other handles may delete or rename the path.
+    delete: bool,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// What to do when updating a path fails during an operation on multiple paths.
+pub enum ErrorPolicy {
+    /// Stop at the first failure.
+    FailFast,
+    /// Continue with the remaining paths, collecting every failure.
+    Continue,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+#[non_exhaustive]
+/// What happened to a path when its timestamps were updated.
+pub enum Outcome {
+    /// The path already existed.
+    Updated,
+    /// The path already existed and was read-only, so its read-only attribute was cleared while
+    /// updating it and then restored.
+    UpdatedReadOnly,
+    /// The path did not exist and was created.
+    Created,
+    /// The path was a dangling symbolic link, and its target was created.
+    CreatedSymlinkTarget,
+    /// The path was skipped, and its timestamps were left unchanged.
+    Skipped,
+}
+
 impl Builder {
     #[inline]
     /// Creates a new builder with default values.
@@ -73,26 +325,188 @@ impl Builder {
         Self {
             accessed: None,
             modified: None,
+            created: None,
+            #[cfg(feature = "change-time")]
+            changed: None,
+            latest: None,
+            coarse_clock: false,
             follow_symlinks: false,
+            junctions: Junctions::default(),
+            dangling_symlinks: DanglingSymlinks::default(),
             creation_target: CreationTarget::default(),
+            create_parents: false,
+            rollback_parents: false,
+            sync_file: false,
+            #[cfg(windows)]
+            backup_privileges: false,
+            #[cfg(windows)]
+            share_mode: ShareMode::default(),
+            #[cfg(windows)]
+            clear_readonly: false,
+            #[cfg(windows)]
+            preserve_accessed: false,
+            #[cfg(unix)]
+            open_for_writing: true,
+            #[cfg(unix)]
+            no_atime: false,
+            sync_parent_dir: false,
+            existing_only: false,
+            strict: false,
+            error_policy: ErrorPolicy::default(),
+            deduplicate: false,
+            optimize_layout: false,
+            walk: WalkOptions::default(),
+            cancellation: None,
+            progress: None,
+            ops_per_second: None,
+            max_in_flight: None,
+            threads: None,
+            chunk_size: None,
+            queue_depth: None,
+            age: AgeFilter::default(),
+            retry: None,
+            granularity: GranularityMode::default(),
+            backend: None,
         }
     }
 
+    /// Creates a new builder for reproducible builds, using the `SOURCE_DATE_EPOCH` environment
+    /// variable.
+    ///
+    /// `SOURCE_DATE_EPOCH` is a number of seconds since the Unix epoch. Both timestamps are set
+    /// to that time, and it is also passed to `clamp_to`, so that any later timestamps requested
+    /// afterwards are clamped down to it. Returns an error of kind `NotFound` if the variable is
+    /// not set, or `InvalidInput` if it is not a non-negative integer.
+    pub fn from_source_date_epoch() -> io::Result<Self> {
+        Self::from_source_date_epoch_var(env::var("SOURCE_DATE_EPOCH"))
+    }
+
+    /// Creates a new builder for reproducible builds, given the value of `SOURCE_DATE_EPOCH`.
+    fn from_source_date_epoch_var(value: Result<String, env::VarError>) -> io::Result<Self> {
+        let value = value.map_err(|e| match e {
+            env::VarError::NotPresent => {
+                io::Error::new(io::ErrorKind::NotFound, "SOURCE_DATE_EPOCH is not set")
+            }
+            env::VarError::NotUnicode(_) => {
+                io::Error::new(io::ErrorKind::InvalidInput, "SOURCE_DATE_EPOCH is not valid")
+            }
+        })?;
+        let seconds = value
+            .parse::<i64>()
+            .ok()
+            .filter(|&s| s >= 0 && value.bytes().all(|b| b.is_ascii_digit()))
+            .ok_or_else(|| {
+                io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("SOURCE_DATE_EPOCH `{}` is not a non-negative integer", value),
+                )
+            })?;
+        let time = FileTime::from_unix_time(seconds, 0);
+        let mut builder = Self::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .clamp_to(Some(time));
+        Ok(builder)
+    }
+
     #[inline]
     /// Specifies the access timestamp to use when updating timestamps.
     ///
-    /// If this is `None` (the default), the access timestamp will not be updated.
-    pub fn accessed(&mut self, time: Option<SystemTime>) -> &mut Self {
-        self.accessed = time;
+    /// This accepts a `SystemTime`, a `FileTime`, a chrono `DateTime` in any time zone (with the
+    /// `chrono` feature), or an `OffsetDateTime` (with the `time` feature). If this is `None` (the
+    /// default), the access timestamp will not be updated.
+    pub fn accessed<T: Into<FileTime>>(&mut self, time: Option<T>) -> &mut Self {
+        self.accessed = time.map(|t| Timestamp::At(t.into()));
+        self
+    }
+
+    #[inline]
+    /// Specifies that the access timestamp should be set to the current time.
+    ///
+    /// Unlike passing `SystemTime::now()` to `accessed`, the current time is read by the
+    /// operating system as each path is updated, so every path in a long operation on multiple
+    /// paths gets the time at which it was actually updated. On Windows, where `SetFileTime`
+    /// cannot read the current time itself, it is read with `GetSystemTimePreciseAsFileTime`
+    /// immediately beforehand, with the full 100-nanosecond precision of NTFS, unless
+    /// `coarse_clock` is set.
+    pub fn accessed_now(&mut self) -> &mut Self {
+        self.accessed = Some(Timestamp::Now);
         self
     }
 
     #[inline]
     /// Specifies the modification timestamp to use when updating timestamps.
     ///
-    /// If this is `None` (the default), the modification timestamp will not be updated.
-    pub fn modified(&mut self, time: Option<SystemTime>) -> &mut Self {
-        self.modified = time;
+    /// This accepts the same types as `accessed`. If this is `None` (the default), the
+    /// modification timestamp will not be updated.
+    pub fn modified<T: Into<FileTime>>(&mut self, time: Option<T>) -> &mut Self {
+        self.modified = time.map(|t| Timestamp::At(t.into()));
+        self
+    }
+
+    #[inline]
+    /// Specifies that the modification timestamp should be set to the current time.
+    ///
+    /// As with `accessed_now`, the current time is read by the operating system as each path is
+    /// updated.
+    pub fn modified_now(&mut self) -> &mut Self {
+        self.modified = Some(Timestamp::Now);
+        self
+    }
+
+    #[inline]
+    /// Specifies whether to read the current time from a coarse clock, which is cheaper to read
+    /// but only advances every few milliseconds.
+    ///
+    /// This suits programs that update thousands of paths per second and have no use for
+    /// sub-millisecond accuracy. If this is `true`, `accessed_now` and `modified_now` read the
+    /// current time on Windows with `GetSystemTimeAsFileTime`, which advances once per timer
+    /// tick, and `clamp_to` compares against `FileTime::now_coarse`. On Unix, the current time is
+    /// read by the kernel itself, which already uses its coarse clock for file timestamps, so
+    /// only `clamp_to` is affected. Defaults to `false`.
+    pub fn coarse_clock(&mut self, coarse: bool) -> &mut Self {
+        self.coarse_clock = coarse;
+        self
+    }
+
+    #[inline]
+    /// Specifies the creation timestamp to use when updating timestamps.
+    ///
+    /// This accepts the same types as `accessed`. If this is `None` (the default), the creation
+    /// timestamp will not be updated. Only Windows and FreeBSD allow creation timestamps to be
+    /// set; on other platforms, updating a path fails with an `UnsupportedTimestamp` error if
+    /// this is not `None`. FreeBSD can only move a creation timestamp back in time, and fails
+    /// with an error of kind `InvalidInput` otherwise.
+    pub fn created<T: Into<FileTime>>(&mut self, time: Option<T>) -> &mut Self {
+        self.created = time.map(Into::into);
+        self
+    }
+
+    #[cfg(feature = "change-time")]
+    #[inline]
+    /// Specifies the change timestamp to use when updating timestamps.
+    ///
+    /// The change timestamp records when a path's metadata was last changed, so it is normally
+    /// updated by the operating system whenever any other timestamp is set. This option is meant
+    /// for restoring timestamps exactly, e.g. for forensics or backups.
+    ///
+    /// This accepts the same types as `accessed`. Only Windows allows change timestamps to be
+    /// set, using `NtSetInformationFile`; on other platforms, updating a path fails with an
+    /// `UnsupportedTimestamp` error if this is not `None`.
+    pub fn changed<T: Into<FileTime>>(&mut self, time: Option<T>) -> &mut Self {
+        self.changed = time.map(Into::into);
+        self
+    }
+
+    #[inline]
+    /// Specifies the latest timestamp that may be set.
+    ///
+    /// Timestamps later than this are set to this time instead. This
+    /// accepts the same types as `accessed`. If this is `None` (the default), timestamps are not
+    /// clamped.
+    pub fn clamp_to<T: Into<FileTime>>(&mut self, time: Option<T>) -> &mut Self {
+        self.latest = time.map(Into::into);
         self
     }
 
@@ -100,16 +514,51 @@ impl Builder {
     /// Specifies whether to follow symbolic links.
     ///
     /// If this is `false` (the default) and a path refers to a symbolic link, the symbolic link
-    /// will be updated instead of the path it refers to.
+    /// will be updated instead of the path it refers to. On Windows, this applies to every kind
+    /// of reparse point, including directory junctions unless `junctions` says otherwise.
     pub fn follow_symlinks(&mut self, follow: bool) -> &mut Self {
         self.follow_symlinks = follow;
         self
     }
 
+    #[inline]
+    /// Specifies whether to follow NTFS junctions, independently of symbolic links.
+    ///
+    /// Windows uses junctions for compatibility links such as `Application Data` inside
+    /// `AppData`, so a recursive operation that follows symbolic links may otherwise visit the
+    /// same directories several times. Volume mount points are treated as junctions, since
+    /// Windows implements them the same way.
+    ///
+    /// By default, junctions are followed exactly when symbolic links are. Other platforms have
+    /// no junctions, so this has no effect there.
+    pub fn junctions(&mut self, junctions: Junctions) -> &mut Self {
+        self.junctions = junctions;
+        self
+    }
+
+    #[inline]
+    /// Specifies what to do if a path refers to a symbolic link whose target does not exist.
+    ///
+    /// This only has an effect if symbolic links are followed and a creation target is
+    /// specified. If symbolic links are not followed, a dangling symbolic link is updated itself
+    /// and nothing is created, just as any other symbolic link would be.
+    /// `DanglingSymlinks::Refuse` prevents creation from writing through links that may be
+    /// controlled by someone else, and fails with an error that `Failure::of` classifies as
+    /// `Failure::IsSymlink` on every platform.
+    ///
+    /// By default, the target of the symbolic link is created as a file, and `touch` reports
+    /// this with `Outcome::CreatedSymlinkTarget`. Missing parent directories of the target are
+    /// never created, even if `create_parents` is used.
+    pub fn dangling_symlinks(&mut self, dangling: DanglingSymlinks) -> &mut Self {
+        self.dangling_symlinks = dangling;
+        self
+    }
+
     #[inline]
     /// Specifies what to create if a path does not exist.
     ///
-    /// This is non-recursive, i.e. if any parent directories do not exist, creation will fail.
+    /// This is non-recursive, i.e. if any parent directories do not exist, creation will fail
+    /// unless `create_parents` is also used.
     ///
     /// By default, nothing will be created.
     pub fn creation_target(&mut self, target: CreationTarget) -> &mut Self {
@@ -118,383 +567,4354 @@ impl Builder {
     }
 
     #[inline]
-    /// Updates the timestamps for a filesystem path, using the options given to a builder.
-    pub fn touch<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        self.touch_sys(path)
+    /// Specifies whether to create missing parent directories when creating a path.
+    ///
+    /// This only has an effect if a creation target is specified.
+    ///
+    /// By default, parent directories are not created.
+    pub fn create_parents(&mut self, create: bool) -> &mut Self {
+        self.create_parents = create;
+        self
     }
-}
 
-impl Default for Builder {
     #[inline]
-    fn default() -> Self {
-        Self::new()
+    /// Specifies whether to remove parent directories created by `create_parents` if a later step
+    /// fails.
+    ///
+    /// If this is `true`, a failed `touch` removes any directories it created, so that it does
+    /// not leave a partial directory hierarchy behind. Directories that already existed, or that
+    /// were created by someone else in the meantime, are never removed.
+    ///
+    /// By default, created parent directories are kept.
+    pub fn rollback_parents(&mut self, rollback: bool) -> &mut Self {
+        self.rollback_parents = rollback;
+        self
     }
-}
 
-impl Default for CreationTarget {
     #[inline]
-    fn default() -> Self {
-        CreationTarget::None
+    /// Specifies whether to synchronise a file to storage after updating its timestamps.
+    ///
+    /// If this is `true`, the file (including any new timestamps) is flushed to storage before
+    /// `touch` returns, so that it survives a crash or power loss. This requires the file to be
+    /// opened for reading on Unix, and for writing on Windows. Symbolic links that are not
+    /// followed cannot be synchronised themselves; use `sync_parent_dir` for those instead.
+    ///
+    /// By default, files are not synchronised.
+    pub fn sync_file(&mut self, sync: bool) -> &mut Self {
+        self.sync_file = sync;
+        self
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use {Builder, CreationTarget};
-    use std::fs::{self, OpenOptions};
-    use std::io;
-    #[cfg(unix)]
-    use std::os::unix;
-    #[cfg(windows)]
-    use std::os::windows;
-    use std::path::{Path, PathBuf};
-    use std::time::SystemTime;
-    use tempdir::TempDir;
+    #[inline]
+    /// Specifies whether to use the backup and restore privileges on Windows.
+    ///
+    /// If this is `true`, `SeBackupPrivilege` and `SeRestorePrivilege` are enabled for the whole
+    /// process before the first path is updated, and paths are opened with backup intent. This
+    /// lets administrators and backup operators update paths whose permissions would otherwise
+    /// deny them access, as backup and restore tools need to. Updating a path fails if the
+    /// process is not allowed to enable the privileges.
+    ///
+    /// By default, no privileges are enabled. This has no effect on other platforms, where the
+    /// superuser can already update any path.
+    pub fn backup_privileges(&mut self, enable: bool) -> &mut Self {
+        #[cfg(windows)]
+        {
+            self.backup_privileges = enable;
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = enable;
+        }
+        self
+    }
 
-    struct TestHelper(TempDir);
+    #[inline]
+    /// Specifies whether to temporarily clear the read-only attribute of a path on Windows.
+    ///
+    /// If this is `true` and an existing path is read-only, its read-only attribute is cleared,
+    /// its timestamps are updated, and the attribute is then restored, which is reported as
+    /// `Outcome::UpdatedReadOnly`. The attribute is restored even if updating the path fails.
+    /// This is needed to open read-only files for writing, as `sync_file` does.
+    ///
+    /// By default, read-only paths are opened as they are. This has no effect on other
+    /// platforms, which have no read-only attribute.
+    pub fn clear_readonly(&mut self, clear: bool) -> &mut Self {
+        #[cfg(windows)]
+        {
+            self.clear_readonly = clear;
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = clear;
+        }
+        self
+    }
 
-    fn file_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
-        prefix.as_ref().join("file.txt")
+    #[inline]
+    /// Specifies whether to explicitly preserve the access timestamp of a path on Windows when
+    /// it is not being changed.
+    ///
+    /// Windows is asked not to update the access timestamp through the handle used to update
+    /// the other timestamps, but some filesystems and network redirectors ignore this. If this
+    /// is `true`, the access timestamp is instead read as soon as the path is opened and written
+    /// back along with the other timestamps, so that changing only the modification timestamp
+    /// has no side effects.
+    ///
+    /// By default, the access timestamp is left to the filesystem. This has no effect on other
+    /// platforms, where updating timestamps never opens the path.
+    pub fn preserve_accessed(&mut self, preserve: bool) -> &mut Self {
+        #[cfg(windows)]
+        {
+            self.preserve_accessed = preserve;
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = preserve;
+        }
+        self
     }
 
-    fn directory_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
-        prefix.as_ref().join("directory")
+    #[inline]
+    /// Specifies whether files may be opened for writing on Unix.
+    ///
+    /// Timestamps are set with `utimensat`, which does not open the path at all, and existing
+    /// paths are only ever opened for reading, to synchronise them to storage. Only creating a
+    /// missing file opens it for writing, without truncating it in case someone else created it
+    /// in the meantime. If this is `false`, a missing file is instead created by opening it for
+    /// reading only, as its owner can still set its timestamps, so that a process that is not
+    /// allowed to open files for writing can create them.
+    ///
+    /// By default, created files are opened for writing. This has no effect on other platforms.
+    pub fn open_for_writing(&mut self, write: bool) -> &mut Self {
+        #[cfg(unix)]
+        {
+            self.open_for_writing = write;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = write;
+        }
+        self
     }
 
-    fn symlink_file_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
-        prefix.as_ref().join("symlink-file.txt")
+    #[inline]
+    /// Specifies whether to open paths with `O_NOATIME` on Linux.
+    ///
+    /// Paths are opened to create them and to synchronise them to storage. Neither reads from
+    /// them, but archival tools that treat access timestamps as meaningful may not want to rely
+    /// on that. If this is `true`, `O_NOATIME` is passed wherever the calling process is
+    /// permitted to use it, i.e. for paths it owns or if it has the `CAP_FOWNER` capability, and
+    /// is silently dropped elsewhere.
+    ///
+    /// By default, `O_NOATIME` is not used. This has no effect on other platforms.
+    pub fn no_atime(&mut self, no_atime: bool) -> &mut Self {
+        #[cfg(unix)]
+        {
+            self.no_atime = no_atime;
+        }
+        #[cfg(not(unix))]
+        {
+            let _ = no_atime;
+        }
+        self
     }
 
-    fn symlink_directory_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
-        prefix.as_ref().join("symlink-directory")
+    #[inline]
+    /// Specifies which kinds of access other handles may have to a path while it is open.
+    ///
+    /// Stricter sharing prevents other processes from, for example, deleting a file while its
+    /// timestamps are being updated, but makes opening the path fail with a sharing violation
+    /// if another handle already has access that is not shared. Looser sharing does the
+    /// opposite.
+    ///
+    /// By default, every kind of access is shared. This has no effect on other platforms, which
+    /// do not lock open paths.
+    pub fn share_mode(&mut self, mode: ShareMode) -> &mut Self {
+        #[cfg(windows)]
+        {
+            self.share_mode = mode;
+        }
+        #[cfg(not(windows))]
+        {
+            let _ = mode;
+        }
+        self
     }
 
-    fn times<P: AsRef<Path>>(path: P) -> (SystemTime, SystemTime) {
-        let metadata = match fs::metadata(&path) {
-            Ok(m) => m,
-            Err(e) => panic!(
-                "could not obtain metadata for {}: {}",
-                path.as_ref().display(),
-                e
-            ),
-        };
-        (
-            metadata
-                .accessed()
-                .expect("atime not supported on this platform"),
-            metadata
-                .modified()
-                .expect("mtime not supported on this platform"),
-        )
+    #[inline]
+    /// Specifies whether to synchronise the parent directory of a path to storage after updating
+    /// its timestamps.
+    ///
+    /// If this is `true`, the directory entry for a path (including one that was just created) is
+    /// flushed to storage before `touch` returns. On Windows, directory entries are journaled by
+    /// the filesystem and cannot be flushed explicitly, so this has no effect there.
+    ///
+    /// By default, parent directories are not synchronised.
+    pub fn sync_parent_dir(&mut self, sync: bool) -> &mut Self {
+        self.sync_parent_dir = sync;
+        self
     }
 
-    fn symlink_times<P: AsRef<Path>>(path: P) -> (SystemTime, SystemTime) {
-        let metadata = match fs::symlink_metadata(&path) {
-            Ok(m) => m,
-            Err(e) => panic!(
-                "could not obtain metadata for {}: {}",
-                path.as_ref().display(),
-                e
-            ),
-        };
-        (
-            metadata
-                .accessed()
-                .expect("atime not supported on this platform"),
-            metadata
-                .modified()
-                .expect("mtime not supported on this platform"),
-        )
+    #[inline]
+    /// Specifies whether to guarantee that paths are never opened or created while updating
+    /// them.
+    ///
+    /// If this is `true`, timestamps are only ever set on existing paths: with `utimensat` on
+    /// Unix, and with `SetFileTime` on a handle opened for `FILE_WRITE_ATTRIBUTES` on Windows,
+    /// which has no way to set timestamps by path. Any option that would do more, i.e. a
+    /// `creation_target`, `sync_file` or `sync_parent_dir`, makes updating fail with a
+    /// `WouldOpen` error before anything is done, as does `touch_cap`. This lets services
+    /// confined by AppArmor, seccomp or Landlock rely on a fixed set of system calls. Recursive
+    /// operations still list directories, which opens them.
+    ///
+    /// By default, options are not restricted.
+    pub fn existing_only(&mut self, existing_only: bool) -> &mut Self {
+        self.existing_only = existing_only;
+        self
     }
 
-    fn touch<P: AsRef<Path>>(builder: &Builder, path: P) {
-        if let Err(e) = builder.touch(path) {
-            panic!("`Builder::touch` failed: {}", e);
-        }
+    #[inline]
+    /// Specifies whether to refuse a configuration that would leave every path unchanged.
+    ///
+    /// If this is `true`, and neither a timestamp nor a creation target is specified, updating
+    /// fails with a `NothingToDo` error before any path is opened. Otherwise, such a builder
+    /// quietly succeeds without changing anything, which usually means that a script forgot to
+    /// pass a timestamp.
+    ///
+    /// By default, such configurations are allowed.
+    pub fn strict(&mut self, strict: bool) -> &mut Self {
+        self.strict = strict;
+        self
     }
 
-    impl TestHelper {
-        pub fn new() -> TestHelper {
-            match TempDir::new("nudge-rs_test") {
-                Ok(td) => TestHelper(td),
-                Err(e) => panic!("could not create temporary directory: {}", e),
-            }
-        }
+    #[inline]
+    /// Specifies what to do when updating a path fails during an operation on multiple paths.
+    ///
+    /// With `ErrorPolicy::FailFast`, the operation stops at the first failure, and its report
+    /// ends with the path that failed. With `ErrorPolicy::Continue` (the default), the remaining
+    /// paths are still updated, and the report contains every failure.
+    pub fn error_policy(&mut self, policy: ErrorPolicy) -> &mut Self {
+        self.error_policy = policy;
+        self
+    }
 
-        pub fn create_top_level_file(&self) -> PathBuf {
-            let path = file_path(self.0.path());
-            match OpenOptions::new().write(true).create_new(true).open(&path) {
-                Ok(_) => path,
-                Err(e) => panic!("could not create top-level file: {}", e),
-            }
-        }
+    #[inline]
+    /// Specifies whether to skip duplicate paths during an operation on multiple paths.
+    ///
+    /// If this is `true`, each path is resolved to the file it refers to before it is updated,
+    /// and paths that refer to an already updated file are reported as `Outcome::Skipped`. This
+    /// includes the same file reached through different hard links, or through symbolic links if
+    /// they are followed, so that no file is updated more than once.
+    ///
+    /// By default, every path is updated.
+    pub fn deduplicate(&mut self, deduplicate: bool) -> &mut Self {
+        self.deduplicate = deduplicate;
+        self
+    }
 
-        pub fn create_top_level_directory(&self) -> PathBuf {
-            let path = directory_path(self.0.path());
-            match fs::create_dir(&path) {
-                Ok(_) => path,
-                Err(e) => panic!("could not create top-level directory: {}", e),
-            }
-        }
+    #[inline]
+    /// Specifies whether `touch_all` may reorder paths to update those in the same directory
+    /// together.
+    ///
+    /// If this is `true`, paths are grouped by their parent directory, which is opened once per
+    /// group; each path in it is then updated relative to the open directory, like `touch_at`,
+    /// so the kernel does not resolve the directory's path again for every entry. This helps
+    /// with long lists of paths deep inside a tree. Paths are still updated in their original
+    /// order within each group, but the groups are sorted by directory, and the report follows
+    /// the order in which paths were updated. A path whose directory cannot be opened, such as
+    /// one that `create_parents` has yet to create, is updated by its full path instead, so every
+    /// other option applies as it does without this. This has no effect together with
+    /// `existing_only`, since it opens directories.
+    ///
+    /// By default, paths are updated in the order given.
+    pub fn optimize_layout(&mut self, optimize: bool) -> &mut Self {
+        self.optimize_layout = optimize;
+        self
+    }
 
-        #[cfg(unix)]
-        pub fn create_top_level_symlink_file(&self) -> PathBuf {
-            let src = file_path(self.0.path());
-            let dst = symlink_file_path(self.0.path());
-            match unix::fs::symlink(src, &dst) {
-                Ok(_) => dst,
-                Err(e) => panic!("could not create file symbolic link: {}", e),
-            }
+    #[inline]
+    /// Specifies a token for cancelling operations on multiple paths.
+    ///
+    /// Once the token is cancelled, any running batch or recursive operation stops before
+    /// updating its next path, and its report is marked as cancelled. Paths that are already
+    /// being updated are finished first, so no path is left half updated.
+    ///
+    /// By default, operations run until every path has been processed.
+    pub fn cancellation_token(&mut self, token: Option<CancellationToken>) -> &mut Self {
+        self.cancellation = token;
+        self
+    }
+
+    #[inline]
+    /// Specifies a sink for progress snapshots during operations on multiple paths.
+    ///
+    /// While a batch or recursive operation runs, the sink receives a snapshot of how many paths
+    /// have been processed, how many failed, and which path was processed most recently, at most
+    /// once per the sink's interval. During a parallel operation, the sink may be invoked from
+    /// any of the operation's threads, but never from more than one at a time.
+    ///
+    /// By default, no progress is reported.
+    pub fn progress(&mut self, sink: Option<ProgressSink>) -> &mut Self {
+        self.progress = sink;
+        self
+    }
+
+    #[inline]
+    /// Specifies the maximum number of paths to update per second during an operation on
+    /// multiple paths.
+    ///
+    /// This avoids overwhelming network filesystems, whose servers may throttle or time out
+    /// clients that send too many metadata updates at once. Updates are spread evenly over each
+    /// second, even during parallel operations. A limit of zero is treated as no limit.
+    ///
+    /// By default, paths are updated as quickly as possible.
+    pub fn rate_limit(&mut self, ops_per_second: Option<u32>) -> &mut Self {
+        self.ops_per_second = ops_per_second;
+        self
+    }
+
+    #[inline]
+    /// Specifies the maximum number of paths to update at once during an operation on multiple
+    /// paths.
+    ///
+    /// This only has an effect on parallel operations, since other operations update one path
+    /// at a time. A limit of zero is treated as no limit. Regardless of this setting, parallel
+    /// operations never update more paths at once than the process's file descriptor or handle
+    /// limit allows.
+    ///
+    /// By default, parallel operations update as many paths at once as they have threads.
+    pub fn max_in_flight(&mut self, limit: Option<usize>) -> &mut Self {
+        self.max_in_flight = limit;
+        self
+    }
+
+    #[inline]
+    /// Specifies how many threads parallel operations use.
+    ///
+    /// Fewer threads suit spinning disks, which have to seek between concurrent requests, while
+    /// more threads than cores can help on network filesystems, where each request mostly waits
+    /// for the server. With the `rayon` feature, `touch_all_par` runs on a dedicated pool of
+    /// this many threads instead of rayon's global pool. A value of zero is treated as `None`.
+    ///
+    /// If this is `None` (the default), there is one thread per available core. Either way,
+    /// `touch_recursive_par` never uses more threads than the process's file descriptor or
+    /// handle limit allows paths to be updated at once.
+    pub fn threads(&mut self, threads: Option<usize>) -> &mut Self {
+        self.threads = threads.filter(|&n| n > 0);
+        self
+    }
+
+    #[inline]
+    /// Specifies how many paths a thread takes at a time during a parallel operation.
+    ///
+    /// Larger chunks mean less contention between threads when paths are quick to update, and
+    /// smaller chunks keep every thread busy when some paths are slow. A value of zero is treated
+    /// as `None`.
+    ///
+    /// If this is `None` (the default), `touch_all_par` leaves splitting the paths to rayon and
+    /// `touch_recursive_par` lets an idle thread take up to half of the paths waiting in the
+    /// queue it takes them from.
+    pub fn chunk_size(&mut self, size: Option<usize>) -> &mut Self {
+        self.chunk_size = size.filter(|&n| n > 0);
+        self
+    }
+
+    #[inline]
+    /// Specifies how many paths may wait to be visited during `touch_recursive_par`.
+    ///
+    /// Once this many paths are waiting, a thread visits the contents of each directory it lists
+    /// straight away, depth first, instead of queueing them for other threads. A small queue
+    /// keeps memory use down on very wide trees, at the cost of leaving threads idle when there
+    /// is too little queued work to share; it also keeps a directory open for each level the
+    /// thread descends. `touch_all_par` is given all of its paths up front, so it has no queue
+    /// to limit; use `max_in_flight` to limit how many paths it updates at once. A value of zero
+    /// is treated as `None`.
+    ///
+    /// If this is `None` (the default), every path found is queued.
+    pub fn queue_depth(&mut self, depth: Option<usize>) -> &mut Self {
+        self.queue_depth = depth.filter(|&n| n > 0);
+        self
+    }
+
+    #[inline]
+    /// Specifies how to retry updating a path that is temporarily locked by another process.
+    ///
+    /// On Windows, antivirus scanners and search indexers briefly open files without sharing
+    /// them, which would otherwise make an update fail with a sharing violation. During an
+    /// operation on multiple paths, each path is retried independently.
+    ///
+    /// By default, failures are not retried.
+    pub fn retry(&mut self, policy: Option<RetryPolicy>) -> &mut Self {
+        self.retry = policy;
+        self
+    }
+
+    #[inline]
+    /// Specifies how to deal with filesystems that store timestamps less precisely than
+    /// requested, such as FAT.
+    ///
+    /// `GranularityMode::Round` rounds the access, modification and creation timestamps down to
+    /// what the filesystem containing each path can store, as reported by `granularity`, so that
+    /// the timestamps read back later are exactly the ones that were set. Access dates on FAT are
+    /// stored in local time, which is not known here, so they are left for the filesystem to
+    /// round. `GranularityMode::Verify` instead reads the timestamps back after setting them,
+    /// and fails with a `TimestampMismatch` if any of them was stored differently.
+    /// `GranularityMode::Exact` is for archival tools, where keeping timestamps intact matters
+    /// more than updating the path: it fails with a `TimestampTooPrecise`, without changing
+    /// anything, if the filesystem cannot store one of the timestamps exactly, and then verifies
+    /// them as `GranularityMode::Verify` does. None of these modes affects timestamps set to the
+    /// current time by the operating system.
+    ///
+    /// By default, filesystems round timestamps without this being reported.
+    pub fn granularity(&mut self, mode: GranularityMode) -> &mut Self {
+        self.granularity = mode;
+        self
+    }
+
+    #[inline]
+    /// Specifies what to update paths through, if not the operating system.
+    ///
+    /// `touch` and the methods built on it, including those that update multiple paths, then open,
+    /// create and set the timestamps of each path using the backend, while still applying the
+    /// timestamps, `clamp_to`, `coarse_clock`, `follow_symlinks`, `dangling_symlinks`,
+    /// `creation_target`, `create_parents`, `rollback_parents`, `existing_only`, `strict` and
+    /// `retry`. `GranularityMode::Verify` and `GranularityMode::Exact` read the timestamps back
+    /// through the backend and compare them exactly, while the backend is assumed to store every
+    /// timestamp exactly for `GranularityMode::Round` and `GranularityMode::Exact`. Options
+    /// specific to the operating system, such as `sync_file` or `share_mode`, are only applied
+    /// by `Native`.
+    ///
+    /// Recursive operations still list directories through the operating system, and `touch_at`,
+    /// `touch_fd`, `touch_cap` and `touch_dir_entries` always use it, since they work with open
+    /// directories or files.
+    ///
+    /// By default, paths are updated through the operating system.
+    pub fn backend(&mut self, backend: Option<Arc<dyn Backend>>) -> &mut Self {
+        self.backend = backend;
+        self
+    }
+
+    #[inline]
+    /// Specifies the minimum age of paths to update during an operation on multiple paths.
+    ///
+    /// If this is set, paths whose modification timestamp is more recent than this long before
+    /// the start of the operation are reported as `Outcome::Skipped`, instead of being updated.
+    /// Paths that do not exist yet are not affected. For example, `Duration::from_secs(30 *
+    /// 86_400)` refreshes only paths that have not been modified for 30 days.
+    ///
+    /// By default, paths are updated regardless of their age.
+    pub fn only_older_than(&mut self, age: Option<Duration>) -> &mut Self {
+        self.age.set_older_than(age);
+        self
+    }
+
+    #[inline]
+    /// Specifies the maximum age of paths to update during an operation on multiple paths.
+    ///
+    /// If this is set, paths whose modification timestamp is this long or longer before the
+    /// start of the operation are reported as `Outcome::Skipped`, instead of being updated.
+    /// Paths that do not exist yet are not affected.
+    ///
+    /// By default, paths are updated regardless of their age.
+    pub fn only_newer_than(&mut self, age: Option<Duration>) -> &mut Self {
+        self.age.set_newer_than(age);
+        self
+    }
+
+    #[inline]
+    /// Specifies the minimum depth of paths to update during a recursive operation.
+    ///
+    /// The root of the tree has a depth of zero, its contents have a depth of one, and so on.
+    /// Paths above the minimum depth are still visited, but their timestamps are not updated;
+    /// for example, a minimum depth of one updates everything except the root itself.
+    ///
+    /// By default, the minimum depth is zero.
+    pub fn min_depth(&mut self, depth: usize) -> &mut Self {
+        self.walk.min_depth = depth;
+        self
+    }
+
+    #[inline]
+    /// Specifies the maximum depth of paths to update during a recursive operation.
+    ///
+    /// Directories at the maximum depth are updated, but their contents are not visited; for
+    /// example, a maximum depth of one updates only the root and its immediate contents.
+    ///
+    /// By default, there is no maximum depth.
+    pub fn max_depth(&mut self, depth: usize) -> &mut Self {
+        self.walk.max_depth = depth;
+        self
+    }
+
+    #[inline]
+    /// Specifies which types of path to update during a recursive operation.
+    ///
+    /// Directories are visited regardless of this setting; for example, `EntryTypes::Files`
+    /// updates every file in a tree, but none of the directories containing them.
+    ///
+    /// By default, every type of path is updated.
+    pub fn entry_types(&mut self, types: EntryTypes) -> &mut Self {
+        self.walk.filter.set_types(types);
+        self
+    }
+
+    #[inline]
+    /// Adds a file extension (without the leading `.`) to update during a recursive operation.
+    ///
+    /// If any extensions are added, only paths with one of them are updated. Extensions are
+    /// compared exactly, including case.
+    ///
+    /// By default, paths are updated regardless of their extension.
+    pub fn extension<S: AsRef<OsStr>>(&mut self, extension: S) -> &mut Self {
+        self.walk.filter.add_extension(extension.as_ref().to_os_string());
+        self
+    }
+
+    #[cfg(feature = "glob")]
+    #[inline]
+    /// Adds a glob pattern to update during a recursive operation.
+    ///
+    /// If any patterns are added, only paths matching one of them are updated. Patterns are
+    /// matched against paths relative to the root of the tree, and `*` matches across directory
+    /// separators, so `*.o` matches every object file in the tree.
+    ///
+    /// By default, paths are updated regardless of their name. Returns an error of kind
+    /// `InvalidInput` if the pattern is invalid. This requires the `glob` feature.
+    pub fn glob(&mut self, pattern: &str) -> io::Result<&mut Self> {
+        self.walk.filter.add_glob(pattern)?;
+        Ok(self)
+    }
+
+    #[cfg(feature = "glob")]
+    #[inline]
+    /// Adds a glob pattern to exclude during a recursive operation.
+    ///
+    /// Paths matching an excluded pattern are not updated, and excluded directories are not
+    /// descended into. Patterns are matched against paths relative to the root of the tree, so
+    /// `**/.git` excludes every `.git` directory in the tree, while `.git` only excludes the one
+    /// directly beneath the root.
+    ///
+    /// Returns an error of kind `InvalidInput` if the pattern is invalid. This requires the `glob`
+    /// feature.
+    pub fn exclude_glob(&mut self, pattern: &str) -> io::Result<&mut Self> {
+        self.walk.filter.add_exclude_glob(pattern)?;
+        Ok(self)
+    }
+
+    #[inline]
+    /// Adds a path, relative to the root of the tree, to exclude during a recursive operation.
+    ///
+    /// The path is matched literally, without interpreting any wildcards. Like excluded patterns,
+    /// an excluded path is not updated, and is not descended into if it is a directory.
+    pub fn exclude_path<P: AsRef<Path>>(&mut self, path: P) -> &mut Self {
+        self.walk.filter.add_exclude_path(path.as_ref().to_path_buf());
+        self
+    }
+
+    #[cfg(feature = "gitignore")]
+    #[inline]
+    /// Specifies whether to honour `.gitignore` and `.ignore` files during a recursive operation.
+    ///
+    /// If this is `true`, the rules in any `.gitignore` and `.ignore` files found in the tree are
+    /// applied to the paths beneath them, using the same syntax and precedence as Git: rules in
+    /// inner directories override those in outer directories, and `.ignore` files override
+    /// `.gitignore` files in the same directory. Ignored paths are not updated, and ignored
+    /// directories are not descended into. Ignore files outside the tree, and Git's global and
+    /// per-repository exclude files, are not consulted.
+    ///
+    /// By default, ignore files are not honoured. This requires the `gitignore` feature.
+    pub fn respect_ignore_files(&mut self, respect: bool) -> &mut Self {
+        self.walk.respect_ignore_files = respect;
+        self
+    }
+
+    #[inline]
+    /// Specifies whether to stay on the filesystem of the root during a recursive operation.
+    ///
+    /// If this is `true`, paths on a different filesystem than the root (i.e. mount points, and
+    /// everything beneath them) are neither updated nor descended into. Filesystems are compared
+    /// by device number on Unix, and by volume serial number on Windows.
+    ///
+    /// By default, recursive operations cross filesystem boundaries.
+    pub fn one_file_system(&mut self, one: bool) -> &mut Self {
+        self.walk.one_file_system = one;
+        self
+    }
+
+    #[inline]
+    /// Specifies whether to skip hidden paths during a recursive operation.
+    ///
+    /// If this is `true`, hidden paths beneath the root are neither updated nor descended into.
+    /// On Unix, a path is hidden if its name starts with a `.`; on Windows, a path is hidden if
+    /// it has the hidden attribute.
+    ///
+    /// By default, hidden paths are updated like any other path.
+    pub fn skip_hidden(&mut self, skip: bool) -> &mut Self {
+        self.walk.skip_hidden = skip;
+        self
+    }
+
+    #[inline]
+    /// Specifies whether to visit paths in a deterministic order during a recursive operation.
+    ///
+    /// If this is `true`, the contents of each directory are visited in order of their names,
+    /// compared as raw bytes (or UTF-16 code units on Windows), so that paths are updated (and
+    /// reported) in the same order on every run. This requires reading each directory in full
+    /// before visiting its contents.
+    ///
+    /// By default, paths are visited in whatever order the operating system lists them.
+    pub fn sorted(&mut self, sorted: bool) -> &mut Self {
+        self.walk.sorted = sorted;
+        self
+    }
+
+    #[inline]
+    /// Updates the timestamps for a filesystem path, using the options given to a builder.
+    ///
+    /// On success, returns whether the path already existed or had to be created.
+    ///
+    /// On Windows, the path may name an alternate data stream, such as `file.txt:stream`.
+    /// Timestamps belong to the file rather than its streams, so this updates the file's
+    /// timestamps, creating the stream first if a creation target is specified. Filesystems
+    /// without named streams, such as FAT, fail with an error of kind `Unsupported`.
+    pub fn touch<P: AsRef<Path>>(&self, path: P) -> io::Result<Outcome> {
+        self.touch_with_times(path.as_ref(), &FileTimes::from_builder(self))
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path that was already converted into the form the operating
+    /// system expects, using the options given to a builder.
+    ///
+    /// This behaves exactly like `touch`, but the path is not converted again, which saves an
+    /// allocation when the same path is updated many times.
+    pub fn touch_prepared(&self, path: &PreparedPath) -> io::Result<Outcome> {
+        self.touch_encoded(path.path(), path.as_raw(), &FileTimes::from_builder(self))
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path, using the options given to a builder and remembering
+    /// the prepared path in a cache.
+    ///
+    /// This behaves exactly like `touch_prepared`, but prepares the path only the first time it
+    /// is seen, or again if the cache has forgotten it since then.
+    pub fn touch_cached<P: AsRef<Path>>(
+        &self,
+        cache: &mut PathCache,
+        path: P,
+    ) -> io::Result<Outcome> {
+        let path = path.as_ref();
+        let prepared = cache
+            .get(path)
+            .map_err(error::context(Operation::SetTimes, path))?;
+        self.touch_prepared(prepared)
+    }
+
+    #[cfg(unix)]
+    #[inline]
+    /// Updates the timestamps for a path that is already a C string, using the options given to
+    /// a builder.
+    ///
+    /// This behaves exactly like `touch`, but passes the path to the operating system as it is,
+    /// without copying or converting it, which suits callers that already hold paths in this
+    /// form, such as the names returned by `readdir`.
+    ///
+    /// This method is only available on Unix.
+    pub fn touch_c_str(&self, path: &CStr) -> io::Result<Outcome> {
+        let p = Path::new(OsStr::from_bytes(path.to_bytes()));
+        self.touch_encoded(p, path, &FileTimes::from_builder(self))
+    }
+
+    #[cfg(windows)]
+    #[inline]
+    /// Updates the timestamps for a path that is already a NUL-terminated wide string, using the
+    /// options given to a builder.
+    ///
+    /// This behaves like `touch`, but passes the path to `CreateFileW` exactly as it is, which
+    /// suits callers that already hold paths in this form, such as the names returned by
+    /// `FindFirstFileW`. Unlike `touch`, long paths are not given the extended-length prefix and
+    /// `/` separators are left for Windows to handle. The path is still copied once, since
+    /// several options need to inspect it.
+    ///
+    /// Fails with an error of kind `InvalidInput` unless the path ends with its only NUL
+    /// character. This method is only available on Windows.
+    pub fn touch_wide(&self, path: &[u16]) -> io::Result<Outcome> {
+        match path.iter().position(|&c| c == 0) {
+            Some(len) if len + 1 == path.len() => {
+                let p = PathBuf::from(OsString::from_wide(&path[..len]));
+                self.touch_encoded(&p, path, &FileTimes::from_builder(self))
+            }
+            _ => Err(error::invalid_path("path does not end with its only NUL character")),
+        }
+    }
+
+    #[inline]
+    /// Updates the timestamps for a filesystem path, using timestamps that were already
+    /// converted and the other options given to a builder.
+    ///
+    /// This behaves like `touch`, but sets the prepared timestamps instead of converting the
+    /// builder's own timestamps again, which saves work when updating many paths one at a time.
+    /// The timestamps should have been prepared from this builder, since `GranularityMode::Round`
+    /// still rounds the builder's own timestamps.
+    pub fn touch_with_prepared<P: AsRef<Path>>(
+        &self,
+        path: P,
+        times: &PreparedTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_with_times(path.as_ref(), times.times())
+    }
+
+    #[inline]
+    /// Updates the timestamps for a path relative to an open directory, using the options given
+    /// to a builder.
+    ///
+    /// This behaves like `touch`, except that a relative path is resolved from `dir` instead of
+    /// the current directory. On Unix, this uses `utimensat` and `openat` with the directory's
+    /// file descriptor. Absolute paths are not resolved from `dir`. `create_parents` and
+    /// `granularity` have no effect here, since both need the full path.
+    pub fn touch_at<P: AsRef<Path>>(&self, dir: &Dir, path: P) -> io::Result<Outcome> {
+        let path = path.as_ref();
+        let times = FileTimes::from_builder(self);
+        let touch = || -> io::Result<Outcome> {
+            times.check()?;
+            existing::check(self)?;
+            strict::check(self)?;
+            self.retrying(|| self.touch_at_sys(dir.handle(), path, &times))
+        };
+        touch().map_err(error::context(Operation::SetTimes, path))
+    }
+
+    #[cfg(target_os = "linux")]
+    #[inline]
+    /// Updates the timestamps for the file an open file descriptor refers to, using the options
+    /// given to a builder.
+    ///
+    /// This uses `utimensat` with `AT_EMPTY_PATH`, so the file is updated even if it has since
+    /// been renamed or unlinked, and the descriptor may have been opened with `O_PATH`. Kernels
+    /// that do not support this fall back to `futimens`, which does not accept `O_PATH`
+    /// descriptors. If the descriptor refers to a symbolic link, the link itself is updated.
+    ///
+    /// Nothing is created, and only `sync_file` and `retry` have any effect; synchronising also
+    /// fails for `O_PATH` descriptors. Returns `Outcome::Updated` on success.
+    ///
+    /// This method is only available on Linux.
+    pub fn touch_fd<F: AsFd>(&self, fd: F) -> io::Result<Outcome> {
+        let times = FileTimes::from_builder(self);
+        times.check()?;
+        strict::check(self)?;
+        self.retrying(|| self.touch_fd_sys(fd.as_fd(), &times))
+    }
+
+    #[cfg(feature = "cap")]
+    #[inline]
+    /// Updates the timestamps for a path beneath a `cap-std` directory, using the options given
+    /// to a builder.
+    ///
+    /// The path is resolved by `cap-std`, which fails instead of letting it escape the directory
+    /// through `..`, an absolute path or a symbolic link, so a capability-sandboxed program can
+    /// use this without any ambient authority. Only access and modification timestamps can be
+    /// set this way, on every platform. `follow_symlinks`, `creation_target`,
+    /// `dangling_symlinks`, `sync_file` and `retry` are honoured; other options have no effect.
+    ///
+    /// This method is only available if the `cap` feature is enabled.
+    pub fn touch_cap<P: AsRef<Path>>(
+        &self,
+        dir: &cap_std::fs::Dir,
+        path: P,
+    ) -> io::Result<Outcome> {
+        let path = path.as_ref();
+        cap::touch(self, dir, path).map_err(error::context(Operation::SetTimes, path))
+    }
+
+    #[cfg(feature = "tokio")]
+    #[inline]
+    /// Updates the timestamps for a filesystem path without blocking an asynchronous runtime,
+    /// using the options given to a builder.
+    ///
+    /// This behaves like `touch`, but runs on tokio's blocking thread pool, so the runtime's
+    /// other tasks keep running in the meantime. The builder's options are copied when this is
+    /// called, but nothing happens until the returned future is polled. Fails with an error of
+    /// kind `Other` if the runtime shuts down before the path is updated.
+    ///
+    /// The returned future must be polled within a tokio runtime. This method is only available
+    /// if the `tokio` feature is enabled.
+    pub fn touch_async<P: Into<PathBuf>>(
+        &self,
+        path: P,
+    ) -> impl Future<Output = io::Result<Outcome>> + Send {
+        let builder = self.clone();
+        let path = path.into();
+        future::spawn(move || builder.touch(&path))
+    }
+
+    #[cfg(feature = "tokio")]
+    #[inline]
+    /// Updates the timestamps for several filesystem paths without blocking an asynchronous
+    /// runtime, using the options given to a builder.
+    ///
+    /// This behaves like `touch_all`, running the whole operation on tokio's blocking thread
+    /// pool. Fails only if the runtime shuts down before the operation runs; failures for
+    /// individual paths are recorded in the report as usual.
+    ///
+    /// The returned future must be polled within a tokio runtime. This method is only available
+    /// if the `tokio` feature is enabled.
+    pub fn touch_all_async<I, P>(&self, paths: I) -> impl Future<Output = io::Result<Report>> + Send
+    where
+        I: IntoIterator<Item = P>,
+        P: Into<PathBuf>,
+    {
+        let builder = self.clone();
+        let paths: Vec<PathBuf> = paths.into_iter().map(Into::into).collect();
+        future::spawn(move || Ok(builder.touch_all(paths)))
+    }
+
+    #[inline]
+    /// Updates the timestamps for several filesystem paths, using the options given to a builder.
+    ///
+    /// The timestamps are converted only once and then applied to every path in turn. Whether a
+    /// failure for one path stops the remaining paths from being updated depends on the builder's
+    /// error policy.
+    ///
+    /// Returns a report with the result for each processed path, in the same order as the input
+    /// unless `optimize_layout` is set.
+    pub fn touch_all<I, P>(&self, paths: I) -> Report
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        let batch = Batch::new(self);
+        if self.optimize_layout && !self.existing_only && self.backend.is_none() {
+            let paths = paths.into_iter().map(|p| p.as_ref().to_path_buf()).collect();
+            self.touch_grouped(&batch, paths);
+            return batch.finish();
+        }
+        for path in paths {
+            if !batch.touch(path.as_ref()) {
+                break;
+            }
+        }
+        batch.finish()
+    }
+
+    #[inline]
+    /// Returns an iterator that updates the timestamps for each of several filesystem paths as
+    /// it is reached, using the options given to a builder.
+    ///
+    /// This behaves like `touch_all`, except that nothing happens until the iterator is advanced,
+    /// and each path is yielded together with its result instead of being collected into a
+    /// report. Dropping the iterator leaves the remaining paths untouched. The iterator ends
+    /// early if a failure stops it under the `FailFast` error policy, or if it is cancelled.
+    pub fn touch_iter<I, P>(&self, paths: I) -> TouchIter<'_, I::IntoIter>
+    where
+        I: IntoIterator<Item = P>,
+        P: AsRef<Path>,
+    {
+        TouchIter::new(Batch::new(self), paths.into_iter())
+    }
+
+    #[cfg(feature = "rayon")]
+    #[inline]
+    /// Updates the timestamps for several filesystem paths in parallel, using the options given
+    /// to a builder.
+    ///
+    /// This behaves like `touch_all`, except that the paths are spread across Rayon's thread
+    /// pool, so at most one path per pool thread is updated at a time. If the error policy is
+    /// `FailFast`, paths that were not yet started when a failure occurred are not updated.
+    ///
+    /// Returns a report with the result for each processed path, in the same order as the input.
+    /// Fails only if a dedicated thread pool was requested with `threads` but could not be
+    /// started, in which case no paths are updated.
+    ///
+    /// This method is only available if the `rayon` feature is enabled.
+    pub fn touch_all_par<I, P>(&self, paths: I) -> io::Result<Report>
+    where
+        I: IntoParallelIterator<Item = P>,
+        I::Iter: IndexedParallelIterator,
+        P: AsRef<Path>,
+    {
+        let batch = Batch::new(self);
+        let paths = paths
+            .into_par_iter()
+            .with_min_len(self.chunk_size.unwrap_or(1));
+        let touch = || -> Vec<_> {
+            paths
+                .map(|path| {
+                    if batch.is_stopped() {
+                        return None;
+                    }
+                    let path = path.as_ref();
+                    Some(ReportEntry::new(path.to_path_buf(), batch.apply(path)))
+                })
+                .collect()
+        };
+        let entries = match self.threads {
+            Some(n) => ThreadPoolBuilder::new()
+                .num_threads(n)
+                .build()
+                .map_err(io::Error::other)?
+                .install(touch),
+            None => touch(),
+        };
+        batch.extend(entries.into_iter().flatten());
+        Ok(batch.finish())
+    }
+
+    #[cfg(feature = "tokio")]
+    #[inline]
+    /// Updates the timestamps for every path in a directory tree without blocking an
+    /// asynchronous runtime, using the options given to a builder.
+    ///
+    /// This behaves like `touch_recursive`, running the whole operation on tokio's blocking
+    /// thread pool. Fails only if the runtime shuts down before the operation runs.
+    ///
+    /// The returned future must be polled within a tokio runtime. This method is only available
+    /// if the `tokio` feature is enabled.
+    pub fn touch_recursive_async<P: Into<PathBuf>>(
+        &self,
+        root: P,
+    ) -> impl Future<Output = io::Result<Report>> + Send {
+        let builder = self.clone();
+        let root = root.into();
+        future::spawn(move || Ok(builder.touch_recursive(root)))
+    }
+
+    #[inline]
+    /// Updates the timestamps for every path in a directory tree, using the options given to a
+    /// builder.
+    ///
+    /// Symbolic links are followed according to `follow_symlinks`: if they are not followed, they
+    /// are updated themselves and their targets are not visited. If they are followed, any
+    /// directories they point to are visited as well, except for directories that are already
+    /// being visited, which would otherwise lead to an endless loop.
+    ///
+    /// The contents of a directory are updated before the directory itself. If `root` does not
+    /// exist, it is treated like a single path given to `touch`.
+    ///
+    /// Returns a report with the result for each path, including any directories whose
+    /// contents could not be listed.
+    pub fn touch_recursive<P: AsRef<Path>>(&self, root: P) -> Report {
+        let root = root.as_ref();
+        let batch = Batch::new(self);
+        if fs::symlink_metadata(root).is_err() {
+            let _ = batch.touch(root);
+            return batch.finish();
+        }
+        for item in Walk::new(root, self.follow_symlinks, self.junctions, &self.walk) {
+            let proceed = match item {
+                Ok(entry) => batch.touch(entry.path()),
+                Err((path, e)) => batch.fail(path, e),
+            };
+            if !proceed {
+                break;
+            }
+        }
+        batch.finish()
+    }
+
+    #[cfg(feature = "parallel")]
+    #[inline]
+    /// Updates the timestamps for every path in a directory tree using several threads, using the
+    /// options given to a builder.
+    ///
+    /// This behaves like `touch_recursive`, except that the tree is walked by one thread per
+    /// available core, which scales far better for trees containing many files. Directories are
+    /// still updated after their contents, but the report lists results in no particular order,
+    /// and `sorted` has no effect on the order in which paths are updated.
+    ///
+    /// This method is only available if the `parallel` feature is enabled.
+    pub fn touch_recursive_par<P: AsRef<Path>>(&self, root: P) -> Report {
+        let root = root.as_ref();
+        let batch = Batch::new(self);
+        if fs::symlink_metadata(root).is_err() {
+            let _ = batch.touch(root);
+            return batch.finish();
+        }
+        let tree = Tree::new(root, self.follow_symlinks, self.junctions, &self.walk);
+        parallel::touch_tree(
+            &tree,
+            &batch,
+            self.threads,
+            self.chunk_size,
+            self.queue_depth,
+        );
+        batch.finish()
+    }
+
+    /// Updates the timestamps for every entry of a directory, using the options given to a
+    /// builder.
+    ///
+    /// The directory is opened once, and each entry is updated relative to it, which avoids
+    /// resolving the full path of every entry in large flat directories. On Windows, where this
+    /// is not possible, the directory is kept open while its entries are updated by path.
+    ///
+    /// The directory itself and the contents of its subdirectories are not updated. Entries are
+    /// selected using `entry_types`, `extension`, `glob`, `exclude_glob`, `exclude_path` and
+    /// `skip_hidden`, with patterns matched against their names. Entries are never created, so
+    /// `creation_target` has no effect.
+    ///
+    /// Returns a report with the result for each selected entry, or a single failure for the
+    /// directory if it could not be opened or listed.
+    pub fn touch_dir_entries<P: AsRef<Path>>(&self, dir: P) -> Report {
+        let dir = dir.as_ref();
+        let batch = Batch::new(self);
+        let opened = DirHandle::open(dir)
+            .and_then(|handle| fs::read_dir(dir).map(|entries| (handle, entries)));
+        let (handle, entries) = match opened {
+            Ok(opened) => opened,
+            Err(e) => {
+                let _ = batch.fail(dir.to_path_buf(), e);
+                return batch.finish();
+            }
+        };
+        // An entry removed while the directory is being listed is not created again.
+        let existing = Builder {
+            creation_target: CreationTarget::None,
+            ..self.clone()
+        };
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    if batch.fail(dir.to_path_buf(), e) {
+                        continue;
+                    }
+                    break;
+                }
+            };
+            let path = entry.path();
+            let name = entry.file_name();
+            let metadata = match entry.metadata() {
+                // A dangling symbolic link is still updated, so fall back to the link itself.
+                Ok(ref m) if m.file_type().is_symlink() && self.follows(&path) => {
+                    fs::metadata(&path).unwrap_or_else(|_| m.clone())
+                }
+                Ok(m) => m,
+                Err(e) => {
+                    if batch.fail(path, e) {
+                        continue;
+                    }
+                    break;
+                }
+            };
+            if !self.walk.selects_entry(&path, Path::new(&name), &metadata) {
+                continue;
+            }
+            let touch_at = |times: &FileTimes| {
+                times.check()?;
+                existing::check(&existing)?;
+                strict::check(self)?;
+                self.touch_granular(&path, None, times, |_, times| {
+                    self.retrying(|| existing.touch_at_sys(&handle, Path::new(&name), times))
+                })
+            };
+            if !batch.touch_with(&path, touch_at) {
+                break;
+            }
+        }
+        batch.finish()
+    }
+
+    #[inline]
+    /// Updates paths in a batch grouped by their parent directory, relative to that directory.
+    ///
+    /// Parent directories are only created by `touch_encoded`, so paths whose parent directory
+    /// is missing are handed to it instead.
+    fn touch_grouped(&self, batch: &Batch, mut paths: Vec<PathBuf>) {
+        // The sort is stable, so paths in the same directory keep their order.
+        paths.sort_by(|a, b| a.parent().cmp(&b.parent()));
+        for group in paths.chunk_by(|a, b| a.parent() == b.parent()) {
+            let dir = match group[0].parent() {
+                Some(dir) if dir.as_os_str().is_empty() => Path::new("."),
+                Some(dir) => dir,
+                None => Path::new(""),
+            };
+            let handle = DirHandle::open(dir).ok();
+            for path in group {
+                let touched = match (handle.as_ref(), path.file_name()) {
+                    (Some(handle), Some(name)) => {
+                        let touch_at = |times: &FileTimes| {
+                            times.check()?;
+                            existing::check(self)?;
+                            strict::check(self)?;
+                            self.touch_granular(path, None, times, |_, times| {
+                                self.retrying(|| self.touch_at_sys(handle, Path::new(name), times))
+                            })
+                        };
+                        batch.touch_with(path, touch_at)
+                    }
+                    // Paths such as `/` or ending in `..` cannot be named relative to a directory.
+                    _ => batch.touch(path),
+                };
+                if !touched {
+                    return;
+                }
+            }
+        }
+    }
+
+    #[inline]
+    /// Updates the timestamps for a filesystem path, using already converted timestamps.
+    fn touch_with_times(&self, path: &Path, times: &FileTimes) -> io::Result<Outcome> {
+        match EncodedPath::new(path) {
+            Ok(raw) => self.touch_encoded(path, raw.as_raw(), times),
+            Err(e) => Err(error::context(Operation::SetTimes, path)(e)),
+        }
+    }
+
+    #[inline]
+    /// Updates the timestamps for a filesystem path, using an already converted path and
+    /// timestamps.
+    ///
+    /// Every error is given the path, together with the step that failed if it is known, or
+    /// `Operation::SetTimes` otherwise.
+    fn touch_encoded(
+        &self,
+        path: &Path,
+        raw: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_checked(path, raw, times)
+            .map_err(error::context(Operation::SetTimes, path))
+    }
+
+    /// Checks a builder's options, then updates the timestamps for a filesystem path as
+    /// `touch_encoded` does.
+    fn touch_checked(
+        &self,
+        path: &Path,
+        raw: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let backend = self.backend.as_deref();
+        // Nothing should be created for timestamps that cannot be stored.
+        match backend {
+            Some(backend) => backend.check(&NewTimes::from_builder(self))?,
+            None => times.check()?,
+        }
+        existing::check(self)?;
+        strict::check(self)?;
+        let created_dirs = match self.creation_target {
+            CreationTarget::File if self.create_parents => self.create_parent_dirs(path)?,
+            _ => Vec::new(),
+        };
+        let result = self.touch_granular(path, backend, times, |builder, times| match backend {
+            Some(backend) => {
+                let times = NewTimes::from_builder(builder);
+                self.retrying(|| self.touch_backend(backend, path, &times))
+            }
+            None => self.retrying(|| self.touch_sys(path, raw, times)),
+        });
+        if result.is_err() {
+            self.remove_created_dirs(&created_dirs);
+        }
+        result
+    }
+
+    /// Updates a path through a backend instead of the operating system, once its options have
+    /// been checked.
+    fn touch_backend(
+        &self,
+        backend: &dyn Backend,
+        path: &Path,
+        times: &NewTimes,
+    ) -> io::Result<Outcome> {
+        let follow = self.follow_symlinks;
+        let outcome = match backend.open(path, follow) {
+            Ok(()) => Outcome::Updated,
+            Err(ref e)
+                if e.kind() == io::ErrorKind::NotFound &&
+                    matches!(self.creation_target, CreationTarget::File) =>
+            {
+                // Symbolic links are only left dangling here if they are followed.
+                let dangling = follow && backend.open(path, false).is_ok();
+                if dangling && self.dangling_symlinks == DanglingSymlinks::Refuse {
+                    return Err(error::context(Operation::Create, path)(
+                        Failure::IsSymlink.into(),
+                    ));
+                }
+                match backend.create(path) {
+                    Ok(()) if dangling => Outcome::CreatedSymlinkTarget,
+                    Ok(()) => Outcome::Created,
+                    Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && !dangling => {
+                        Outcome::Updated
+                    }
+                    Err(e) => return Err(error::context(Operation::Create, path)(e)),
+                }
+            }
+            Err(e) => return Err(error::context(Operation::Open, path)(e)),
+        };
+        backend
+            .set_times(path, times, follow)
+            .map_err(error::context(Operation::SetTimes, path))?;
+        Ok(outcome)
+    }
+
+    /// Updates a path using already converted timestamps, compensating for coarse filesystems
+    /// according to the granularity mode.
+    ///
+    /// `touch` is given the builder whose timestamps to set, together with the same timestamps
+    /// converted for the operating system. If the path is updated through a backend, it is
+    /// assumed to store timestamps exactly, and they are read back through it.
+    fn touch_granular<F>(
+        &self,
+        path: &Path,
+        backend: Option<&dyn Backend>,
+        times: &FileTimes,
+        touch: F,
+    ) -> io::Result<Outcome>
+    where
+        F: Fn(&Self, &FileTimes) -> io::Result<Outcome>,
+    {
+        let granularity = || match backend {
+            Some(_) => Ok(Granularity::of(Filesystem::Other)),
+            None => granularity(path),
+        };
+        match self.granularity {
+            GranularityMode::Ignore => touch(self, times),
+            GranularityMode::Round => {
+                let g = granularity()?;
+                if g.is_native() {
+                    return touch(self, times);
+                }
+                let round = |kind, time| match time {
+                    Some(Timestamp::At(t)) => Some(Timestamp::At(g.round(kind, t))),
+                    t => t,
+                };
+                let mut rounded = self.clone();
+                rounded.accessed = round(TimestampKind::Accessed, self.accessed_time());
+                rounded.modified = round(TimestampKind::Modified, self.modified_time());
+                rounded.created = match self.created_time() {
+                    Some(Timestamp::At(t)) => Some(g.round(TimestampKind::Created, t)),
+                    _ => None,
+                };
+                touch(&rounded, &FileTimes::from_builder(&rounded))
+            }
+            GranularityMode::Verify => {
+                let outcome = touch(self, times)?;
+                granularity::verify(self, path, backend)?;
+                Ok(outcome)
+            }
+            GranularityMode::Exact => {
+                granularity::check_precision(self, &granularity()?)?;
+                let outcome = touch(self, times)?;
+                granularity::verify(self, path, backend)?;
+                Ok(outcome)
+            }
+        }
+    }
+
+    #[inline]
+    /// Runs an operation, retrying it according to the retry policy, if any.
+    fn retrying<T, F: FnMut() -> io::Result<T>>(&self, mut f: F) -> io::Result<T> {
+        match self.retry {
+            Some(ref policy) => policy.run(f),
+            None => f(),
+        }
+    }
+
+    /// Creates any missing parent directories of a path.
+    ///
+    /// Returns the directories that were created, outermost first.
+    fn create_parent_dirs(&self, path: &Path) -> io::Result<Vec<PathBuf>> {
+        let mut missing = Vec::new();
+        let mut parent = path.parent();
+        while let Some(dir) = parent {
+            let exists = match self.backend {
+                Some(ref backend) => backend.open(dir, false).is_ok(),
+                None => fs::symlink_metadata(dir).is_ok(),
+            };
+            if dir.as_os_str().is_empty() || exists {
+                break;
+            }
+            missing.push(dir);
+            parent = dir.parent();
+        }
+        let mut created = Vec::with_capacity(missing.len());
+        for dir in missing.into_iter().rev() {
+            let result = match self.backend {
+                Some(ref backend) => backend.create_dir(dir),
+                None => fs::create_dir(dir),
+            };
+            match result {
+                Ok(()) => created.push(dir.to_path_buf()),
+                Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists => (),
+                Err(e) => {
+                    self.remove_created_dirs(&created);
+                    return Err(error::context(Operation::Create, dir)(e));
+                }
+            }
+        }
+        Ok(created)
+    }
+
+    #[inline]
+    /// Returns the access timestamp to set, after clamping.
+    fn accessed_time(&self) -> Option<Timestamp> {
+        self.clamp(self.accessed)
+    }
+
+    #[inline]
+    /// Returns the modification timestamp to set, after clamping.
+    fn modified_time(&self) -> Option<Timestamp> {
+        self.clamp(self.modified)
+    }
+
+    #[inline]
+    /// Returns whether to follow a path if it is a symbolic link or junction.
+    fn follows(&self, path: &Path) -> bool {
+        self.junctions.follows(path, self.follow_symlinks)
+    }
+
+    #[inline]
+    /// Returns the creation timestamp to set, after clamping.
+    fn created_time(&self) -> Option<Timestamp> {
+        self.clamp(self.created.map(Timestamp::At))
+    }
+
+    #[cfg(feature = "change-time")]
+    #[inline]
+    /// Returns the change timestamp to set, after clamping.
+    fn changed_time(&self) -> Option<Timestamp> {
+        self.clamp(self.changed.map(Timestamp::At))
+    }
+
+    #[inline]
+    /// Clamps a timestamp to the latest timestamp that may be set, if any.
+    ///
+    /// The current time is left for the operating system to read unless it is already past the
+    /// latest timestamp.
+    fn clamp(&self, time: Option<Timestamp>) -> Option<Timestamp> {
+        match (time, self.latest) {
+            (Some(Timestamp::At(t)), Some(latest)) => Some(Timestamp::At(t.min(latest))),
+            (Some(Timestamp::Now), Some(latest)) if self.now() > latest => {
+                Some(Timestamp::At(latest))
+            }
+            (t, _) => t,
+        }
+    }
+
+    #[inline]
+    /// Returns the current time, from the coarse clock if one was asked for.
+    fn now(&self) -> FileTime {
+        if self.coarse_clock {
+            FileTime::now_coarse()
+        } else {
+            FileTime::now()
+        }
+    }
+
+    /// Removes directories created by `create_parent_dirs`, if rollback is enabled.
+    fn remove_created_dirs(&self, created: &[PathBuf]) {
+        if self.rollback_parents {
+            // Removal is best-effort; a directory that is no longer empty belongs to someone else.
+            for dir in created.iter().rev() {
+                let _ = match self.backend {
+                    Some(ref backend) => backend.remove_dir(dir),
+                    None => fs::remove_dir(dir),
+                };
+            }
+        }
+    }
+}
+
+impl Default for Builder {
+    #[inline]
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Default for CreationTarget {
+    #[inline]
+    fn default() -> Self {
+        CreationTarget::None
+    }
+}
+
+impl Default for ErrorPolicy {
+    #[inline]
+    fn default() -> Self {
+        ErrorPolicy::Continue
+    }
+}
+
+impl Junctions {
+    #[inline]
+    /// Returns whether to follow a path, given whether symbolic links are followed.
+    ///
+    /// The path is only checked for being a junction if that would change the answer.
+    pub(crate) fn follows(self, path: &Path, follow_symlinks: bool) -> bool {
+        match self {
+            Junctions::LikeSymlinks => follow_symlinks,
+            Junctions::Follow => follow_symlinks || sys::is_junction(path),
+            Junctions::NoFollow => follow_symlinks && !sys::is_junction(path),
+        }
+    }
+}
+
+impl ShareMode {
+    #[inline]
+    /// Returns a share mode that allows every kind of access.
+    pub fn all() -> Self {
+        Self {
+            read: true,
+            write: true,
+            delete: true,
+        }
+    }
+
+    #[inline]
+    /// Returns a share mode that allows no access at all.
+    pub fn none() -> Self {
+        Self {
+            read: false,
+            write: false,
+            delete: false,
+        }
+    }
+
+    #[inline]
+    /// Specifies whether other handles may read the path.
+    pub fn read(mut self, share: bool) -> Self {
+        self.read = share;
+        self
+    }
+
+    #[inline]
+    /// Specifies whether other handles may write to the path.
+    pub fn write(mut self, share: bool) -> Self {
+        self.write = share;
+        self
+    }
+
+    #[inline]
+    /// Specifies whether other handles may delete or rename the path.
+    pub fn delete(mut self, share: bool) -> Self {
+        self.delete = share;
+        self
+    }
+
+    #[inline]
+    /// Returns whether other handles may read the path.
+    pub fn shares_read(self) -> bool {
+        self.read
+    }
+
+    #[inline]
+    /// Returns whether other handles may write to the path.
+    pub fn shares_write(self) -> bool {
+        self.write
+    }
+
+    #[inline]
+    /// Returns whether other handles may delete or rename the path.
+    pub fn shares_delete(self) -> bool {
+        self.delete
+    }
+}
+
+impl Default for ShareMode {
+    #[inline]
+    fn default() -> Self {
+        ShareMode::all()
+    }
+}
+
+impl Default for Junctions {
+    #[inline]
+    fn default() -> Self {
+        Junctions::LikeSymlinks
+    }
+}
+
+impl Default for DanglingSymlinks {
+    #[inline]
+    fn default() -> Self {
+        DanglingSymlinks::CreateTarget
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Backend, Builder, CancellationToken, CreationTarget, DanglingSymlinks, Dir, EntryTypes,
+         Error, ErrorPolicy, Failure, FileTime, GranularityMode, Native, NewTimes,
+         NothingToDo, Operation, Outcome, PathCache, PreparedPath, PreparedTimes, Progress,
+         ProgressSink, RetryPolicy, ShareMode, TimestampKind, TimestampMismatch, Timestamps,
+         TouchService, UnsupportedTimestamp, WouldOpen};
+    #[cfg(any(windows, target_os = "linux"))]
+    use Junctions;
+    #[cfg(any(feature = "parallel", feature = "rayon"))]
+    use Report;
+    use convert;
+    use proptest::prelude::{any, Strategy};
+    use std::collections::HashMap;
+    #[cfg(any(feature = "parallel", feature = "rayon"))]
+    use std::collections::HashSet;
+    use std::env;
+    #[cfg(unix)]
+    use std::ffi::CString;
+    use std::fs::{self, OpenOptions};
+    use std::io;
+    #[cfg(unix)]
+    use std::os::unix;
+    #[cfg(unix)]
+    use std::os::unix::ffi::OsStrExt;
+    #[cfg(windows)]
+    use std::os::windows;
+    #[cfg(windows)]
+    use std::os::windows::ffi::OsStrExt;
+    use std::path::{Path, PathBuf};
+    #[cfg(windows)]
+    use std::path::{Component, Prefix};
+    use std::sync::{Arc, Mutex};
+    #[cfg(windows)]
+    use std::thread;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use tempdir::TempDir;
+    #[cfg(feature = "chrono")]
+    use chrono::{FixedOffset, TimeZone, Utc};
+    #[cfg(feature = "time")]
+    use time::{OffsetDateTime, UtcOffset};
+
+    struct TestHelper(TempDir);
+
+    #[derive(Debug, Default)]
+    /// A backend that keeps timestamps in memory, storing modification timestamps to the second.
+    struct MemoryBackend(Mutex<HashMap<PathBuf, Timestamps>>);
+
+    impl Backend for MemoryBackend {
+        fn open(&self, path: &Path, _: bool) -> io::Result<()> {
+            match self.0.lock().unwrap().get(path) {
+                Some(_) => Ok(()),
+                None => Err(io::ErrorKind::NotFound.into()),
+            }
+        }
+
+        fn create(&self, path: &Path) -> io::Result<()> {
+            let epoch = FileTime::from_unix_time(0, 0);
+            let times = Timestamps::new(epoch, epoch, epoch, Some(epoch));
+            let _ = self.0.lock().unwrap().insert(path.to_path_buf(), times);
+            Ok(())
+        }
+
+        fn create_dir(&self, path: &Path) -> io::Result<()> {
+            self.create(path)
+        }
+
+        fn remove_dir(&self, path: &Path) -> io::Result<()> {
+            let _ = self.0.lock().unwrap().remove(path);
+            Ok(())
+        }
+
+        fn set_times(&self, path: &Path, times: &NewTimes, _: bool) -> io::Result<()> {
+            let mut paths = self.0.lock().unwrap();
+            let old = paths[path];
+            let modified = times
+                .modified()
+                .map(|t| FileTime::from_unix_time(t.unix_seconds(), 0));
+            let new = Timestamps::new(
+                times.accessed().unwrap_or(old.accessed()),
+                modified.unwrap_or(old.modified()),
+                old.changed(),
+                times.created().or(old.created()),
+            );
+            let _ = paths.insert(path.to_path_buf(), new);
+            Ok(())
+        }
+
+        fn read_times(&self, path: &Path, _: bool) -> io::Result<Timestamps> {
+            Ok(self.0.lock().unwrap()[path])
+        }
+    }
+
+    fn file_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
+        prefix.as_ref().join("file.txt")
+    }
+
+    fn directory_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
+        prefix.as_ref().join("directory")
+    }
+
+    fn symlink_file_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
+        prefix.as_ref().join("symlink-file.txt")
+    }
+
+    fn symlink_directory_path<P: AsRef<Path>>(prefix: P) -> PathBuf {
+        prefix.as_ref().join("symlink-directory")
+    }
+
+    fn times<P: AsRef<Path>>(path: P) -> (SystemTime, SystemTime) {
+        let metadata = match fs::metadata(&path) {
+            Ok(m) => m,
+            Err(e) => panic!(
+                "could not obtain metadata for {}: {}",
+                path.as_ref().display(),
+                e
+            ),
+        };
+        (
+            metadata
+                .accessed()
+                .expect("atime not supported on this platform"),
+            metadata
+                .modified()
+                .expect("mtime not supported on this platform"),
+        )
+    }
+
+    fn symlink_times<P: AsRef<Path>>(path: P) -> (SystemTime, SystemTime) {
+        let metadata = match fs::symlink_metadata(&path) {
+            Ok(m) => m,
+            Err(e) => panic!(
+                "could not obtain metadata for {}: {}",
+                path.as_ref().display(),
+                e
+            ),
+        };
+        (
+            metadata
+                .accessed()
+                .expect("atime not supported on this platform"),
+            metadata
+                .modified()
+                .expect("mtime not supported on this platform"),
+        )
+    }
+
+    fn touch<P: AsRef<Path>>(builder: &Builder, path: P) {
+        let _ = touch_outcome(builder, path);
+    }
+
+    fn touch_outcome<P: AsRef<Path>>(builder: &Builder, path: P) -> Outcome {
+        match builder.touch(path) {
+            Ok(outcome) => outcome,
+            Err(e) => panic!("`Builder::touch` failed: {}", e),
+        }
+    }
+
+    #[cfg(any(feature = "parallel", feature = "rayon"))]
+    /// Returns how many threads updated paths while a builder ran, and the report it returned.
+    fn threads_used<F: FnOnce(&Builder) -> Report>(builder: &mut Builder, f: F) -> (usize, Report) {
+        let threads = Arc::new(Mutex::new(HashSet::new()));
+        let sink_threads = Arc::clone(&threads);
+        // Progress is reported by the thread that updated each path.
+        let sink = ProgressSink::new(Duration::from_secs(0), move |_: &Progress| {
+            let mut threads = sink_threads.lock().expect("could not lock threads");
+            let _ = threads.insert(::std::thread::current().id());
+        });
+        let report = f(builder.progress(Some(sink)));
+        let _ = builder.progress(None);
+        let count = threads.lock().expect("could not lock threads").len();
+        (count, report)
+    }
+
+    impl TestHelper {
+        pub fn new() -> TestHelper {
+            match TempDir::new("nudge-rs_test") {
+                Ok(td) => TestHelper(td),
+                Err(e) => panic!("could not create temporary directory: {}", e),
+            }
+        }
+
+        pub fn create_top_level_file(&self) -> PathBuf {
+            let path = file_path(self.0.path());
+            match OpenOptions::new().write(true).create_new(true).open(&path) {
+                Ok(_) => path,
+                Err(e) => panic!("could not create top-level file: {}", e),
+            }
+        }
+
+        pub fn create_top_level_directory(&self) -> PathBuf {
+            let path = directory_path(self.0.path());
+            match fs::create_dir(&path) {
+                Ok(_) => path,
+                Err(e) => panic!("could not create top-level directory: {}", e),
+            }
+        }
+
+        #[cfg(unix)]
+        pub fn create_top_level_symlink_file(&self) -> PathBuf {
+            let src = file_path(self.0.path());
+            let dst = symlink_file_path(self.0.path());
+            if let Err(e) = unix::fs::symlink(src, &dst) {
+                panic!("could not create file symbolic link: {}", e);
+            }
+            // Under `relatime`, resolving a link updates its access timestamp unless that is
+            // already later than its other timestamps, which would make comparing them flaky.
+            let mut builder = Builder::new();
+            let _ = builder.accessed(Some(SystemTime::now() + Duration::from_secs(3_600)));
+            touch(&builder, &dst);
+            dst
+        }
+
+        #[cfg(unix)]
+        pub fn create_top_level_symlink_directory(&self) -> PathBuf {
+            let src = directory_path(self.0.path());
+            let dst = symlink_directory_path(self.0.path());
+            match unix::fs::symlink(src, &dst) {
+                Ok(_) => dst,
+                Err(e) => panic!("could not create directory symbolic link: {}", e),
+            }
+        }
+
+        #[cfg(windows)]
+        pub fn create_top_level_symlink_file(&self) -> PathBuf {
+            let src = file_path(self.0.path());
+            let dst = symlink_file_path(self.0.path());
+            match windows::fs::symlink_file(src, &dst) {
+                Ok(_) => dst,
+                Err(e) => panic!("could not create file symbolic link: {}", e),
+            }
+        }
+
+        #[cfg(windows)]
+        pub fn create_top_level_symlink_directory(&self) -> PathBuf {
+            let src = directory_path(self.0.path());
+            let dst = symlink_directory_path(self.0.path());
+            match windows::fs::symlink_dir(src, &dst) {
+                Ok(_) => dst,
+                Err(e) => panic!("could not create directory symbolic link: {}", e),
+            }
+        }
+
+        pub fn nonexisting_file_path(&self) -> PathBuf {
+            self.0.path().join("nonexisting-file-path.txt")
+        }
+    }
+
+    #[test]
+    fn existing_file_noupdate() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let old_times = times(&file_path);
+        let builder = Builder::new();
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &file_path));
+        assert_eq!(old_times, times(file_path));
+    }
+
+    #[test]
+    fn existing_file_atime() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let (_, old_mtime) = times(&file_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now));
+        touch(&builder, &file_path);
+        assert_eq!((now, old_mtime), times(file_path));
+    }
+
+    #[test]
+    fn existing_file_mtime() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let (old_atime, _) = times(&file_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(now));
+        touch(&builder, &file_path);
+        assert_eq!((old_atime, now), times(file_path));
+    }
+
+    #[test]
+    fn existing_file_times() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        touch(&builder, &file_path);
+        assert_eq!((now, now), times(file_path));
+    }
+
+    #[test]
+    fn existing_directory_noupdate() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let old_times = times(&dir_path);
+        let builder = Builder::new();
+        touch(&builder, &dir_path);
+        assert_eq!(old_times, times(dir_path));
+    }
+
+    #[test]
+    fn existing_directory_atime() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let (_, old_mtime) = times(&dir_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now));
+        touch(&builder, &dir_path);
+        assert_eq!((now, old_mtime), times(dir_path));
+    }
+
+    #[test]
+    fn existing_directory_mtime() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let (old_atime, _) = times(&dir_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(now));
+        touch(&builder, &dir_path);
+        assert_eq!((old_atime, now), times(dir_path));
+    }
+
+    #[test]
+    fn existing_directory_times() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        touch(&builder, &dir_path);
+        assert_eq!((now, now), times(dir_path));
+    }
+
+    #[test]
+    fn follow_symlink() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let sym_path = helper.create_top_level_symlink_file();
+        let sym_old_times = symlink_times(&sym_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .follow_symlinks(true);
+        touch(&builder, &sym_path);
+        assert_eq!((now, now), times(file_path));
+        assert_eq!(sym_old_times, symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_file_noupdate() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_file();
+        let old_times = symlink_times(&sym_path);
+        let builder = Builder::new();
+        touch(&builder, &sym_path);
+        assert_eq!(old_times, symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_file_atime() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_file();
+        let (_, old_mtime) = symlink_times(&sym_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((now, old_mtime), symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_file_mtime() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_file();
+        let (old_atime, _) = symlink_times(&sym_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((old_atime, now), symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_file_times() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_file();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((now, now), symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_directory_noupdate() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_directory();
+        let old_times = symlink_times(&sym_path);
+        let builder = Builder::new();
+        touch(&builder, &sym_path);
+        assert_eq!(old_times, symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_directory_atime() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_directory();
+        let (_, old_mtime) = symlink_times(&sym_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((now, old_mtime), symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_directory_mtime() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_directory();
+        let (old_atime, _) = symlink_times(&sym_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((old_atime, now), symlink_times(sym_path));
+    }
+
+    #[test]
+    fn symlink_directory_times() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_directory();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        touch(&builder, &sym_path);
+        assert_eq!((now, now), symlink_times(sym_path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn junctions() {
+        use std::process::Command;
+
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let junction_path = helper.0.path().join("junction");
+        let status = Command::new("cmd")
+            .args(&["/C", "mklink", "/J"])
+            .arg(&junction_path)
+            .arg(&dir_path)
+            .status()
+            .expect("could not run mklink");
+        assert!(status.success(), "could not create junction");
+        let then = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(then)).modified(Some(then));
+        touch(&builder, &dir_path);
+        let now = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .follow_symlinks(true)
+            .junctions(Junctions::NoFollow);
+        touch(&builder, &junction_path);
+        assert_eq!((then, then), times(&dir_path));
+        assert_eq!((now, now), symlink_times(&junction_path));
+        let _ = builder
+            .follow_symlinks(false)
+            .junctions(Junctions::Follow);
+        touch(&builder, &junction_path);
+        assert_eq!((now, now), times(dir_path));
+    }
+
+    #[test]
+    fn existing_file_sync() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .sync_file(true)
+            .sync_parent_dir(true);
+        touch(&builder, &file_path);
+        assert_eq!((now, now), times(file_path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn backup_privileges() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .backup_privileges(true);
+        match builder.touch(&file_path) {
+            Ok(outcome) => {
+                assert_eq!(Outcome::Updated, outcome);
+                assert_eq!((now, now), times(file_path));
+            }
+            // Only administrators and backup operators may enable the privileges, which is
+            // reported as `ERROR_NOT_ALL_ASSIGNED`.
+            Err(e) => assert_eq!(Some(1300), e.raw_os_error()),
+        }
+    }
+
+    #[test]
+    fn share_mode() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let _open = fs::File::open(&file_path).unwrap();
+        let mut builder = Builder::new();
+        let _ = builder.share_mode(ShareMode::all().read(false));
+        let result = builder.touch(&file_path);
+        // Another handle is reading the file, so refusing to share reads is a sharing violation.
+        #[cfg(windows)]
+        {
+            let err = result.unwrap_err();
+            let context = Error::of(&err).expect("no context");
+            assert_eq!(Operation::Open, context.operation());
+            assert_eq!(Some(32), context.io_error().raw_os_error());
+        }
+        #[cfg(not(windows))]
+        assert_eq!(Outcome::Updated, result.unwrap());
+        let _ = builder.share_mode(ShareMode::default());
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+    }
+
+    #[test]
+    fn retry() {
+        let helper = TestHelper::new();
+        let mut builder = Builder::new();
+        let _ = builder
+            .creation_target(CreationTarget::None)
+            .retry(Some(RetryPolicy::new(3, Duration::from_secs(3600))));
+        // A missing path is not a transient failure, so it fails without waiting.
+        let start = Instant::now();
+        let e = builder
+            .touch(helper.nonexisting_file_path())
+            .expect_err("updated missing file");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+        assert!(start.elapsed() < Duration::from_secs(60));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn retry_sharing_violation() {
+        use std::os::windows::fs::OpenOptionsExt;
+
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let locked = OpenOptions::new()
+            .read(true)
+            .share_mode(0)
+            .open(&file_path)
+            .expect("could not lock file");
+        let unlock = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(locked);
+        });
+        let mut builder = Builder::new();
+        let _ = builder.retry(Some(RetryPolicy::new(10, Duration::from_millis(10))));
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        unlock.join().unwrap();
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn clear_readonly() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let set_readonly = |readonly| {
+            let mut permissions = fs::metadata(&file_path).unwrap().permissions();
+            permissions.set_readonly(readonly);
+            fs::set_permissions(&file_path, permissions).unwrap();
+        };
+        set_readonly(true);
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .sync_file(true);
+        // Flushing requires opening the file for writing, which its attribute forbids.
+        let e = builder.touch(&file_path).expect_err("opened read-only file for writing");
+        assert_eq!(io::ErrorKind::PermissionDenied, e.kind());
+        let _ = builder.clear_readonly(true);
+        assert_eq!(Outcome::UpdatedReadOnly, builder.touch(&file_path).unwrap());
+        assert_eq!((time, time), times(&file_path));
+        assert!(fs::metadata(&file_path).unwrap().permissions().readonly());
+        set_readonly(false);
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn preserve_accessed() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let accessed = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let modified = UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(accessed)).modified(Some(accessed));
+        touch(&builder, &file_path);
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(modified)).preserve_accessed(true).sync_file(true);
+        touch(&builder, &file_path);
+        assert_eq!((accessed, modified), times(&file_path));
+    }
+
+    #[test]
+    fn file_time_system_time() {
+        let after = UNIX_EPOCH + Duration::new(1_500_000_000, 250_000_000);
+        let before = UNIX_EPOCH - Duration::new(1, 250_000_000);
+        assert_eq!(FileTime::from_unix_time(1_500_000_000, 250_000_000), after.into());
+        assert_eq!(FileTime::from_unix_time(-2, 750_000_000), before.into());
+        assert_eq!(FileTime::from_unix_time(-1, 0), (UNIX_EPOCH - Duration::from_secs(1)).into());
+        for time in &[after, before, UNIX_EPOCH] {
+            assert_eq!(Some(*time), FileTime::from(*time).to_system_time());
+        }
+    }
+
+    #[test]
+    fn file_time_raw() {
+        let before = FileTime::from_unix_time(-2, 750_000_000);
+        assert_eq!(-1_250_000_000, before.unix_nanos());
+        assert_eq!(before, FileTime::from_unix_nanos(-1_250_000_000));
+        let max = FileTime::from_unix_time(i64::MAX, 999_999_999);
+        assert_eq!(max, FileTime::from_unix_nanos(i128::MAX));
+        #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+        {
+            #[cfg(not(feature = "rustix"))]
+            let ts = {
+                use libc::timespec;
+                use std::convert::TryFrom;
+
+                timespec::try_from(before).expect("timestamp out of range")
+            };
+            #[cfg(feature = "rustix")]
+            let ts = rustix::fs::Timespec::from(before);
+            assert_eq!((-2, 750_000_000), (ts.tv_sec, ts.tv_nsec));
+            assert_eq!(before, FileTime::from(ts));
+        }
+        #[cfg(windows)]
+        {
+            use std::convert::TryFrom;
+            use windows_sys::Win32::Foundation::{FILETIME, SYSTEMTIME};
+
+            let ft = FILETIME::try_from(before).expect("timestamp out of range");
+            assert_eq!(before, FileTime::from(ft));
+            let st = SYSTEMTIME::try_from(before).expect("timestamp out of range");
+            assert_eq!(
+                (1969, 12, 31, 58, 750),
+                (st.wYear, st.wMonth, st.wDay, st.wSecond, st.wMilliseconds)
+            );
+            assert_eq!(before, FileTime::try_from(st).expect("invalid SYSTEMTIME"));
+            assert!(FILETIME::try_from(FileTime::from_unix_time(-11_644_473_600, 0)).is_err());
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn read_times_without_statx() {
+        use sys::{read_times, stat_times};
+
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let symlink_path = helper.create_top_level_symlink_file();
+        // Apart from the creation timestamp, `stat` reports the same timestamps as `statx`.
+        for &(path, follow_symlinks) in &[(&file_path, true), (&symlink_path, false)] {
+            let statx = read_times(path, follow_symlinks).expect("could not read timestamps");
+            let stat = stat_times(path, follow_symlinks).expect("could not read timestamps");
+            assert_eq!(
+                (statx.accessed(), statx.modified(), statx.changed(), None),
+                (stat.accessed(), stat.modified(), stat.changed(), stat.created())
+            );
+        }
+        let err = stat_times(&helper.nonexisting_file_path(), true).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+    }
+
+    #[cfg(target_os = "linux")]
+    #[allow(unsafe_code)]
+    #[test]
+    fn retry_interrupted() {
+        use sys::retry_interrupted;
+
+        // Calls that fail with `EINTR` are retried until they succeed or fail with another error.
+        for &fail_with in &[None, Some(io::ErrorKind::WouldBlock)] {
+            let mut calls = 0;
+            #[cfg(not(feature = "rustix"))]
+            let outcome = retry_interrupted(|| {
+                calls += 1;
+                let errno = match (calls, fail_with) {
+                    (1..=2, _) => libc::EINTR,
+                    (_, Some(_)) => libc::EAGAIN,
+                    (_, None) => return 7,
+                };
+                unsafe { *libc::__errno_location() = errno };
+                -1
+            });
+            #[cfg(feature = "rustix")]
+            let outcome = retry_interrupted(|| {
+                calls += 1;
+                match (calls, fail_with) {
+                    (1..=2, _) => Err(rustix::io::Errno::INTR),
+                    (_, Some(_)) => Err(rustix::io::Errno::AGAIN),
+                    (_, None) => Ok(7),
+                }
+            }).map_err(io::Error::from);
+            assert_eq!(3, calls);
+            match outcome {
+                Ok(n) => assert_eq!((None, 7), (fail_with, n)),
+                Err(e) => assert_eq!(fail_with, Some(e.kind())),
+            }
+        }
+    }
+
+    #[cfg(target_os = "linux")]
+    #[test]
+    fn close() {
+        use std::os::unix::io::IntoRawFd;
+        use sys;
+
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let fd = fs::File::open(&file_path).expect("failed to open file").into_raw_fd();
+        sys::close(fd).expect("failed to close file");
+        // Errors other than `EINTR` are still returned. A descriptor beyond any limit is used
+        // rather than closing `fd` twice, since another thread may have been given it since.
+        let e = sys::close(i32::MAX).expect_err("closed an invalid descriptor");
+        assert_eq!(Some(libc::EBADF), e.raw_os_error());
+    }
+
+    #[cfg(all(target_os = "linux", feature = "rustix"))]
+    #[test]
+    fn rustix_backend() {
+        use std::os::unix::fs::MetadataExt;
+        use sys;
+
+        let helper = TestHelper::new();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let symlink_path = helper.create_top_level_symlink_file();
+        let accessed = FileTime::from_unix_time(-2, 750_000_000);
+        let modified = FileTime::from_unix_time(1_500_000_000, 123_456_789);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(accessed))
+            .modified(Some(modified))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &nonexisting_path));
+        let metadata = fs::metadata(&nonexisting_path).expect("could not obtain metadata");
+        assert_eq!((-2, 750_000_000), (metadata.atime(), metadata.atime_nsec()));
+        assert_eq!((1_500_000_000, 123_456_789), (metadata.mtime(), metadata.mtime_nsec()));
+        let times = sys::read_times(&nonexisting_path, true).expect("could not read timestamps");
+        assert_eq!((accessed, modified), (times.accessed(), times.modified()));
+        // The link itself is updated, and the missing file it points to is not created.
+        let _ = builder.follow_symlinks(false);
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &symlink_path));
+        let metadata = fs::symlink_metadata(&symlink_path).expect("could not obtain metadata");
+        assert_eq!((1_500_000_000, 123_456_789), (metadata.mtime(), metadata.mtime_nsec()));
+        assert!(fs::metadata(&symlink_path).is_err());
+    }
+
+    #[test]
+    fn convert_windows_ticks() {
+        let epoch = convert::WINDOWS_EPOCH_OFFSET * 10_000_000;
+        assert_eq!(Some(epoch), convert::to_windows_ticks(FileTime::from_unix_time(0, 0)));
+        assert_eq!(FileTime::from_unix_time(0, 0), convert::from_windows_ticks(epoch));
+        // Timestamps are rounded down to the previous tick, even before the Unix epoch.
+        let before = FileTime::from_unix_time(-1, 999_999_999);
+        assert_eq!(Some(epoch - 1), convert::to_windows_ticks(before));
+        assert_eq!(
+            FileTime::from_unix_time(-1, 999_999_900),
+            convert::from_windows_ticks(epoch - 1)
+        );
+        let windows_epoch = FileTime::from_unix_time(-convert::WINDOWS_EPOCH_OFFSET, 0);
+        assert_eq!(Some(0), convert::to_windows_ticks(windows_epoch));
+        let too_early = FileTime::from_unix_time(-convert::WINDOWS_EPOCH_OFFSET - 1, 999_999_999);
+        assert_eq!(None, convert::to_windows_ticks(too_early));
+        assert_eq!(None, convert::to_windows_ticks(FileTime::from_unix_time(i64::MAX, 0)));
+        let pre_epoch = FileTime::from_unix_nanos(-1_250_000_000);
+        assert_eq!((-2, 750_000_000), convert::to_timespec(pre_epoch));
+        assert_eq!(
+            FileTime::from_unix_time(-2, 750_000_000),
+            convert::from_timespec(-1, -250_000_000)
+        );
+    }
+
+    fn file_times() -> impl Strategy<Value = FileTime> {
+        (any::<i64>(), 0..1_000_000_000u32).prop_map(|(s, ns)| FileTime::from_unix_time(s, ns))
+    }
+
+    proptest! {
+        #[test]
+        fn convert_round_trips(time in file_times(), ticks in 0..i64::MAX) {
+            prop_assert_eq!(time, FileTime::from_unix_nanos(time.unix_nanos()));
+            let (seconds, nanoseconds) = convert::to_timespec(time);
+            prop_assert_eq!(time, convert::from_timespec(seconds, nanoseconds));
+            if let Some(system_time) = time.to_system_time() {
+                prop_assert_eq!(time, FileTime::from(system_time));
+            }
+            let rounded = convert::to_windows_ticks(time).map(convert::from_windows_ticks);
+            if let Some(rounded) = rounded {
+                prop_assert!(rounded <= time);
+                prop_assert!(time.unix_nanos() - rounded.unix_nanos() < 100);
+            }
+            let exact = convert::from_windows_ticks(ticks);
+            prop_assert_eq!(Some(ticks), convert::to_windows_ticks(exact));
+        }
+
+        #[test]
+        fn convert_windows_ticks_monotonic(a in file_times(), b in file_times()) {
+            let (earlier, later) = if a <= b { (a, b) } else { (b, a) };
+            let ticks = (convert::to_windows_ticks(earlier), convert::to_windows_ticks(later));
+            if let (Some(earlier), Some(later)) = ticks {
+                prop_assert!(earlier <= later);
+            }
+        }
+    }
+
+    #[test]
+    fn compare_with_tolerance() {
+        use {is_newer_than, is_older_than, is_same_time, FAT_RESOLUTION, NTFS_RESOLUTION};
+
+        let a = FileTime::from_unix_time(1_498_910_401, 999_999_999);
+        let b = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        assert!(is_newer_than(a, b, NTFS_RESOLUTION));
+        assert!(!is_newer_than(a, b, FAT_RESOLUTION));
+        assert!(is_older_than(b, a, Duration::from_secs(1)));
+        assert!(!is_older_than(a, b, Duration::from_secs(0)));
+        assert!(is_same_time(a, b, FAT_RESOLUTION));
+        assert!(is_same_time(b, a, FAT_RESOLUTION));
+        assert!(!is_same_time(a, b, Duration::from_secs(1)));
+        assert!(is_same_time(a, a, Duration::from_secs(0)));
+    }
+
+    #[cfg(feature = "chrono")]
+    #[test]
+    fn chrono_date_time() {
+        let helper = TestHelper::new();
+        let path = helper.create_top_level_file();
+        let accessed = Utc
+            .with_ymd_and_hms(1969, 7, 20, 20, 17, 40)
+            .single()
+            .expect("invalid date");
+        let modified = FixedOffset::east_opt(3600)
+            .and_then(|tz| tz.with_ymd_and_hms(2017, 7, 1, 12, 0, 0).single())
+            .expect("invalid date");
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(accessed)).modified(Some(modified));
+        touch(&builder, &path);
+        assert_eq!(
+            (
+                UNIX_EPOCH - Duration::from_secs(14_182_940),
+                UNIX_EPOCH + Duration::from_secs(1_498_906_800)
+            ),
+            times(&path)
+        );
+    }
+
+    #[cfg(feature = "time")]
+    #[test]
+    fn time_offset_date_time() {
+        let helper = TestHelper::new();
+        let path = helper.create_top_level_file();
+        let accessed = OffsetDateTime::from_unix_timestamp_nanos(-14_182_939_500_000_000)
+            .expect("invalid date");
+        let modified = OffsetDateTime::from_unix_timestamp(1_498_906_800)
+            .expect("invalid date")
+            .to_offset(UtcOffset::from_hms(1, 0, 0).expect("invalid offset"));
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(accessed)).modified(Some(modified));
+        touch(&builder, &path);
+        assert_eq!(
+            (
+                UNIX_EPOCH - Duration::new(14_182_939, 500_000_000),
+                UNIX_EPOCH + Duration::from_secs(1_498_906_800)
+            ),
+            times(&path)
+        );
+        let file_time = FileTime::from(accessed);
+        assert_eq!(FileTime::from_unix_time(-14_182_940, 500_000_000), file_time);
+        assert_eq!(Some(accessed), file_time.to_offset_date_time());
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn parse_relative() {
+        use parse;
+
+        // 2017-07-01 12:00:00 UTC
+        let now = FileTime::from_unix_time(1_498_910_400, 500);
+        let expect = |s: &str, seconds: i64| {
+            let parsed = parse::relative(s, now).expect("could not parse time");
+            assert_eq!(FileTime::from_unix_time(seconds, 500), parsed, "{}", s);
+        };
+        expect("now", 1_498_910_400);
+        expect("2 hours ago", 1_498_903_200);
+        expect("1 hour 30 minutes ago", 1_498_905_000);
+        expect("yesterday", 1_498_824_000);
+        expect("+30m", 1_498_912_200);
+        expect("-1h30m", 1_498_905_000);
+        expect("in 2 days", 1_499_083_200);
+        expect("next week", 1_499_515_200);
+        expect("last month", 1_496_318_400);
+        expect("1 year ago", 1_467_374_400);
+        let invalid = [
+            "",
+            "2",
+            "soon",
+            "3 parsecs ago",
+            "99999999999999999999 s",
+            // Calendar arithmetic on dates this far away would overflow.
+            "700000000000000000 months",
+            "99999999999999999 years",
+            "99999999999999999 years ago",
+        ];
+        for s in &invalid {
+            let e = parse::relative(s, now).expect_err("parsed invalid time");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn parse_gnu_date() {
+        use parse;
+
+        // 2017-07-01 12:00:00 UTC
+        let now = FileTime::from_unix_time(1_498_910_400, 500);
+        let expect = |s: &str, seconds: i64, nanoseconds: u32| {
+            let parsed = parse::gnu_date(s, now).expect("could not parse time");
+            assert_eq!(FileTime::from_unix_time(seconds, nanoseconds), parsed, "{}", s);
+        };
+        expect("2017-07-01", 1_498_867_200, 0);
+        expect("2017-07-01 12:00:00", 1_498_910_400, 0);
+        expect("2017-07-01T12:00:00.25Z", 1_498_910_400, 250_000_000);
+        expect("2017-07-01T14:00:00+02:00", 1_498_910_400, 0);
+        expect("2017-07-01 07:00 -0500", 1_498_910_400, 0);
+        expect("1969-12-31 23:59:59", -1, 0);
+        expect("2016-02-29 +1 year", 1_488_326_400, 0);
+        expect("tomorrow 09:00", 1_498_986_000, 0);
+        expect("2 hours ago", 1_498_903_200, 500);
+        for s in &["2017-02-29", "2017-07-01 25:00", "2017-07-01 2017-07-02", "12:00 soon"] {
+            let e = parse::gnu_date(s, now).expect_err("parsed invalid time");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn parse_posix_stamp() {
+        use parse;
+
+        // 2017-07-01 12:00:00 UTC
+        let now = FileTime::from_unix_time(1_498_910_400, 500);
+        let expect = |s: &str, seconds: i64| {
+            let parsed = parse::posix_stamp(s, now).expect("could not parse time");
+            assert_eq!(FileTime::from_unix_time(seconds, 0), parsed, "{}", s);
+        };
+        expect("07011200", 1_498_910_400);
+        expect("1707011200.30", 1_498_910_430);
+        expect("201707011200", 1_498_910_400);
+        expect("6912312359.59", -1);
+        expect("6801010000", 3_092_601_600);
+        expect("12312359.60", 1_514_764_800);
+        for s in &["0701120", "1707011200.3", "17070112x0", "1702291200", "1707012400", ""] {
+            let e = parse::posix_stamp(s, now).expect_err("parsed invalid time");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn parse_epoch() {
+        use parse;
+
+        let expect = |s: &str, seconds: i64, nanoseconds: u32| {
+            let parsed = parse::epoch(s).expect("could not parse time");
+            assert_eq!(FileTime::from_unix_time(seconds, nanoseconds), parsed, "{}", s);
+        };
+        expect("@0", 0, 0);
+        expect("@1498910400", 1_498_910_400, 0);
+        expect("@1498910400.123456789", 1_498_910_400, 123_456_789);
+        expect("@1.5", 1, 500_000_000);
+        expect("@-1", -1, 0);
+        expect("@-1.5", -2, 500_000_000);
+        expect("@-14182940.0000000001", -14_182_940, 0);
+        let now = FileTime::from_unix_time(0, 0);
+        assert_eq!(
+            FileTime::from_unix_time(1_498_996_800, 0),
+            parse::gnu_date("@1498910400 +1 day", now).expect("could not parse time")
+        );
+        for s in &["", "@", "1", "@1.", "@1.x", "@--1", "@99999999999999999999"] {
+            let e = parse::epoch(s).expect_err("parsed invalid time");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+    }
+
+    #[cfg(feature = "parse")]
+    #[test]
+    fn parse_zone() {
+        use parse::{self, Zone};
+
+        let now = FileTime::from_unix_time(1_498_910_400, 0);
+        let zone = |name: &str| Zone::from_name(name).expect("unknown time zone");
+        assert_eq!(Zone::Utc, zone("UTC"));
+        assert_eq!(Zone::Fixed(-5 * 3600), zone("EST5"));
+        assert_eq!(Zone::Fixed(5 * 3600 + 1800), zone("<+0530>-5:30"));
+        assert_eq!(Zone::Fixed(3600), zone(":+01:00"));
+        for name in &["", "E5", "EST5EDT,M3.2.0,M11.1.0", "Nowhere/Special"] {
+            let e = Zone::from_name(name).expect_err("accepted unknown time zone");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+        let gnu = |s: &str, zone: Zone| parse::gnu_date_in(s, now, zone).expect("could not parse");
+        assert_eq!(
+            FileTime::from_unix_time(1_498_928_400, 0),
+            gnu("2017-07-01 12:00", zone("EST5"))
+        );
+        assert_eq!(
+            FileTime::from_unix_time(1_498_910_400, 0),
+            gnu("2017-07-01 12:00Z", zone("EST5"))
+        );
+        assert_eq!(FileTime::from_unix_time(1_498_939_200, 0), gnu("15:00", zone("EST5")));
+        assert_eq!(
+            FileTime::from_unix_time(1_498_928_400, 0),
+            parse::posix_stamp_in("07011200", now, zone("EST5")).expect("could not parse")
+        );
+        #[cfg(feature = "tz")]
+        {
+            let london = zone("Europe/London");
+            assert_eq!(FileTime::from_unix_time(1_498_906_800, 0), gnu("2017-07-01 12:00", london));
+            assert_eq!(FileTime::from_unix_time(1_483_272_000, 0), gnu("2017-01-01 12:00", london));
+            let e = parse::gnu_date_in("2017-03-26 01:30", now, london)
+                .expect_err("parsed a time that does not exist");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        }
+    }
+
+    #[test]
+    fn touch_clamped() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let epoch = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(epoch - Duration::from_secs(60)))
+            .modified(Some(SystemTime::now()))
+            .clamp_to(Some(epoch));
+        touch(&builder, &file_path);
+        assert_eq!((epoch - Duration::from_secs(60), epoch), times(&file_path));
+        let builder = Builder::from_source_date_epoch_var(Ok("1498910400".to_owned()))
+            .expect("could not read SOURCE_DATE_EPOCH");
+        let _ = builder.touch(&file_path).expect("could not touch file");
+        assert_eq!((epoch, epoch), times(&file_path));
+        let invalid = [
+            (Err(env::VarError::NotPresent), io::ErrorKind::NotFound),
+            (Ok("-1".to_owned()), io::ErrorKind::InvalidInput),
+            (Ok("+1".to_owned()), io::ErrorKind::InvalidInput),
+            (Ok("soon".to_owned()), io::ErrorKind::InvalidInput),
+        ];
+        for (value, kind) in invalid {
+            let e = Builder::from_source_date_epoch_var(value).expect_err("read an invalid value");
+            assert_eq!(kind, e.kind());
+        }
+    }
+
+    #[test]
+    fn touch_now() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let old = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(old)).modified(Some(old));
+        touch(&builder, &file_path);
+        let before = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.modified_now();
+        touch(&builder, &file_path);
+        let (atime, mtime) = times(&file_path);
+        assert_eq!(old, atime);
+        assert!(mtime > old);
+        // The kernel's clock may be slightly coarser than `SystemTime::now`.
+        assert!(mtime + Duration::from_secs(1) >= before);
+        let _ = builder.accessed_now().clamp_to(Some(old));
+        touch(&builder, &file_path);
+        assert_eq!((old, old), times(&file_path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_now_precise() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let before = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed_now().modified_now();
+        touch(&builder, &file_path);
+        let after = SystemTime::now();
+        // Both timestamps are read from the same precise clock as `SystemTime::now`, at once.
+        let (atime, mtime) = times(&file_path);
+        assert_eq!(atime, mtime);
+        assert!(before <= mtime && mtime <= after);
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn volume_roots() {
+        let helper = TestHelper::new();
+        let drive = match helper.0.path().components().next() {
+            Some(Component::Prefix(prefix)) => match prefix.kind() {
+                Prefix::Disk(letter) => char::from(letter),
+                _ => return,
+            },
+            _ => return,
+        };
+        let root = ::read_times(format!(r"{}:\", drive), true).unwrap();
+        // Without a trailing separator, these would name the volume rather than its root.
+        for path in &[format!(r"\\.\{}:", drive), format!(r"\\?\{}:", drive)] {
+            let times = ::read_times(path, true).unwrap();
+            assert_eq!(root.modified(), times.modified());
+            assert_eq!(root.created(), times.created());
+        }
+    }
+
+    #[test]
+    fn touch_pre_epoch() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let dir_path = helper.create_top_level_directory();
+        let accessed = UNIX_EPOCH - Duration::new(1, 250_000_000);
+        let modified = UNIX_EPOCH - Duration::new(14_182_940, 999_999_999);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(accessed)).modified(Some(modified));
+        for path in &[&file_path, &dir_path] {
+            touch(&builder, path);
+            assert_eq!((accessed, modified), times(path));
+        }
+        let whole = UNIX_EPOCH - Duration::from_secs(1);
+        let _ = builder.accessed(Some(whole)).modified(Some(whole));
+        touch(&builder, &file_path);
+        assert_eq!((whole, whole), times(&file_path));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_out_of_range() {
+        use TimestampOutOfRange;
+
+        let helper = TestHelper::new();
+        let path = helper.nonexisting_file_path();
+        let before_1601 = FileTime::from_unix_time(-11_644_473_601, 0);
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(before_1601))
+            .creation_target(CreationTarget::File);
+        let e = builder.touch(&path).expect_err("set an out of range timestamp");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        let inner = Error::downcast_ref::<TimestampOutOfRange>(&e)
+            .expect("error is not TimestampOutOfRange");
+        assert_eq!(before_1601, inner.time());
+        assert!(!path.exists());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_long_path() {
+        let helper = TestHelper::new();
+        let dir = (0..12).fold(helper.0.path().to_path_buf(), |dir, i| {
+            dir.join(format!("{:02}-long-directory-name", i))
+        });
+        fs::create_dir_all(&dir).expect("could not create directories");
+        assert!(dir.as_os_str().len() > 260);
+        // Extended-length paths are not normalised by Windows, so this checks that `..` is still
+        // resolved.
+        let path = dir.join("..").join("file.txt");
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &path));
+        let parent = dir.parent().expect("directory has no parent");
+        assert_eq!((now, now), times(parent.join("file.txt")));
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_unc_paths() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let dir = fs::canonicalize(helper.0.path()).expect("could not canonicalize directory");
+        let dir = dir.to_str().expect("directory is not valid Unicode");
+        // The drive is also reachable as `\\localhost\C$` through its administrative share.
+        let local = dir.trim_start_matches(r"\\?\");
+        let share = format!(r"localhost\{}${}", &local[..1], &local[2..]);
+        let paths = [
+            format!(r"{}\file.txt", dir),
+            format!(r"{}/file.txt", dir),
+            format!(r"\\{}\file.txt", share),
+            format!(r"\\?\UNC\{}\file.txt", share),
+        ];
+        let mut builder = Builder::new();
+        for (i, path) in paths.iter().enumerate() {
+            // Administrative shares may be disabled, so those paths are skipped if unreachable.
+            if path.contains("localhost") && fs::metadata(path).is_err() {
+                continue;
+            }
+            let time = UNIX_EPOCH + Duration::from_secs(1_000_000_000 + i as u64);
+            let _ = builder.accessed(Some(time)).modified(Some(time));
+            assert_eq!(Outcome::Updated, touch_outcome(&builder, path));
+            assert_eq!((time, time), times(&file_path));
+        }
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_named_stream() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let stream_path = helper.0.path().join("file.txt:stream");
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        assert!(builder.touch(&stream_path).is_err());
+        let _ = builder.creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &stream_path));
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &stream_path));
+        assert_eq!((now, now), times(file_path));
+    }
+
+    #[test]
+    fn touch_created() {
+        let helper = TestHelper::new();
+        let path = helper.nonexisting_file_path();
+        let then = FileTime::from_unix_time(1_000_000_000, 0);
+        let mut builder = Builder::new();
+        let _ = builder
+            .created(Some(then))
+            .creation_target(CreationTarget::File);
+        if cfg!(any(windows, target_os = "freebsd")) {
+            assert_eq!(Outcome::Created, touch_outcome(&builder, &path));
+            let times = super::read_times(&path, true).expect("could not read timestamps");
+            assert_eq!(Some(then), times.created());
+        } else {
+            let e = builder.touch(&path).expect_err("set a creation timestamp");
+            assert_eq!(io::ErrorKind::Unsupported, e.kind());
+            let inner = Error::downcast_ref::<UnsupportedTimestamp>(&e)
+                .expect("error is not UnsupportedTimestamp");
+            assert_eq!(TimestampKind::Created, inner.kind());
+            assert!(!path.exists());
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    #[test]
+    fn touch_created_later() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(time))
+            .created(Some(SystemTime::now() + Duration::from_secs(86_400)));
+        let e = builder.touch(&file_path).expect_err("moved a creation timestamp forwards");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        // Moving the creation timestamp back leaves the modification timestamp as requested.
+        let _ = builder.created(Some(UNIX_EPOCH));
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        let times = super::read_times(&file_path, true).expect("could not read timestamps");
+        assert_eq!(Some(FileTime::from(UNIX_EPOCH)), times.created());
+        assert_eq!(FileTime::from(time), times.modified());
+    }
+
+    #[cfg(feature = "change-time")]
+    #[test]
+    fn touch_changed() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let then = FileTime::from_unix_time(1_000_000_000, 0);
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(then))
+            .changed(Some(then));
+        if cfg!(windows) {
+            touch(&builder, &file_path);
+            let times = super::read_times(&file_path, true).expect("could not read timestamps");
+            assert_eq!((then, then), (times.modified(), times.changed()));
+        } else {
+            let e = builder.touch(&file_path).expect_err("set a change timestamp");
+            let inner = Error::downcast_ref::<UnsupportedTimestamp>(&e)
+                .expect("error is not UnsupportedTimestamp");
+            assert_eq!(TimestampKind::Changed, inner.kind());
+        }
+    }
+
+    #[test]
+    fn age() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let day = Duration::from_secs(86_400);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now - day * 2))
+            .modified(Some(now - day));
+        touch(&builder, &file_path);
+        let modified = super::age(&file_path).expect("could not read age");
+        assert!(modified >= day && modified < day * 2);
+        let accessed = super::age_of(&file_path, TimestampKind::Accessed, false)
+            .expect("could not read age");
+        assert!(accessed >= day * 2 && accessed < day * 3);
+        let changed = super::age_of(&file_path, TimestampKind::Changed, false)
+            .expect("could not read age");
+        assert!(changed < day);
+        let _ = builder.modified(Some(now + day));
+        touch(&builder, &file_path);
+        assert_eq!(
+            Duration::from_secs(0),
+            super::age_nofollow(&file_path).expect("could not read age")
+        );
+        let e = super::age(helper.nonexisting_file_path()).expect_err("read age of missing file");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+    }
+
+    #[test]
+    fn read_times() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let accessed = FileTime::from_unix_time(1_498_910_400, 123_456_700);
+        let modified = FileTime::from_unix_time(-14_182_940, 0);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(accessed)).modified(Some(modified));
+        touch(&builder, &file_path);
+        let times = super::read_times(&file_path, true).expect("could not read timestamps");
+        assert_eq!(accessed, times.accessed());
+        assert_eq!(modified, times.modified());
+        // Setting the other timestamps just now also changed the metadata.
+        assert!(times.changed() > accessed);
+        if let Some(created) = times.created() {
+            assert!(created <= times.changed());
+        }
+        let e = super::read_times(helper.nonexisting_file_path(), false)
+            .expect_err("read timestamps of missing file");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+    }
+
+    #[test]
+    fn interior_nul() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let before = times(&file_path);
+        let nul_path = helper.0.path().join("file.txt\0.bak");
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File);
+        let e = builder.touch(&nul_path).expect_err("touched a path containing a NUL");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        let e = super::read_times(&nul_path, true).expect_err("read a path containing a NUL");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        // The path was not cut short at the NUL.
+        assert_eq!(before, times(&file_path));
+    }
+
+    #[test]
+    fn touch_prepared() {
+        let helper = TestHelper::new();
+        let path = PreparedPath::new(file_path(helper.0.path())).expect("could not prepare path");
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, builder.touch_prepared(&path).expect("touch failed"));
+        let _ = builder.modified(Some(UNIX_EPOCH + Duration::from_secs(60)));
+        assert_eq!(Outcome::Updated, builder.touch_prepared(&path).expect("touch failed"));
+        let modified = fs::metadata(path.path()).unwrap().modified().unwrap();
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(60), modified);
+        let e = PreparedPath::new("file.txt\0.bak").expect_err("prepared a path containing a NUL");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+    }
+
+    #[test]
+    fn touch_cached() {
+        let helper = TestHelper::new();
+        let paths: Vec<_> = (0..3)
+            .map(|i| helper.0.path().join(format!("file{}.txt", i)))
+            .collect();
+        let mut cache = PathCache::new(2);
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File);
+        for path in &paths[..2] {
+            let outcome = builder.touch_cached(&mut cache, path).expect("touch failed");
+            assert_eq!(Outcome::Created, outcome);
+        }
+        // Using the first path again leaves the second as the least recently used.
+        let outcome = builder.touch_cached(&mut cache, &paths[0]).expect("touch failed");
+        assert_eq!(Outcome::Updated, outcome);
+        let outcome = builder.touch_cached(&mut cache, &paths[2]).expect("touch failed");
+        assert_eq!(Outcome::Created, outcome);
+        assert_eq!(2, cache.len());
+        assert!(cache.contains(&paths[0]));
+        assert!(!cache.contains(&paths[1]));
+        assert!(cache.contains(&paths[2]));
+        for path in &paths {
+            assert_eq!(UNIX_EPOCH, fs::metadata(path).unwrap().modified().unwrap());
+        }
+    }
+
+    #[test]
+    fn touch_with_prepared() {
+        let helper = TestHelper::new();
+        let paths = [helper.create_top_level_file(), helper.create_top_level_directory()];
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(UNIX_EPOCH));
+        let times = PreparedTimes::new(&builder);
+        // Later changes to the builder's timestamps do not affect the prepared ones.
+        let _ = builder.modified(Some(UNIX_EPOCH + Duration::from_secs(60)));
+        for path in &paths {
+            let outcome = builder.touch_with_prepared(path, &times).expect("touch failed");
+            assert_eq!(Outcome::Updated, outcome);
+            let modified = fs::metadata(path).unwrap().modified().unwrap();
+            assert_eq!(UNIX_EPOCH, modified);
+        }
+    }
+
+    #[test]
+    fn coarse_clock() {
+        let helper = TestHelper::new();
+        let path = helper.create_top_level_file();
+        let before = FileTime::now();
+        let coarse = FileTime::now_coarse();
+        assert!(coarse.unix_seconds() >= before.unix_seconds() - 1);
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .touch(&path)
+            .expect("touch failed");
+        let _ = builder.modified_now().coarse_clock(true).touch(&path).expect("touch failed");
+        let modified = FileTime::from(fs::metadata(&path).unwrap().modified().unwrap());
+        assert!(modified.unix_seconds() >= before.unix_seconds() - 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn touch_c_str() {
+        let helper = TestHelper::new();
+        let path = file_path(helper.0.path());
+        let c_path = CString::new(path.as_os_str().as_bytes()).unwrap();
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, builder.touch_c_str(&c_path).expect("touch failed"));
+        assert_eq!(Outcome::Updated, builder.touch_c_str(&c_path).expect("touch failed"));
+        assert_eq!(UNIX_EPOCH, fs::metadata(&path).unwrap().modified().unwrap());
+    }
+
+    #[cfg(windows)]
+    #[test]
+    fn touch_wide() {
+        let helper = TestHelper::new();
+        let path = file_path(helper.0.path());
+        let mut wide = path.as_os_str().encode_wide().collect::<Vec<_>>();
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File);
+        let e = builder.touch_wide(&wide).expect_err("touched a path without a NUL");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        wide.push(0);
+        assert_eq!(Outcome::Created, builder.touch_wide(&wide).expect("touch failed"));
+        assert_eq!(Outcome::Updated, builder.touch_wide(&wide).expect("touch failed"));
+        assert_eq!(UNIX_EPOCH, fs::metadata(&path).unwrap().modified().unwrap());
+    }
+
+    #[test]
+    fn touch_now_fast_path() {
+        let helper = TestHelper::new();
+        let path = helper.create_top_level_file();
+        let before = FileTime::now();
+        let _ = Builder::new()
+            .accessed(Some(UNIX_EPOCH))
+            .modified(Some(UNIX_EPOCH))
+            .touch(&path)
+            .expect("touch failed");
+        super::touch_now(&path).expect("touch_now failed");
+        let (accessed, modified) = times(&path);
+        assert!(FileTime::from(accessed).unix_seconds() >= before.unix_seconds() - 1);
+        assert!(FileTime::from(modified).unix_seconds() >= before.unix_seconds() - 1);
+        let missing = helper.0.path().join("missing.txt");
+        let e = super::touch_now(&missing).expect_err("touched a missing path");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+        assert!(!missing.exists());
+    }
+
+    #[test]
+    fn failure() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let builder = Builder::new();
+        let touched = |path: &Path| builder.touch(path).expect_err("touch succeeded");
+        #[cfg(unix)]
+        let codes = [
+            (libc::EROFS, Failure::ReadOnlyFilesystem),
+            (libc::ENOSPC, Failure::NoSpace),
+            (libc::EDQUOT, Failure::QuotaExceeded),
+            (libc::EACCES, Failure::PermissionDenied),
+            (libc::EEXIST, Failure::Other),
+        ];
+        #[cfg(windows)]
+        let codes = [
+            (19, Failure::WriteProtected),
+            (112, Failure::NoSpace),
+            (1295, Failure::QuotaExceeded),
+            (5, Failure::PermissionDenied),
+            (183, Failure::Other),
+        ];
+        let mut cases: Vec<_> = codes
+            .iter()
+            .map(|&(code, failure)| (io::Error::from_raw_os_error(code), failure))
+            .collect();
+        cases.extend(vec![
+            (io::Error::from(Failure::Immutable), Failure::Immutable),
+            (io::Error::from(io::ErrorKind::PermissionDenied), Failure::PermissionDenied),
+            (io::Error::other("something else"), Failure::Other),
+            (touched(&helper.nonexisting_file_path()), Failure::NotFound),
+            (touched(Path::new("nul\0byte")), Failure::InvalidPath),
+        ]);
+        #[cfg(unix)]
+        cases.push((touched(&file_path.join("child")), Failure::WrongItemType));
+        #[cfg(any(not(windows), feature = "cap"))]
+        cases.push((
+            UnsupportedTimestamp::new(TimestampKind::Created).into(),
+            Failure::UnsupportedOnPlatform,
+        ));
+        for &(ref err, failure) in &cases {
+            assert_eq!(failure, Failure::of(err), "{}", err);
+        }
+        assert_eq!(io::ErrorKind::PermissionDenied, io::Error::from(Failure::Immutable).kind());
+        assert!(!Failure::Immutable.is_filesystem_wide());
+        assert!(Failure::ReadOnlyFilesystem.is_filesystem_wide());
+        let err = touched(&helper.nonexisting_file_path().join("file"));
+        assert_eq!(Some(Failure::NotFound), Error::of(&err).map(Error::failure));
+        assert!(file_path.exists());
+    }
+
+    #[test]
+    fn error_context() {
+        let helper = TestHelper::new();
+        let missing_path = helper.nonexisting_file_path().join("file");
+        let mut builder = Builder::new();
+        let err = builder.touch(&missing_path).unwrap_err();
+        assert_eq!(io::ErrorKind::NotFound, err.kind());
+        {
+            let context = Error::of(&err).expect("no context");
+            assert_eq!(Operation::SetTimes, context.operation());
+            assert_eq!(missing_path, context.path());
+            assert_eq!(io::ErrorKind::NotFound, context.io_error().kind());
+            let source = std::error::Error::source(context).expect("no source");
+            assert!(err.to_string().contains(&*missing_path.to_string_lossy()));
+            assert!(!err.to_string().contains(&source.to_string()));
+        }
+        let _ = builder.creation_target(CreationTarget::File);
+        let err = builder.touch(&missing_path).unwrap_err();
+        assert_eq!(Some(Operation::Create), Error::of(&err).map(Error::operation));
+        // A path is only given a context once, by the step that failed.
+        let err = super::error::context(Operation::Sync, &missing_path)(err);
+        assert_eq!(Some(Operation::Create), Error::of(&err).map(Error::operation));
+        // Errors that have a type of their own are given a context too.
+        let _ = builder.existing_only(true);
+        let err = builder.touch(&missing_path).unwrap_err();
+        assert_eq!(io::ErrorKind::InvalidInput, err.kind());
+        assert_eq!(Some(&*missing_path), Error::of(&err).map(Error::path));
+        assert!(Error::downcast_ref::<WouldOpen>(&err).is_some());
+        let report = builder.touch_all([&missing_path]);
+        let err = report.errors().next().expect("no error").1;
+        assert_eq!(Some(&*missing_path), Error::of(err).map(Error::path));
+        let err = io::Error::from(Error::new(
+            Operation::SetTimes,
+            &missing_path,
+            Failure::Immutable.into(),
+        ));
+        assert_eq!(io::ErrorKind::PermissionDenied, err.kind());
+        assert_eq!(Failure::Immutable, Failure::of(&err));
+        // Failures are still classified through the context.
+        #[cfg(unix)]
+        let code = libc::EROFS;
+        #[cfg(windows)]
+        let code = 19;
+        let err = io::Error::from(Error::new(
+            Operation::Open,
+            &missing_path,
+            io::Error::from_raw_os_error(code),
+        ));
+        assert!(Failure::of(&err).is_filesystem_wide());
+    }
+
+    #[test]
+    fn existing_only() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let missing_path = helper.nonexisting_file_path();
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(UNIX_EPOCH)).existing_only(true);
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        let e = builder.touch(&missing_path).expect_err("updated a missing path");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+        for &cause in &["creation_target", "sync_file"] {
+            let mut builder = builder.clone();
+            if cause == "creation_target" {
+                let _ = builder.creation_target(CreationTarget::File);
+            } else {
+                let _ = builder.sync_file(true);
+            }
+            let e = builder.touch(&missing_path).expect_err("opened a path");
+            assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+            let inner = Error::downcast_ref::<WouldOpen>(&e)
+                .expect("error is not WouldOpen");
+            assert_eq!(cause, inner.cause());
+        }
+        assert!(!missing_path.exists());
+    }
+
+    #[test]
+    fn strict() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let mut builder = Builder::new();
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        let _ = builder.strict(true);
+        let e = builder.touch(&file_path).expect_err("updated a path without changing it");
+        assert_eq!(io::ErrorKind::InvalidInput, e.kind());
+        assert_eq!(Some(&NothingToDo), Error::downcast_ref::<NothingToDo>(&e));
+        let report = builder.touch_all([&file_path]);
+        assert_eq!(1, report.failed());
+        let _ = builder.creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        let _ = builder
+            .creation_target(CreationTarget::None)
+            .modified(Some(UNIX_EPOCH));
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+    }
+
+    #[test]
+    fn backend() {
+        let memory = Arc::new(MemoryBackend::default());
+        let path = Path::new("/nonexistent/file.txt");
+        let time = FileTime::from_unix_time(1_498_910_400, 0);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .created(Some(time))
+            .backend(Some(memory.clone()));
+        let err = builder.touch(path).expect_err("created missing path");
+        assert_eq!(Failure::NotFound, Failure::of(&err));
+        let _ = builder.creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, builder.touch(path).unwrap());
+        assert_eq!(Outcome::Updated, builder.touch(path).unwrap());
+        let stored = memory.read_times(path, false).unwrap();
+        assert_eq!(
+            (time, time, Some(time)),
+            (stored.accessed(), stored.modified(), stored.created())
+        );
+        // Sub-second modification timestamps are truncated, which only verification reports.
+        let precise = FileTime::from_unix_time(1_498_910_400, 500);
+        let _ = builder.modified(Some(precise));
+        let report = builder.touch_all([path]);
+        assert!(report.is_success());
+        let _ = builder.granularity(GranularityMode::Verify);
+        let err = builder.touch(path).expect_err("verified truncated timestamp");
+        let mismatch = Error::downcast_ref::<TimestampMismatch>(&err)
+            .expect("error is not TimestampMismatch");
+        assert_eq!(time, mismatch.stored());
+        // Parent directories are created through the backend.
+        let nested_path = Path::new("/nonexistent/directory/file.txt");
+        let _ = builder.create_parents(true).modified(Some(time));
+        assert_eq!(Outcome::Created, builder.touch(nested_path).unwrap());
+        assert!(memory.open(nested_path.parent().unwrap(), false).is_ok());
+        // The operating system can be wrapped like any other backend.
+        let helper = TestHelper::new();
+        let file_path = helper.nonexisting_file_path();
+        let _ = builder
+            .created(None::<FileTime>)
+            .modified(Some(time))
+            .backend(Some(Arc::new(Native)));
+        assert_eq!(Outcome::Created, builder.touch(&file_path).unwrap());
+        let system_time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        assert_eq!((system_time, system_time), times(&file_path));
+    }
+
+    #[test]
+    fn native_backend() {
+        let helper = TestHelper::new();
+        let sym_path = helper.create_top_level_symlink_file();
+        let target_path = file_path(helper.0.path());
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .follow_symlinks(true)
+            .dangling_symlinks(DanglingSymlinks::Refuse)
+            .creation_target(CreationTarget::File)
+            .backend(Some(Arc::new(Native)));
+        let err = builder.touch(&sym_path).expect_err("created symbolic link target");
+        assert_eq!(Failure::IsSymlink, Failure::of(&err));
+        assert_eq!(Some(Operation::Create), Error::of(&err).map(Error::operation));
+        assert!(fs::symlink_metadata(&target_path).is_err());
+        let _ = builder.dangling_symlinks(DanglingSymlinks::CreateTarget);
+        assert_eq!(
+            Outcome::CreatedSymlinkTarget,
+            touch_outcome(&builder, &sym_path)
+        );
+        assert_eq!((now, now), times(&target_path));
+        let nested_path = helper.nonexisting_file_path().join("file.txt");
+        let _ = builder.create_parents(true);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &nested_path));
+        // Timestamps that the operating system cannot set are refused before anything is created.
+        #[cfg(all(unix, not(target_os = "freebsd")))]
+        {
+            let missing_path = helper.0.path().join("created.txt");
+            let _ = builder.created(Some(now));
+            let err = builder.touch(&missing_path).expect_err("set creation timestamp");
+            assert!(Error::downcast_ref::<UnsupportedTimestamp>(&err).is_some());
+            assert!(!missing_path.exists());
+        }
+    }
+
+    #[test]
+    fn granularity() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        // Temporary directories are not expected to be on FAT-family filesystems.
+        let g = super::granularity(&file_path).expect("could not read granularity");
+        assert!(g.is_native());
+        assert_eq!(
+            g,
+            super::granularity(helper.nonexisting_file_path())
+                .expect("could not read granularity of parent")
+        );
+        let two_seconds = Duration::from_secs(2);
+        let time = FileTime::from_unix_time(1_000_000_001, 123_456_789);
+        assert_eq!(
+            FileTime::from_unix_time(1_000_000_000, 0),
+            super::granularity::round_down(time, two_seconds)
+        );
+        assert_eq!(
+            FileTime::from_unix_time(-2, 0),
+            super::granularity::round_down(FileTime::from_unix_time(-1, 500), two_seconds)
+        );
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .granularity(GranularityMode::Round);
+        touch(&builder, &file_path);
+        let _ = builder.granularity(GranularityMode::Verify);
+        touch(&builder, &file_path);
+        let stored = super::read_times(&file_path, true).expect("could not read timestamps");
+        assert!(time.unix_nanos() - stored.modified().unix_nanos() < 1_000);
+        // Whole seconds can be stored exactly by every filesystem apart from FAT.
+        let exact = FileTime::from_unix_time(1_000_000_001, 0);
+        let _ = builder
+            .accessed(Some(exact))
+            .modified(Some(exact))
+            .granularity(GranularityMode::Exact);
+        touch(&builder, &file_path);
+        let fat = super::Granularity::of(super::granularity::Filesystem::Fat);
+        let err = super::granularity::check_precision(&builder, &fat)
+            .expect_err("FAT stored an odd second");
+        let too_precise = Error::downcast_ref::<super::TimestampTooPrecise>(&err)
+            .expect("error is not TimestampTooPrecise");
+        assert_eq!(TimestampKind::Modified, too_precise.kind());
+        assert_eq!(FileTime::from_unix_time(1_000_000_000, 0), too_precise.storable());
+        assert_eq!(Failure::UnsupportedOnPlatform, Failure::of(&err));
+    }
+
+    #[test]
+    fn granularity_exact() {
+        use granularity::{Filesystem, ASSUMED_FILESYSTEM};
+        use sys;
+
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let original = FileTime::from_unix_time(1_000_000_000, 0);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(original)).modified(Some(original));
+        touch(&builder, &file_path);
+        let before = super::read_times(&file_path, true).expect("could not read timestamps");
+        ASSUMED_FILESYSTEM.with(|f| f.set(Some(Filesystem::Fat)));
+        // FAT cannot store an odd second, so nothing is set or created.
+        let odd = FileTime::from_unix_time(1_000_000_001, 0);
+        let _ = builder
+            .accessed(None::<FileTime>)
+            .modified(Some(odd))
+            .granularity(GranularityMode::Exact)
+            .creation_target(CreationTarget::File);
+        for path in &[&file_path, &nonexisting_path] {
+            let err = builder.touch(path).expect_err("FAT stored an odd second");
+            assert_eq!(Failure::UnsupportedOnPlatform, Failure::of(&err));
+            assert_eq!(Some(path.as_path()), Error::of(&err).map(Error::path));
+        }
+        assert_eq!(
+            before,
+            super::read_times(&file_path, true).expect("could not read timestamps")
+        );
+        assert!(!nonexisting_path.exists());
+        // An even second can be stored exactly.
+        let even = FileTime::from_unix_time(1_000_000_002, 0);
+        let _ = builder.modified(Some(even));
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &file_path));
+        let after = super::read_times(&file_path, true).expect("could not read timestamps");
+        assert_eq!(even, after.modified());
+        // Nor can any filesystem store a timestamp more precisely than the platform sets it.
+        ASSUMED_FILESYSTEM.with(|f| f.set(Some(Filesystem::Other)));
+        let fine = FileTime::from_unix_time(1_000_000_002, 1);
+        let _ = builder.modified(Some(fine));
+        let result = builder.touch(&file_path);
+        if sys::RESOLUTION > Duration::from_nanos(1) {
+            let err = result.expect_err("stored a timestamp finer than the platform's resolution");
+            assert_eq!(Failure::UnsupportedOnPlatform, Failure::of(&err));
+        } else {
+            assert_eq!(Outcome::Updated, result.expect("could not touch file"));
+        }
+        ASSUMED_FILESYSTEM.with(|f| f.set(None));
+    }
+
+    #[test]
+    fn probe() {
+        let helper = TestHelper::new();
+        let caps = super::probe(helper.0.path()).expect("could not probe filesystem");
+        assert!(caps.resolution() <= Duration::from_secs(2));
+        assert!(caps.earliest() <= FileTime::from_unix_time(0, 0));
+        assert!(caps.latest() >= FileTime::from_unix_time(2_147_483_646, 0));
+        #[cfg(unix)]
+        assert!(caps.symlink_times());
+        #[cfg(all(unix, not(target_os = "freebsd")))]
+        assert!(!caps.settable_creation_time());
+        // Every scratch file is removed again.
+        let entries = fs::read_dir(helper.0.path()).expect("could not read directory");
+        assert_eq!(0, entries.count());
+    }
+
+    #[test]
+    fn touch_all() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let dir_path = helper.create_top_level_directory();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        let report = builder.touch_all([&file_path, &dir_path, &nonexisting_path]);
+        let entries = report.entries();
+        assert_eq!(3, report.len());
+        assert_eq!(
+            (2, 0, 0, 1),
+            (
+                report.updated(),
+                report.created(),
+                report.skipped(),
+                report.failed(),
+            )
+        );
+        assert_eq!(file_path, entries[0].path());
+        assert_eq!(Some(Outcome::Updated), entries[0].outcome());
+        assert_eq!(dir_path, entries[1].path());
+        assert_eq!(Some(Outcome::Updated), entries[1].outcome());
+        assert_eq!(nonexisting_path, entries[2].path());
+        match entries[2].error() {
+            Some(e) if e.kind() == io::ErrorKind::NotFound => (),
+            Some(e) => panic!("`Builder::touch_all` failed with an unexpected error: {}", e),
+            None => panic!("`Builder::touch_all` succeeded for a nonexisting path"),
+        }
+        assert_eq!((now, now), times(file_path));
+        assert_eq!((now, now), times(dir_path));
+    }
+
+    #[test]
+    fn touch_all_report() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let missing_dir_path = helper.0.path().join("missing").join("file.txt");
+        let mut builder = Builder::new();
+        let _ = builder.creation_target(CreationTarget::File);
+        let empty = builder.touch_all(Vec::<PathBuf>::new());
+        assert!(empty.is_empty());
+        assert!(empty.is_success());
+        assert!(!empty.is_cancelled());
+        let report = builder.touch_all([&file_path, &nonexisting_path, &missing_dir_path]);
+        assert_eq!(
+            (3, 1, 1, 0, 1),
+            (
+                report.len(),
+                report.updated(),
+                report.created(),
+                report.skipped(),
+                report.failed(),
+            )
+        );
+        assert!(!report.is_success());
+        let errors: Vec<_> = report.errors().collect();
+        assert_eq!(1, errors.len());
+        assert_eq!(missing_dir_path, errors[0].0);
+        assert_eq!(io::ErrorKind::NotFound, errors[0].1.kind());
+        let outcomes: Vec<_> = report.iter().map(|e| e.outcome()).collect();
+        assert_eq!(
+            vec![Some(Outcome::Updated), Some(Outcome::Created), None],
+            outcomes
+        );
+        let (path, result) = report.into_iter().nth(1).unwrap().into_parts();
+        assert_eq!(nonexisting_path, path);
+        assert_eq!(Outcome::Created, result.unwrap());
+    }
+
+    #[test]
+    fn touch_all_fail_fast() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let mut builder = Builder::new();
+        let _ = builder.error_policy(ErrorPolicy::FailFast);
+        let report = builder.touch_all([&nonexisting_path, &file_path]);
+        assert_eq!(1, report.len());
+        assert_eq!(1, report.failed());
+        assert_eq!(nonexisting_path, report.entries()[0].path());
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn touch_all_par() {
+        let helper = TestHelper::new();
+        let paths: Vec<_> = (0..64)
+            .map(|i| helper.0.path().join(format!("file{}.txt", i)))
+            .collect();
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .creation_target(CreationTarget::File);
+        let report = builder.touch_all_par(&paths).expect("could not start thread pool");
+        assert!(report.is_success());
+        assert_eq!(paths.len(), report.created());
+        for (path, entry) in paths.iter().zip(&report) {
+            assert_eq!(path, entry.path());
+            assert_eq!((now, now), times(path));
+        }
+        // A dedicated pool with a fixed chunk size updates the same paths, in the same order.
+        let _ = builder.threads(Some(2)).chunk_size(Some(8));
+        let report = builder.touch_all_par(&paths).expect("could not start thread pool");
+        assert_eq!(paths.len(), report.updated());
+        assert!(paths.iter().zip(&report).all(|(path, entry)| path == entry.path()));
+    }
+
+    #[cfg(feature = "rayon")]
+    #[test]
+    fn touch_all_par_threads() {
+        let helper = TestHelper::new();
+        let paths: Vec<_> = (0..64)
+            .map(|i| helper.0.path().join(format!("file{}.txt", i)))
+            .collect();
+        let mut builder = Builder::new();
+        let _ = builder.creation_target(CreationTarget::File);
+        // Zero means the default, as it does for `max_in_flight`.
+        let _ = builder.threads(Some(0)).chunk_size(Some(0));
+        assert_eq!((None, None), (builder.threads, builder.chunk_size));
+        let _ = builder.threads(Some(1));
+        let (threads, report) = threads_used(&mut builder, |b| {
+            b.touch_all_par(&paths).expect("could not start thread pool")
+        });
+        assert_eq!((1, paths.len()), (threads, report.created()));
+        let _ = builder.threads(Some(4)).chunk_size(Some(1));
+        let (threads, report) = threads_used(&mut builder, |b| {
+            b.touch_all_par(&paths).expect("could not start thread pool")
+        });
+        assert!((1..=4).contains(&threads));
+        assert_eq!(paths.len(), report.updated());
+        // Chunks are never split, so a chunk as long as the input leaves it all to one thread.
+        for &(chunk_size, max_threads) in &[(paths.len(), 1), (paths.len() - 1, 2)] {
+            let _ = builder.chunk_size(Some(chunk_size));
+            let (threads, report) = threads_used(&mut builder, |b| {
+                b.touch_all_par(&paths).expect("could not start thread pool")
+            });
+            assert!((1..=max_threads).contains(&threads));
+            assert_eq!(paths.len(), report.updated());
+        }
+    }
+
+    #[test]
+    fn touch_all_optimize_layout() {
+        let helper = TestHelper::new();
+        let dir_a = helper.0.path().join("a");
+        let dir_b = helper.0.path().join("b");
+        fs::create_dir(&dir_a).expect("could not create directory");
+        fs::create_dir(&dir_b).expect("could not create directory");
+        let missing = helper.0.path().join("missing").join("file.txt");
+        let paths = [
+            dir_b.join("1.txt"),
+            dir_a.join("2.txt"),
+            missing.clone(),
+            dir_b.join("3.txt"),
+            dir_a.join("4.txt"),
+        ];
+        let mut builder = Builder::new();
+        let _ = builder
+            .modified(Some(UNIX_EPOCH))
+            .creation_target(CreationTarget::File)
+            .create_parents(true)
+            .optimize_layout(true);
+        let report = builder.touch_all(&paths);
+        assert!(report.is_success());
+        assert_eq!(paths.len(), report.created());
+        // Paths in the same directory are updated together, in their original order.
+        let order: Vec<_> = report.iter().map(|entry| entry.path().to_path_buf()).collect();
+        let expected = [&paths[1], &paths[4], &paths[0], &paths[3], &paths[2]];
+        assert_eq!(expected.iter().map(|p| p.to_path_buf()).collect::<Vec<_>>(), order);
+        for path in &paths {
+            assert_eq!(UNIX_EPOCH, fs::metadata(path).unwrap().modified().unwrap());
+        }
+        // `existing_only` forbids opening directories, so paths are updated in the order given.
+        let _ = builder
+            .creation_target(CreationTarget::None)
+            .existing_only(true);
+        let report = builder.touch_all(&paths);
+        assert_eq!(paths.len(), report.updated());
+        assert!(paths.iter().zip(&report).all(|(path, entry)| path == entry.path()));
+    }
+
+    #[cfg(feature = "tokio")]
+    #[test]
+    fn touch_async() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let dir_path = helper.create_top_level_directory();
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .expect("could not start runtime");
+        let mut builder = Builder::new();
+        let _ = builder.modified(Some(UNIX_EPOCH));
+        let outcome = runtime.block_on(builder.touch_async(&file_path));
+        assert_eq!(Outcome::Updated, outcome.expect("touch failed"));
+        assert_eq!(UNIX_EPOCH, fs::metadata(&file_path).unwrap().modified().unwrap());
+        let _ = builder.modified(Some(UNIX_EPOCH + Duration::from_secs(60)));
+        let report = runtime.block_on(builder.touch_all_async(vec![&file_path, &dir_path]));
+        assert_eq!(2, report.expect("runtime shut down").updated());
+        let _ = builder.modified(Some(UNIX_EPOCH + Duration::from_secs(120)));
+        let report = runtime.block_on(builder.touch_recursive_async(&dir_path));
+        assert_eq!(1, report.expect("runtime shut down").updated());
+        let modified = fs::metadata(&dir_path).unwrap().modified().unwrap();
+        assert_eq!(UNIX_EPOCH + Duration::from_secs(120), modified);
+    }
+
+    #[test]
+    fn touch_all_cancelled() {
+        let helper = TestHelper::new();
+        let token = CancellationToken::new();
+        let mut builder = Builder::new();
+        let _ = builder
+            .creation_target(CreationTarget::File)
+            .cancellation_token(Some(token.clone()));
+        let report = builder.touch_all(
+            (0..4)
+                .map(|i| helper.0.path().join(format!("file{}.txt", i)))
+                .inspect(|path| {
+                    if path.ends_with("file2.txt") {
+                        token.cancel();
+                    }
+                }),
+        );
+        assert!(report.is_cancelled());
+        assert_eq!(2, report.len());
+        assert!(!helper.0.path().join("file2.txt").exists());
+    }
+
+    #[test]
+    fn touch_all_progress() {
+        let helper = TestHelper::new();
+        let snapshots = Arc::new(Mutex::new(Vec::new()));
+        let sink_snapshots = Arc::clone(&snapshots);
+        let sink = ProgressSink::new(Duration::from_secs(0), move |p: &Progress| {
+            let mut snapshots = sink_snapshots.lock().expect("could not lock snapshots");
+            snapshots.push((p.processed(), p.failed(), p.current_path().to_path_buf()));
+        });
+        let mut builder = Builder::new();
+        let _ = builder.progress(Some(sink));
+        let file_path = helper.create_top_level_file();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let _ = builder.touch_all([&file_path, &nonexisting_path]);
+        let snapshots = snapshots.lock().expect("could not lock snapshots");
+        assert_eq!(
+            vec![(1, 0, file_path.clone()), (2, 1, nonexisting_path.clone())],
+            *snapshots
+        );
+    }
+
+    #[test]
+    fn touch_all_rate_limit() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let mut builder = Builder::new();
+        let _ = builder.rate_limit(Some(20));
+        let report = builder.touch_all(vec![&file_path; 5]);
+        assert!(report.is_success());
+        // The first update starts immediately, and each later one waits for its own slot.
+        assert!(report.elapsed() >= Duration::from_millis(200));
+    }
+
+    #[test]
+    fn touch_all_handle_limit() {
+        use batch::max_in_flight;
+        use sys::{handle_limit, max_concurrent_operations, operations_within};
+        // Each path may need three descriptors, and half of the limit is left for the process.
+        assert_eq!(Some(170), operations_within(Some(1024)));
+        assert_eq!(Some(1), operations_within(Some(1)));
+        assert_eq!(None, operations_within(None));
+        assert_eq!(operations_within(handle_limit()), max_concurrent_operations());
+        // The limit caps what the builder asks for, and stands in for no limit or zero.
+        assert_eq!(Some(170), max_in_flight(Some(usize::MAX), Some(170)));
+        assert_eq!(Some(8), max_in_flight(Some(8), Some(170)));
+        assert_eq!(Some(170), max_in_flight(Some(0), Some(170)));
+        assert_eq!(Some(170), max_in_flight(None, Some(170)));
+        assert_eq!(Some(8), max_in_flight(Some(8), None));
+        assert_eq!(None, max_in_flight(Some(0), None));
+        assert_eq!(None, max_in_flight(None, None));
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let mut builder = Builder::new();
+        let _ = builder.max_in_flight(Some(usize::MAX));
+        let report = builder.touch_all(vec![&file_path; 64]);
+        assert!(report.is_success());
+    }
+
+    #[test]
+    fn touch_iter() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let nonexisting_path = helper.nonexisting_file_path();
+        let mut builder = Builder::new();
+        let _ = builder.creation_target(CreationTarget::File);
+        let mut results = builder.touch_iter(vec![&file_path, &nonexisting_path]);
+        match results.next() {
+            Some((ref path, Ok(Outcome::Updated))) if path == &file_path => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        // Nothing happens to the remaining paths until the iterator is advanced.
+        assert!(!nonexisting_path.exists());
+        match results.next() {
+            Some((ref path, Ok(Outcome::Created))) if path == &nonexisting_path => (),
+            other => panic!("unexpected result: {:?}", other),
+        }
+        assert!(results.next().is_none());
+    }
+
+    #[test]
+    fn touch_service() {
+        let helper = TestHelper::new();
+        let mut builder = Builder::new();
+        let _ = builder.creation_target(CreationTarget::File);
+        let (service, results) = TouchService::new(builder, 2);
+        let paths: Vec<_> = (0..8)
+            .map(|i| helper.0.path().join(format!("file{}.txt", i)))
+            .collect();
+        for path in &paths {
+            service.submit(path);
+        }
+        service.shutdown();
+        let mut updated: Vec<_> = results
+            .iter()
+            .map(|e| {
+                assert_eq!(Some(Outcome::Created), e.outcome());
+                e.path().to_path_buf()
+            })
+            .collect();
+        updated.sort();
+        assert_eq!(paths, updated);
+    }
+
+    #[test]
+    fn touch_all_age() {
+        let helper = TestHelper::new();
+        let old_path = helper.create_top_level_file();
+        let new_path = helper.0.path().join("new.txt");
+        let _ = fs::File::create(&new_path).expect("could not create new file");
+        let day = Duration::from_secs(86_400);
+        let old = SystemTime::now() - day * 60;
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(old)).modified(Some(old));
+        let _ = builder.touch(&old_path).expect("could not age file");
+        let now = SystemTime::now();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .only_older_than(Some(day * 30));
+        let report = builder.touch_all([&old_path, &new_path]);
+        let outcomes: Vec<_> = report.iter().map(|e| e.outcome()).collect();
+        assert_eq!(vec![Some(Outcome::Updated), Some(Outcome::Skipped)], outcomes);
+        assert_eq!((now, now), times(&old_path));
+        let _ = builder.only_older_than(None).only_newer_than(Some(day));
+        let report = builder.touch_all([&old_path, &new_path]);
+        assert_eq!(2, report.updated());
+    }
+
+    #[test]
+    fn touch_all_multi_error() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let nonexisting_paths = [helper.nonexisting_file_path(), helper.0.path().join("missing")];
+        let builder = Builder::new();
+        assert!(builder.touch_all([&file_path]).into_result().is_ok());
+        let err = builder
+            .touch_all([&nonexisting_paths[0], &file_path, &nonexisting_paths[1]])
+            .into_result()
+            .expect_err("missing paths were updated");
+        assert_eq!(2, err.len());
+        for ((path, e), expected) in err.iter().zip(&nonexisting_paths) {
+            assert_eq!(expected, path);
+            assert_eq!(io::ErrorKind::NotFound, e.kind());
+        }
+        let message = err.to_string();
+        assert!(message.starts_with("could not update 2 paths"));
+        let source = std::error::Error::source(&err).expect("no source");
+        assert!(!message.contains(&source.to_string()));
+    }
+
+    #[test]
+    fn touch_all_deduplicate() {
+        let helper = TestHelper::new();
+        let file_path = helper.create_top_level_file();
+        let sym_path = helper.create_top_level_symlink_file();
+        let hard_link_path = helper.0.path().join("hard-link.txt");
+        fs::hard_link(&file_path, &hard_link_path).expect("could not create hard link");
+        let nonexisting_path = helper.nonexisting_file_path();
+        let mut builder = Builder::new();
+        let _ = builder
+            .follow_symlinks(true)
+            .creation_target(CreationTarget::File)
+            .deduplicate(true);
+        let report = builder.touch_all([
+            &file_path,
+            &sym_path,
+            &hard_link_path,
+            &helper.0.path().join(".").join("file.txt"),
+            &nonexisting_path,
+            &nonexisting_path,
+        ]);
+        let outcomes: Vec<_> = report.iter().map(|e| e.outcome()).collect();
+        assert_eq!(
+            vec![
+                Some(Outcome::Updated),
+                Some(Outcome::Skipped),
+                Some(Outcome::Skipped),
+                Some(Outcome::Skipped),
+                Some(Outcome::Created),
+                Some(Outcome::Skipped),
+            ],
+            outcomes
+        );
+    }
+
+    #[test]
+    fn touch_recursive() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let inner_file_path = file_path(&dir_path);
+        let nested_path = directory_path(&dir_path);
+        let nested_file_path = file_path(&nested_path);
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        for path in &[&inner_file_path, &nested_file_path] {
+            let _ = fs::File::create(path).expect("could not create nested file");
+        }
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        let report = builder.touch_recursive(&dir_path);
+        assert_eq!(4, report.len());
+        assert_eq!(4, report.updated());
+        assert_eq!(dir_path, report.entries()[3].path());
+        for path in &[&dir_path, &inner_file_path, &nested_path, &nested_file_path] {
+            assert_eq!((now, now), times(path));
         }
+    }
 
-        #[cfg(unix)]
-        pub fn create_top_level_symlink_directory(&self) -> PathBuf {
-            let src = directory_path(self.0.path());
-            let dst = symlink_directory_path(self.0.path());
-            match unix::fs::symlink(src, &dst) {
-                Ok(_) => dst,
-                Err(e) => panic!("could not create directory symbolic link: {}", e),
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn touch_recursive_par() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let mut paths = vec![dir_path.clone()];
+        for i in 0..4 {
+            let nested_path = dir_path.join(format!("dir{}", i));
+            fs::create_dir(&nested_path).expect("could not create nested directory");
+            paths.push(nested_path.clone());
+            for j in 0..16 {
+                let nested_file_path = nested_path.join(format!("file{}.txt", j));
+                let _ = fs::File::create(&nested_file_path).expect("could not create nested file");
+                paths.push(nested_file_path);
             }
         }
-
-        #[cfg(windows)]
-        pub fn create_top_level_symlink_file(&self) -> PathBuf {
-            let src = file_path(self.0.path());
-            let dst = symlink_file_path(self.0.path());
-            match windows::fs::symlink_file(src, &dst) {
-                Ok(_) => dst,
-                Err(e) => panic!("could not create file symbolic link: {}", e),
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        let report = builder.touch_recursive_par(&dir_path);
+        assert_eq!(paths.len(), report.len());
+        assert_eq!(paths.len(), report.updated());
+        // Every directory is updated after its contents.
+        for (i, entry) in report.iter().enumerate() {
+            if let Some(parent) = entry.path().parent() {
+                assert!(report.entries()[..i].iter().all(|e| e.path() != parent));
             }
         }
+        for path in &paths {
+            assert_eq!((now, now), times(path));
+        }
+        let _ = builder.threads(Some(3)).chunk_size(Some(1));
+        let report = builder.touch_recursive_par(&dir_path);
+        assert_eq!(paths.len(), report.updated());
+        // A single thread does all of the work, however the paths are split up.
+        for &chunk_size in &[None, Some(1), Some(paths.len())] {
+            let _ = builder.threads(Some(1)).chunk_size(chunk_size);
+            let (used, report) = threads_used(&mut builder, |b| b.touch_recursive_par(&dir_path));
+            assert_eq!((1, paths.len()), (used, report.updated()));
+        }
+        // A full queue makes contents be visited straight away, still before their directory.
+        let _ = builder.threads(Some(3)).chunk_size(None).queue_depth(Some(1));
+        let report = builder.touch_recursive_par(&dir_path);
+        assert_eq!(paths.len(), report.updated());
+        for (i, entry) in report.iter().enumerate() {
+            if let Some(parent) = entry.path().parent() {
+                assert!(report.entries()[..i].iter().all(|e| e.path() != parent));
+            }
+        }
+        let _ = builder.queue_depth(Some(0));
+        assert_eq!(None, builder.queue_depth);
+    }
 
-        #[cfg(windows)]
-        pub fn create_top_level_symlink_directory(&self) -> PathBuf {
-            let src = directory_path(self.0.path());
-            let dst = symlink_directory_path(self.0.path());
-            match windows::fs::symlink_dir(src, &dst) {
-                Ok(_) => dst,
-                Err(e) => panic!("could not create directory symbolic link: {}", e),
+    #[cfg(feature = "parallel")]
+    #[test]
+    fn parallel_steal() {
+        use crossbeam_deque::{Injector, Worker};
+        use parallel::steal;
+
+        let (owner, thief) = (Worker::new_lifo(), Worker::new_lifo());
+        let stealers = [owner.stealer(), thief.stealer()];
+        let injector = Injector::new();
+        // Tasks are taken from another thread or the shared queue in chunks of the given size,
+        // or half of what is queued there by default.
+        for &(chunk_size, taken) in &[(Some(1), 1), (Some(3), 3), (None, 8)] {
+            for from_injector in [false, true] {
+                for i in 0..16 {
+                    if from_injector {
+                        injector.push(i);
+                    } else {
+                        owner.push(i);
+                    }
+                }
+                assert!(steal(&injector, &stealers, &thief, chunk_size).is_some());
+                assert_eq!(taken, 1 + thief.len());
+                while thief.pop().is_some() {}
+                while owner.pop().is_some() {}
+                while injector.steal().is_success() {}
             }
         }
+    }
 
-        pub fn nonexisting_file_path(&self) -> PathBuf {
-            self.0.path().join("nonexisting-file-path.txt")
+    #[cfg(feature = "glob")]
+    #[test]
+    fn touch_dir_entries() {
+        let helper = TestHelper::new();
+        let dir_path = helper.create_top_level_directory();
+        let inner_file_path = file_path(&dir_path);
+        let nested_path = directory_path(&dir_path);
+        let nested_file_path = file_path(&nested_path);
+        let other_path = dir_path.join("other.dat");
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        for path in &[&inner_file_path, &nested_file_path, &other_path] {
+            let _ = fs::File::create(path).expect("could not create nested file");
         }
+        let before = times(&nested_file_path);
+        let now = SystemTime::now();
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .exclude_glob("*.dat")
+            .expect("could not compile pattern");
+        let report = builder.touch_dir_entries(&dir_path);
+        assert_eq!(2, report.updated());
+        assert_eq!((now, now), times(&inner_file_path));
+        assert_eq!((now, now), times(&nested_path));
+        assert_ne!((now, now), times(&other_path));
+        assert_eq!(before, times(&nested_file_path));
     }
 
     #[test]
-    fn existing_file_noupdate() {
+    fn touch_at() {
         let helper = TestHelper::new();
-        let file_path = helper.create_top_level_file();
-        let old_times = times(&file_path);
-        let builder = Builder::new();
-        touch(&builder, &file_path);
-        assert_eq!(old_times, times(file_path));
+        let dir_path = helper.create_top_level_directory();
+        let nested_path = directory_path(&dir_path);
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        let dir = Dir::open(&dir_path).unwrap();
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .creation_target(CreationTarget::File);
+        let relative = file_path(nested_path.file_name().unwrap());
+        assert_eq!(Outcome::Created, builder.touch_at(&dir, &relative).unwrap());
+        assert_eq!(Outcome::Updated, builder.touch_at(&dir, &relative).unwrap());
+        assert_eq!((time, time), times(file_path(&nested_path)));
+        let _ = builder.creation_target(CreationTarget::None);
+        let e = builder.touch_at(&dir, "missing.txt").expect_err("created missing file");
+        assert_eq!(io::ErrorKind::NotFound, e.kind());
+        // Paths are resolved from the directory itself, wherever it has been moved.
+        #[cfg(unix)]
+        {
+            let moved_path = helper.0.path().join("moved");
+            fs::rename(&dir_path, &moved_path).expect("could not move directory");
+            let _ = builder.modified(Some(UNIX_EPOCH));
+            assert_eq!(Outcome::Updated, builder.touch_at(&dir, &relative).unwrap());
+            assert_eq!((time, UNIX_EPOCH), times(file_path(directory_path(&moved_path))));
+        }
     }
 
+    #[cfg(feature = "cap")]
     #[test]
-    fn existing_file_atime() {
+    fn touch_cap() {
         let helper = TestHelper::new();
-        let file_path = helper.create_top_level_file();
-        let (_, old_mtime) = times(&file_path);
-        let now = SystemTime::now();
+        let dir_path = helper.create_top_level_directory();
+        let dir = cap_std::fs::Dir::open_ambient_dir(&dir_path, cap_std::ambient_authority())
+            .expect("could not open directory");
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now));
-        touch(&builder, &file_path);
-        assert_eq!((now, old_mtime), times(file_path));
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Created, builder.touch_cap(&dir, "file.txt").unwrap());
+        assert_eq!(Outcome::Updated, builder.touch_cap(&dir, "file.txt").unwrap());
+        assert_eq!((time, time), times(file_path(&dir_path)));
+        // Neither a parent directory nor an absolute path is reachable from the directory.
+        let outside = helper.0.path().join("outside.txt");
+        assert!(builder.touch_cap(&dir, "../outside.txt").is_err());
+        assert!(builder.touch_cap(&dir, &outside).is_err());
+        assert!(!outside.exists());
+        let _ = builder.created(Some(time));
+        let e = builder.touch_cap(&dir, "file.txt").expect_err("set creation time");
+        assert_eq!(io::ErrorKind::Unsupported, e.kind());
     }
 
     #[test]
-    fn existing_file_mtime() {
+    fn touch_recursive_depth() {
         let helper = TestHelper::new();
-        let file_path = helper.create_top_level_file();
-        let (old_atime, _) = times(&file_path);
-        let now = SystemTime::now();
+        let dir_path = helper.create_top_level_directory();
+        let nested_path = directory_path(&dir_path);
+        let nested_file_path = file_path(&nested_path);
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        let _ = fs::File::create(&nested_file_path).expect("could not create nested file");
         let mut builder = Builder::new();
-        let _ = builder.modified(Some(now));
-        touch(&builder, &file_path);
-        assert_eq!((old_atime, now), times(file_path));
+        let _ = builder.min_depth(1).max_depth(1);
+        let report = builder.touch_recursive(&dir_path);
+        assert_eq!(1, report.len());
+        assert_eq!(nested_path, report.entries()[0].path());
     }
 
     #[test]
-    fn existing_file_times() {
+    fn touch_recursive_filters() {
         let helper = TestHelper::new();
-        let file_path = helper.create_top_level_file();
-        let now = SystemTime::now();
+        let dir_path = helper.create_top_level_directory();
+        let nested_path = directory_path(&dir_path);
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        let object_path = nested_path.join("main.o");
+        let archive_path = nested_path.join("libmain.a");
+        for path in &[&object_path, &archive_path, &nested_path.join("main.c")] {
+            let _ = fs::File::create(path).expect("could not create nested file");
+        }
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now)).modified(Some(now));
-        touch(&builder, &file_path);
-        assert_eq!((now, now), times(file_path));
+        let _ = builder.entry_types(EntryTypes::Files).extension("o");
+        let report = builder.touch_recursive(&dir_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(vec![object_path.clone()], paths);
+        #[cfg(feature = "glob")]
+        {
+            let mut builder = Builder::new();
+            let _ = builder
+                .glob("*.o")
+                .and_then(|b| b.glob("**/lib*"))
+                .expect("invalid glob pattern");
+            let report = builder.touch_recursive(&dir_path);
+            let mut paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+            paths.sort();
+            assert_eq!(vec![archive_path, object_path], paths);
+            match Builder::new().glob("a[") {
+                Err(ref e) if e.kind() == io::ErrorKind::InvalidInput => (),
+                _ => panic!("`Builder::glob` accepted an invalid pattern"),
+            }
+        }
     }
 
+    #[cfg(feature = "glob")]
     #[test]
-    fn existing_directory_noupdate() {
+    fn touch_recursive_exclude() {
         let helper = TestHelper::new();
         let dir_path = helper.create_top_level_directory();
-        let old_times = times(&dir_path);
-        let builder = Builder::new();
-        touch(&builder, &dir_path);
-        assert_eq!(old_times, times(dir_path));
+        let git_path = dir_path.join(".git");
+        let build_path = dir_path.join("build");
+        let source_path = dir_path.join("main.c");
+        for path in &[&git_path, &build_path] {
+            fs::create_dir(path).expect("could not create nested directory");
+            let _ = fs::File::create(path.join("inner")).expect("could not create nested file");
+        }
+        let _ = fs::File::create(&source_path).expect("could not create nested file");
+        let mut builder = Builder::new();
+        let _ = builder
+            .min_depth(1)
+            .exclude_path("build")
+            .exclude_glob("**/.git")
+            .expect("invalid glob pattern");
+        let report = builder.touch_recursive(&dir_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(vec![source_path], paths);
     }
 
+    #[cfg(all(feature = "gitignore", feature = "glob"))]
     #[test]
-    fn existing_directory_atime() {
+    fn touch_recursive_ignore_files() {
         let helper = TestHelper::new();
         let dir_path = helper.create_top_level_directory();
-        let (_, old_mtime) = times(&dir_path);
-        let now = SystemTime::now();
+        let target_path = dir_path.join("target");
+        let nested_path = directory_path(&dir_path);
+        let kept_path = nested_path.join("kept.log");
+        fs::create_dir(&target_path).expect("could not create nested directory");
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        fs::write(dir_path.join(".gitignore"), "/target\n*.log\n").expect("could not write");
+        fs::write(nested_path.join(".ignore"), "!kept.log\n").expect("could not write");
+        for path in &[&target_path.join("a.o"), &dir_path.join("a.log"), &kept_path] {
+            let _ = fs::File::create(path).expect("could not create nested file");
+        }
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now));
-        touch(&builder, &dir_path);
-        assert_eq!((now, old_mtime), times(dir_path));
+        let _ = builder
+            .entry_types(EntryTypes::Files)
+            .exclude_glob("**/.*ignore")
+            .expect("invalid glob pattern")
+            .respect_ignore_files(true);
+        let report = builder.touch_recursive(&dir_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(vec![kept_path], paths);
     }
 
     #[test]
-    fn existing_directory_mtime() {
+    fn touch_recursive_sorted() {
         let helper = TestHelper::new();
         let dir_path = helper.create_top_level_directory();
-        let (old_atime, _) = times(&dir_path);
-        let now = SystemTime::now();
+        let names = ["d", "b", "e", "a", "c"];
+        for name in &names {
+            let _ = fs::File::create(dir_path.join(name)).expect("could not create nested file");
+        }
         let mut builder = Builder::new();
-        let _ = builder.modified(Some(now));
-        touch(&builder, &dir_path);
-        assert_eq!((old_atime, now), times(dir_path));
+        let _ = builder.min_depth(1).sorted(true);
+        let report = builder.touch_recursive(&dir_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        let mut expected: Vec<_> = names.iter().map(|n| dir_path.join(n)).collect();
+        expected.sort();
+        assert_eq!(expected, paths);
+        // U+10000 is a surrogate pair in UTF-16, which sorts before U+FFFD, but its UTF-8 bytes
+        // sort after those of U+FFFD.
+        let nested_path = directory_path(&dir_path);
+        fs::create_dir(&nested_path).expect("could not create nested directory");
+        let supplementary_path = nested_path.join("\u{10000}");
+        let replacement_path = nested_path.join("\u{FFFD}");
+        for path in &[&replacement_path, &supplementary_path] {
+            let _ = fs::File::create(path).expect("could not create nested file");
+        }
+        let report = builder.touch_recursive(&nested_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        if cfg!(windows) {
+            assert_eq!(vec![supplementary_path, replacement_path], paths);
+        } else {
+            assert_eq!(vec![replacement_path, supplementary_path], paths);
+        }
     }
 
+    #[cfg(unix)]
     #[test]
-    fn existing_directory_times() {
+    fn touch_recursive_skip_hidden() {
         let helper = TestHelper::new();
         let dir_path = helper.create_top_level_directory();
-        let now = SystemTime::now();
+        let hidden_path = dir_path.join(".hidden");
+        let visible_path = dir_path.join("visible");
+        fs::create_dir(&hidden_path).expect("could not create nested directory");
+        for path in &[&hidden_path.join("inner"), &visible_path] {
+            let _ = fs::File::create(path).expect("could not create nested file");
+        }
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now)).modified(Some(now));
-        touch(&builder, &dir_path);
-        assert_eq!((now, now), times(dir_path));
+        let _ = builder.min_depth(1).skip_hidden(true);
+        let report = builder.touch_recursive(&dir_path);
+        let paths: Vec<_> = report.iter().map(|e| e.path().to_path_buf()).collect();
+        assert_eq!(vec![visible_path], paths);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn follow_symlink() {
+    fn open_for_writing() {
+        let helper = TestHelper::new();
+        let file_path = helper.nonexisting_file_path();
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(time))
+            .modified(Some(time))
+            .creation_target(CreationTarget::File)
+            .sync_file(true)
+            .open_for_writing(false);
+        assert_eq!(Outcome::Created, builder.touch(&file_path).unwrap());
+        assert_eq!((time, time), times(&file_path));
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+    }
+
+    #[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+    #[test]
+    fn create_existing_file() {
+        use sys::{EncodedPath, FileTimes};
+
         let helper = TestHelper::new();
         let file_path = helper.create_top_level_file();
-        let sym_path = helper.create_top_level_symlink_file();
-        let sym_old_times = symlink_times(&sym_path);
-        let now = SystemTime::now();
+        let symlink_path = symlink_file_path(helper.0.path());
+        unix::fs::symlink(&file_path, &symlink_path).expect("could not create symbolic link");
+        fs::write(&file_path, b"contents").expect("could not write file");
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
         let mut builder = Builder::new();
         let _ = builder
-            .accessed(Some(now))
-            .modified(Some(now))
-            .follow_symlinks(true);
-        touch(&builder, &sym_path);
-        assert_eq!((now, now), times(file_path));
-        assert_eq!(sym_old_times, symlink_times(sym_path));
+            .accessed(Some(time))
+            .modified(Some(time))
+            .creation_target(CreationTarget::File);
+        let file_times = FileTimes::from_builder(&builder);
+        // A file that someone else created after it was found to be missing is updated instead.
+        let raw = EncodedPath::new(&file_path).expect("could not encode path");
+        let outcome = builder.create_sys(&file_path, raw.as_raw(), &file_times);
+        assert_eq!(Outcome::Updated, outcome.unwrap());
+        assert_eq!((time, time), times(&file_path));
+        // The target of a symbolic link is opened even if it exists, and is not truncated.
+        let raw = EncodedPath::new(&symlink_path).expect("could not encode path");
+        let _ = builder
+            .create_sys(&symlink_path, raw.as_raw(), &file_times)
+            .expect("could not open symbolic link target");
+        assert_eq!(b"contents", &fs::read(&file_path).expect("could not read file")[..]);
     }
 
+    #[cfg(unix)]
     #[test]
-    fn symlink_file_noupdate() {
+    fn no_atime() {
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_file();
-        let old_times = symlink_times(&sym_path);
-        let builder = Builder::new();
-        touch(&builder, &sym_path);
-        assert_eq!(old_times, symlink_times(sym_path));
+        let file_path = helper.nonexisting_file_path();
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
+        let mut builder = Builder::new();
+        let _ = builder
+            .accessed(Some(UNIX_EPOCH))
+            .modified(Some(time))
+            .creation_target(CreationTarget::File)
+            .sync_file(true)
+            .sync_parent_dir(true)
+            .no_atime(true);
+        assert_eq!(Outcome::Created, builder.touch(&file_path).unwrap());
+        let _ = builder.accessed(None::<SystemTime>);
+        assert_eq!(Outcome::Updated, builder.touch(&file_path).unwrap());
+        assert_eq!((UNIX_EPOCH, time), times(&file_path));
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn symlink_file_atime() {
+    fn touch_fd() {
+        use std::os::unix::fs::OpenOptionsExt;
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_file();
-        let (_, old_mtime) = symlink_times(&sym_path);
-        let now = SystemTime::now();
+        let file_path = helper.create_top_level_file();
+        let time = UNIX_EPOCH + Duration::from_secs(1_498_910_400);
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((now, old_mtime), symlink_times(sym_path));
+        for &(flags, time) in &[(0, time), (libc::O_PATH, UNIX_EPOCH)] {
+            let file = OpenOptions::new()
+                .read(true)
+                .custom_flags(flags)
+                .open(&file_path)
+                .unwrap();
+            let _ = builder.modified(Some(time));
+            assert_eq!(Outcome::Updated, builder.touch_fd(&file).unwrap());
+            assert_eq!(time, file.metadata().unwrap().modified().unwrap());
+        }
+        // The descriptor still refers to the file after it is unlinked.
+        let file = fs::File::open(&file_path).unwrap();
+        fs::remove_file(&file_path).unwrap();
+        let _ = builder.modified(Some(time));
+        assert_eq!(Outcome::Updated, builder.touch_fd(&file).unwrap());
+        assert_eq!(time, file.metadata().unwrap().modified().unwrap());
     }
 
+    #[cfg(target_os = "linux")]
     #[test]
-    fn symlink_file_mtime() {
+    fn walk_one_file_system() {
+        use std::os::unix::fs::MetadataExt;
+        use walk::{Walk, WalkOptions};
+        let (root, proc_path) = (Path::new("/"), Path::new("/proc"));
+        match (fs::metadata(root), fs::metadata(proc_path)) {
+            (Ok(ref r), Ok(ref p)) if r.dev() != p.dev() => (),
+            _ => return,
+        }
+        let options = WalkOptions {
+            max_depth: 1,
+            one_file_system: true,
+            ..WalkOptions::default()
+        };
+        assert!(
+            Walk::new(root, false, Junctions::default(), &options)
+                .filter_map(Result::ok)
+                .all(|e| e.path() != proc_path)
+        );
+    }
+
+    #[test]
+    fn nocreate() {
+        let helper = TestHelper::new();
+        match Builder::new().touch(helper.nonexisting_file_path()) {
+            Ok(_) => panic!("`Builder::touch` succeeded"),
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
+            Err(e) => panic!("`Builder::touch` failed with an unexpected error: {}", e),
+        }
+    }
+
+    #[test]
+    fn refuse_dangling_symlink() {
         let helper = TestHelper::new();
         let sym_path = helper.create_top_level_symlink_file();
-        let (old_atime, _) = symlink_times(&sym_path);
-        let now = SystemTime::now();
         let mut builder = Builder::new();
-        let _ = builder.modified(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((old_atime, now), symlink_times(sym_path));
+        let _ = builder
+            .follow_symlinks(true)
+            .dangling_symlinks(DanglingSymlinks::Refuse)
+            .creation_target(CreationTarget::File);
+        let err = builder.touch(&sym_path).expect_err("`Builder::touch` succeeded");
+        assert_eq!(Failure::IsSymlink, Failure::of(&err));
+        assert!(fs::symlink_metadata(file_path(helper.0.path())).is_err());
     }
 
     #[test]
-    fn symlink_file_times() {
+    fn dangling_symlink_missing_parent() {
+        let helper = TestHelper::new();
+        let target = helper.nonexisting_file_path().join("file");
+        let sym_path = symlink_file_path(helper.0.path());
+        #[cfg(unix)]
+        unix::fs::symlink(&target, &sym_path).unwrap();
+        #[cfg(windows)]
+        windows::fs::symlink_file(&target, &sym_path).unwrap();
+        let mut builder = Builder::new();
+        let _ = builder
+            .follow_symlinks(true)
+            .creation_target(CreationTarget::File)
+            .create_parents(true);
+        let err = builder.touch(&sym_path).expect_err("`Builder::touch` succeeded");
+        assert_eq!(Failure::NotFound, Failure::of(&err));
+        assert!(!helper.nonexisting_file_path().exists());
+    }
+
+    #[test]
+    fn create_dangling_symlink_target() {
         let helper = TestHelper::new();
         let sym_path = helper.create_top_level_symlink_file();
         let now = SystemTime::now();
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now)).modified(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((now, now), symlink_times(sym_path));
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .follow_symlinks(true)
+            .dangling_symlinks(DanglingSymlinks::CreateTarget)
+            .creation_target(CreationTarget::File);
+        assert_eq!(
+            Outcome::CreatedSymlinkTarget,
+            touch_outcome(&builder, &sym_path)
+        );
+        assert_eq!((now, now), times(file_path(helper.0.path())));
     }
 
     #[test]
-    fn symlink_directory_noupdate() {
+    fn symlink_target_untouched() {
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_directory();
-        let old_times = symlink_times(&sym_path);
-        let builder = Builder::new();
-        touch(&builder, &sym_path);
-        assert_eq!(old_times, symlink_times(sym_path));
+        let file_path = helper.create_top_level_file();
+        let sym_path = helper.create_top_level_symlink_file();
+        let then = UNIX_EPOCH + Duration::from_secs(1_000_000_000);
+        let mut builder = Builder::new();
+        let _ = builder.accessed(Some(then)).modified(Some(then));
+        touch(&builder, &file_path);
+        let now = SystemTime::now();
+        let _ = builder.accessed(Some(now)).modified(Some(now));
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &sym_path));
+        assert_eq!((then, then), times(file_path));
+        assert_eq!((now, now), symlink_times(sym_path));
     }
 
     #[test]
-    fn symlink_directory_atime() {
+    fn dangling_symlink_nofollow() {
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_directory();
-        let (_, old_mtime) = symlink_times(&sym_path);
+        let sym_path = helper.create_top_level_symlink_file();
         let now = SystemTime::now();
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((now, old_mtime), symlink_times(sym_path));
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .creation_target(CreationTarget::File);
+        assert_eq!(Outcome::Updated, touch_outcome(&builder, &sym_path));
+        assert_eq!((now, now), symlink_times(sym_path));
+        assert!(fs::symlink_metadata(file_path(helper.0.path())).is_err());
     }
 
     #[test]
-    fn symlink_directory_mtime() {
+    fn new_file_noupdate() {
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_directory();
-        let (old_atime, _) = symlink_times(&sym_path);
-        let now = SystemTime::now();
         let mut builder = Builder::new();
-        let _ = builder.modified(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((old_atime, now), symlink_times(sym_path));
+        let _ = builder.creation_target(CreationTarget::File);
+        assert_eq!(
+            Outcome::Created,
+            touch_outcome(&builder, helper.nonexisting_file_path())
+        );
     }
 
     #[test]
-    fn symlink_directory_times() {
+    fn new_file_sync() {
         let helper = TestHelper::new();
-        let sym_path = helper.create_top_level_symlink_directory();
+        let file_path = helper.nonexisting_file_path();
         let now = SystemTime::now();
         let mut builder = Builder::new();
-        let _ = builder.accessed(Some(now)).modified(Some(now));
-        touch(&builder, &sym_path);
-        assert_eq!((now, now), symlink_times(sym_path));
+        let _ = builder
+            .accessed(Some(now))
+            .modified(Some(now))
+            .creation_target(CreationTarget::File)
+            .sync_file(true)
+            .sync_parent_dir(true);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &file_path));
+        assert_eq!((now, now), times(file_path));
     }
 
     #[test]
-    fn nocreate() {
+    fn new_file_parents() {
         let helper = TestHelper::new();
-        match Builder::new().touch(helper.nonexisting_file_path()) {
-            Ok(_) => panic!("`Builder::touch` succeeded"),
-            Err(ref e) if e.kind() == io::ErrorKind::NotFound => (),
-            Err(e) => panic!("`Builder::touch` failed with an unexpected error: {}", e),
-        }
+        let file_path = file_path(helper.0.path().join("a").join("b"));
+        let mut builder = Builder::new();
+        let _ = builder
+            .creation_target(CreationTarget::File)
+            .create_parents(true);
+        assert_eq!(Outcome::Created, touch_outcome(&builder, &file_path));
+        assert!(file_path.is_file());
     }
 
     #[test]
-    fn new_file_noupdate() {
+    fn new_file_parents_rollback() {
         let helper = TestHelper::new();
+        let top_path = helper.0.path().join("a");
+        let file_path = top_path.join("b").join("x".repeat(1024));
         let mut builder = Builder::new();
-        let _ = builder.creation_target(CreationTarget::File);
-        touch(&builder, helper.nonexisting_file_path());
+        let _ = builder
+            .creation_target(CreationTarget::File)
+            .create_parents(true)
+            .rollback_parents(true);
+        if builder.touch(&file_path).is_ok() {
+            panic!("`Builder::touch` succeeded");
+        }
+        assert!(fs::symlink_metadata(top_path).is_err());
     }
 
     #[test]
@@ -0,0 +1,27 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Setting timestamps to the current time with as little work as possible.
+
+use Operation;
+use error::context;
+use std::io;
+use std::path::Path;
+use sys;
+
+#[inline]
+/// Sets the access and modification timestamps of an existing path to the current time.
+///
+/// This is the most common use of `touch`, without any of a `Builder`'s options: symbolic links
+/// are followed, nothing is created, and a path that does not exist fails with an error of kind
+/// `NotFound`. On Unix, this is a single `utimensat` call that leaves the kernel to read the
+/// current time, which only needs write access to the file rather than ownership of it. On
+/// Windows, the path is opened, given the current time with `SetFileTime` and closed again.
+pub fn touch_now<P: AsRef<Path>>(path: P) -> io::Result<()> {
+    let path = path.as_ref();
+    sys::touch_now(path).map_err(context(Operation::SetTimes, path))
+}
@@ -0,0 +1,285 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Multi-threaded directory tree traversal.
+//!
+//! Each thread keeps its own queue of paths to visit, and steals paths from other threads once
+//! its own queue runs dry. Directories are still updated after their contents: each directory
+//! counts its unfinished children, and whichever thread finishes the last child also updates the
+//! directory itself. Threads that find no work to steal sleep until more is queued.
+
+use batch::{lock, Batch};
+use crossbeam_deque::{Injector, Steal, Stealer, Worker};
+use std::{iter, thread};
+use std::path::PathBuf;
+use std::sync::{Arc, Condvar, Mutex};
+use std::sync::atomic::{self, AtomicUsize, Ordering};
+use sys;
+use walk::{DirInfo, Entry, Tree, Visit};
+
+/// A directory whose contents are being visited.
+struct Node {
+    /// The directory itself, which is updated once its contents have been visited.
+    entry: Entry,
+    /// Information that applies to the contents of the directory.
+    info: DirInfo,
+    /// How many of the directory's contents have not been visited yet.
+    remaining: AtomicUsize,
+    /// The directory containing this one, if any.
+    parent: Option<Arc<Node>>,
+}
+
+/// A path waiting to be visited.
+struct Task {
+    /// The path to visit.
+    path: PathBuf,
+    /// How many levels below the root the path is.
+    depth: usize,
+    /// The directory containing the path, if any.
+    parent: Option<Arc<Node>>,
+}
+
+#[derive(Clone)]
+/// An iterator over the directories containing a path, innermost first.
+struct Ancestors<'a>(Option<&'a Node>);
+
+/// The state shared by every thread walking a tree.
+struct Shared<'a, 'b: 'a> {
+    /// The tree being walked.
+    tree: &'a Tree,
+    /// The batch that paths are updated in.
+    batch: &'a Batch<'b>,
+    /// Paths that have not been claimed by any thread yet.
+    injector: Injector<Task>,
+    /// Allows paths to be stolen from each thread.
+    stealers: Vec<Stealer<Task>>,
+    /// How many tasks have been queued but not finished yet.
+    pending: AtomicUsize,
+    /// How many threads are sleeping until more work is queued.
+    sleeping: AtomicUsize,
+    /// Held by a thread while it decides whether to sleep, so that it cannot miss a wake-up.
+    idle: Mutex<()>,
+    /// Wakes sleeping threads once work is queued or every task has finished.
+    wake: Condvar,
+    /// The most tasks a thread takes from another queue at once, if limited.
+    chunk_size: Option<usize>,
+    /// The most tasks that may be pending before contents are visited instead of queued, if
+    /// limited.
+    queue_depth: Option<usize>,
+}
+
+impl<'a> Iterator for Ancestors<'a> {
+    type Item = &'a DirInfo;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        self.0.map(|node| {
+            self.0 = node.parent.as_deref();
+            &node.info
+        })
+    }
+}
+
+impl<'a, 'b> Shared<'a, 'b> {
+    /// Visits every path queued by a thread or stolen from other threads, until none remain.
+    fn run(&self, local: &Worker<Task>) {
+        loop {
+            let stolen = || steal(&self.injector, &self.stealers, local, self.chunk_size);
+            match local.pop().or_else(stolen) {
+                Some(task) => {
+                    self.visit(local, task);
+                    if self.pending.fetch_sub(1, Ordering::SeqCst) == 1 {
+                        self.wake_idle();
+                    }
+                }
+                None if !self.sleep() => return,
+                None => (),
+            }
+        }
+    }
+
+    /// Sleeps until another thread may have queued work, unless some is already queued.
+    ///
+    /// Returns `false` if every task has finished, so there is nothing left to wait for.
+    fn sleep(&self) -> bool {
+        let mut guard = lock(&self.idle);
+        let _ = self.sleeping.fetch_add(1, Ordering::SeqCst);
+        // Pairs with the fence in `wake_idle`: either this thread sees the new work, or the
+        // thread that queued it sees this one sleeping and wakes it.
+        atomic::fence(Ordering::SeqCst);
+        while self.pending.load(Ordering::SeqCst) != 0 && !self.has_queued_work() {
+            guard = self.wake.wait(guard).unwrap_or_else(|e| e.into_inner());
+        }
+        let _ = self.sleeping.fetch_sub(1, Ordering::SeqCst);
+        self.pending.load(Ordering::SeqCst) != 0
+    }
+
+    #[inline]
+    /// Returns whether any queue has tasks that could be stolen.
+    fn has_queued_work(&self) -> bool {
+        !self.injector.is_empty() || self.stealers.iter().any(|s| !s.is_empty())
+    }
+
+    #[inline]
+    /// Wakes any sleeping threads, after work has been queued or every task has finished.
+    fn wake_idle(&self) {
+        atomic::fence(Ordering::SeqCst);
+        if self.sleeping.load(Ordering::SeqCst) > 0 {
+            let _guard = lock(&self.idle);
+            self.wake.notify_all();
+        }
+    }
+
+    /// Visits a path, queueing its contents if it is a directory.
+    fn visit(&self, local: &Worker<Task>, task: Task) {
+        if self.batch.is_stopped() {
+            return self.complete(task.parent);
+        }
+        let ancestors = Ancestors(task.parent.as_deref());
+        match self.tree.visit(task.path, task.depth, ancestors) {
+            Visit::Skip => (),
+            Visit::Leaf(entry) => self.update(&entry),
+            Visit::Descend(entry, contents, info) => {
+                let depth = entry.depth() + 1;
+                // The extra count keeps the directory from finishing while it is still listed.
+                let node = Arc::new(Node {
+                    entry,
+                    info,
+                    remaining: AtomicUsize::new(1),
+                    parent: task.parent,
+                });
+                for child in contents {
+                    match child {
+                        Ok(path) => {
+                            let _ = node.remaining.fetch_add(1, Ordering::AcqRel);
+                            let task = Task {
+                                path,
+                                depth,
+                                parent: Some(Arc::clone(&node)),
+                            };
+                            // Once enough tasks are pending, contents are visited depth first.
+                            let pending = self.pending.load(Ordering::SeqCst);
+                            if self.queue_depth.is_some_and(|max| pending >= max) {
+                                self.visit(local, task);
+                            } else {
+                                let _ = self.pending.fetch_add(1, Ordering::SeqCst);
+                                local.push(task);
+                                self.wake_idle();
+                            }
+                        }
+                        Err(e) => {
+                            let _ = self.batch.fail(node.entry.path().to_path_buf(), e);
+                        }
+                    }
+                }
+                return self.complete(Some(node));
+            }
+            Visit::Unreadable(entry, e) => {
+                // The directory itself can still be updated, even if its contents cannot be listed.
+                let _ = self.batch.fail(entry.path().to_path_buf(), e);
+                self.update(&entry);
+            }
+            Visit::Failed(path, e) => {
+                let _ = self.batch.fail(path, e);
+            }
+        }
+        self.complete(task.parent)
+    }
+
+    /// Records that one of a directory's contents has been visited, updating the directory
+    /// itself once all of them have been.
+    fn complete(&self, node: Option<Arc<Node>>) {
+        let mut node = node;
+        while let Some(n) = node {
+            if n.remaining.fetch_sub(1, Ordering::AcqRel) != 1 {
+                return;
+            }
+            if !self.batch.is_stopped() {
+                self.update(&n.entry);
+            }
+            node = n.parent.clone();
+        }
+    }
+
+    #[inline]
+    /// Updates an entry, if it is selected.
+    fn update(&self, entry: &Entry) {
+        if self.tree.is_selected(entry) {
+            let _ = self.batch.touch(entry.path());
+        }
+    }
+}
+
+#[inline]
+/// Takes a task from the shared queue or from another thread.
+///
+/// Up to `chunk_size` tasks are taken at once, or half of those in the queue they are taken
+/// from if that is `None`. The first is returned and the rest are moved to the local queue.
+pub fn steal<T>(
+    injector: &Injector<T>,
+    stealers: &[Stealer<T>],
+    local: &Worker<T>,
+    chunk_size: Option<usize>,
+) -> Option<T> {
+    iter::repeat_with(|| {
+        match chunk_size {
+            Some(limit) => injector.steal_batch_with_limit_and_pop(local, limit),
+            None => injector.steal_batch_and_pop(local),
+        }.or_else(|| {
+            stealers
+                .iter()
+                .map(|stealer| match chunk_size {
+                    Some(limit) => stealer.steal_batch_with_limit_and_pop(local, limit),
+                    None => stealer.steal_batch_and_pop(local),
+                })
+                .collect()
+        })
+    }).find(|s| !s.is_retry())
+        .and_then(Steal::success)
+}
+
+/// Updates every path in a tree using several threads.
+///
+/// There is one thread per available core unless `threads` says otherwise, and never more than
+/// the process's file descriptor or handle limit allows paths to be visited at once. Threads
+/// take up to `chunk_size` paths at a time from another queue, or half of it if that is `None`,
+/// and visit the contents of a directory straight away instead of queueing them once
+/// `queue_depth` paths are pending.
+pub fn touch_tree(
+    tree: &Tree,
+    batch: &Batch,
+    threads: Option<usize>,
+    chunk_size: Option<usize>,
+    queue_depth: Option<usize>,
+) {
+    let threads = threads.unwrap_or_else(|| thread::available_parallelism().map_or(1, |n| n.get()));
+    let threads = sys::max_concurrent_operations().map_or(threads, |n| threads.min(n));
+    let workers = (0..threads).map(|_| Worker::new_lifo()).collect::<Vec<_>>();
+    let shared = Shared {
+        tree,
+        batch,
+        injector: Injector::new(),
+        stealers: workers.iter().map(Worker::stealer).collect(),
+        pending: AtomicUsize::new(1),
+        sleeping: AtomicUsize::new(0),
+        idle: Mutex::new(()),
+        wake: Condvar::new(),
+        chunk_size,
+        queue_depth,
+    };
+    shared.injector.push(Task {
+        path: tree.root().to_path_buf(),
+        depth: 0,
+        parent: None,
+    });
+    thread::scope(|scope| {
+        for worker in workers {
+            let shared = &shared;
+            let _ = scope.spawn(move || shared.run(&worker));
+        }
+    });
+}
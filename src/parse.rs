@@ -0,0 +1,708 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Parsing of human-readable timestamps.
+//!
+//! The functions in this module produce `FileTime` values suitable for `Builder::accessed` and
+//! `Builder::modified`. Invalid strings are reported as errors of kind `InvalidInput`.
+//!
+//! Dates and times without an explicit time zone are taken to be in UTC, so that the same string
+//! always means the same point in time. The functions ending in `_in` take a `Zone` to use
+//! instead, which may come from the `TZ` environment variable via `Zone::from_env`.
+//!
+//! This module is only available if the `parse` feature is enabled.
+
+use FileTime;
+#[cfg(feature = "tz")]
+use chrono::{self, TimeZone};
+#[cfg(feature = "tz")]
+use chrono_tz::Tz;
+use std::{env, io, iter};
+
+/// The number of seconds in one day.
+const SECS_PER_DAY: i64 = 86_400;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// A unit of relative time.
+enum Unit {
+    /// A fixed number of seconds.
+    Seconds(i64),
+    /// A number of calendar months.
+    Months(i64),
+}
+
+#[derive(Clone, Debug, Eq, PartialEq)]
+/// A piece of a time string.
+enum Token<'a> {
+    /// An unsigned number.
+    Number(i64),
+    /// A `+` or `-` sign.
+    Sign(i64),
+    /// A word, in lower case.
+    Word(&'a str),
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// An offset from a point in time, made up of calendar months and seconds.
+struct Offset {
+    /// Calendar months to add.
+    months: i64,
+    /// Seconds to add.
+    seconds: i64,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// The time zone that dates and times without an explicit time zone are taken to be in.
+pub enum Zone {
+    /// Coordinated Universal Time.
+    Utc,
+    /// A fixed offset from UTC, in seconds east of UTC.
+    Fixed(i32),
+    #[cfg(feature = "tz")]
+    /// A time zone from the IANA time zone database, including its daylight saving time rules.
+    ///
+    /// This variant is only available if the `tz` feature is enabled.
+    Named(Tz),
+}
+
+impl Zone {
+    /// Returns the time zone named by the `TZ` environment variable.
+    ///
+    /// If `TZ` is unset or empty, UTC is used rather than the system's local time zone, so that
+    /// results do not depend on how the machine is configured. See `Zone::from_name` for the
+    /// values that are accepted.
+    pub fn from_env() -> io::Result<Self> {
+        match env::var("TZ") {
+            Ok(ref name) if !name.is_empty() => Self::from_name(name),
+            Ok(_) | Err(env::VarError::NotPresent) => Ok(Zone::Utc),
+            Err(env::VarError::NotUnicode(_)) => Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "the TZ environment variable is not valid Unicode",
+            )),
+        }
+    }
+
+    /// Returns the time zone with a given name.
+    ///
+    /// The name may be `UTC`, an offset such as `+01:00`, or a POSIX `TZ` value without daylight
+    /// saving time rules, such as `EST5` or `<+0530>-5:30`. If the `tz` feature is enabled, it
+    /// may also be the name of a time zone in the IANA time zone database, such as
+    /// `Europe/London`. As in `TZ`, a leading `:` is ignored.
+    pub fn from_name(name: &str) -> io::Result<Self> {
+        let s = name.strip_prefix(':').unwrap_or(name);
+        match zone_offset(s).or_else(|| posix_zone(s)) {
+            Some(0) => return Ok(Zone::Utc),
+            Some(seconds) => return Ok(Zone::Fixed(seconds as i32)),
+            None => (),
+        }
+        #[cfg(feature = "tz")]
+        {
+            if let Ok(tz) = s.parse::<Tz>() {
+                return Ok(Zone::Named(tz));
+            }
+        }
+        Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("unknown time zone `{}`", name),
+        ))
+    }
+
+    /// Returns the offset from UTC in seconds at a time given in UTC.
+    fn offset_at(self, seconds: i64) -> i64 {
+        #[cfg(not(feature = "tz"))]
+        let _ = seconds;
+        match self {
+            Zone::Utc => 0,
+            Zone::Fixed(offset) => i64::from(offset),
+            #[cfg(feature = "tz")]
+            Zone::Named(tz) => chrono::DateTime::from_timestamp(seconds, 0).map_or(0, |t| {
+                let offset = tz.offset_from_utc_datetime(&t.naive_utc());
+                i64::from(chrono::Offset::fix(&offset).local_minus_utc())
+            }),
+        }
+    }
+
+    /// Returns the offset from UTC in seconds at a time given as wall-clock seconds since the
+    /// Unix epoch.
+    ///
+    /// If the wall-clock time occurs twice, the earlier of the two offsets is used. Returns
+    /// `None` if the wall-clock time does not occur at all, as happens when clocks go forwards.
+    fn offset_at_local(self, seconds: i64) -> Option<i64> {
+        match self {
+            Zone::Utc | Zone::Fixed(_) => Some(self.offset_at(seconds)),
+            #[cfg(feature = "tz")]
+            Zone::Named(tz) => {
+                let local = chrono::DateTime::from_timestamp(seconds, 0)?.naive_utc();
+                let offset = tz.offset_from_local_datetime(&local).earliest()?;
+                Some(i64::from(chrono::Offset::fix(&offset).local_minus_utc()))
+            }
+        }
+    }
+
+    /// Returns the days since the Unix epoch of the local date at a time given in UTC.
+    fn today(self, now: FileTime) -> i64 {
+        let seconds = now.unix_seconds();
+        seconds
+            .saturating_add(self.offset_at(seconds))
+            .div_euclid(SECS_PER_DAY)
+    }
+
+    /// Converts a wall-clock time in this time zone to a time in UTC.
+    fn to_utc(self, s: &str, local: i64, nanoseconds: u32) -> io::Result<FileTime> {
+        let offset = self
+            .offset_at_local(local)
+            .ok_or_else(|| invalid(s, "time does not exist in the time zone"))?;
+        let seconds = local
+            .checked_sub(offset)
+            .ok_or_else(|| invalid(s, "time is out of range"))?;
+        Ok(FileTime::from_unix_time(seconds, nanoseconds))
+    }
+}
+
+impl Default for Zone {
+    #[inline]
+    fn default() -> Self {
+        Zone::Utc
+    }
+}
+
+/// Creates an error for an invalid time string.
+fn invalid(s: &str, reason: &str) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidInput,
+        format!("invalid time string `{}`: {}", s, reason),
+    )
+}
+
+/// Splits a time string into numbers, signs and words.
+fn tokenize(s: &str) -> io::Result<Vec<Token<'_>>> {
+    let mut tokens = Vec::new();
+    let mut rest = s.trim_start();
+    while let Some(c) = rest.chars().next() {
+        let len = if c.is_ascii_digit() {
+            let len = rest.find(|c: char| !c.is_ascii_digit()).unwrap_or(rest.len());
+            let n = rest[..len]
+                .parse()
+                .map_err(|_| invalid(s, "number is too large"))?;
+            tokens.push(Token::Number(n));
+            len
+        } else if c.is_alphabetic() {
+            let len = rest.find(|c: char| !c.is_alphabetic()).unwrap_or(rest.len());
+            tokens.push(Token::Word(&rest[..len]));
+            len
+        } else if c == '+' || c == '-' {
+            tokens.push(Token::Sign(if c == '+' { 1 } else { -1 }));
+            1
+        } else if c == ',' {
+            1
+        } else {
+            return Err(invalid(s, "unexpected character"));
+        };
+        rest = rest[len..].trim_start();
+    }
+    Ok(tokens)
+}
+
+/// Returns the unit that a word refers to, if any.
+fn unit(word: &str) -> Option<Unit> {
+    let unit = match word.to_ascii_lowercase().as_str() {
+        "s" | "sec" | "secs" | "second" | "seconds" => Unit::Seconds(1),
+        "m" | "min" | "mins" | "minute" | "minutes" => Unit::Seconds(60),
+        "h" | "hr" | "hrs" | "hour" | "hours" => Unit::Seconds(3600),
+        "d" | "day" | "days" => Unit::Seconds(SECS_PER_DAY),
+        "w" | "week" | "weeks" => Unit::Seconds(7 * SECS_PER_DAY),
+        "fortnight" | "fortnights" => Unit::Seconds(14 * SECS_PER_DAY),
+        "month" | "months" => Unit::Months(1),
+        "y" | "year" | "years" => Unit::Months(12),
+        _ => return None,
+    };
+    Some(unit)
+}
+
+/// Parses relative items such as `2 hours ago`, `yesterday` or `+30m` into an offset.
+///
+/// Returns `None` if the tokens are not all relative items.
+fn relative_offset(tokens: &[Token]) -> Option<Offset> {
+    let mut total = Offset::default();
+    // The items since the last `ago`, which it negates.
+    let mut pending = Offset::default();
+    // A sign carries over to the items after it, until another sign is given.
+    let mut sign = 1;
+    let mut i = 0;
+    while i < tokens.len() {
+        let mut amount = None;
+        if let Token::Sign(s) = tokens[i] {
+            sign = s;
+            i += 1;
+        }
+        match tokens.get(i) {
+            Some(&Token::Number(n)) => {
+                amount = Some(n);
+                i += 1;
+            }
+            Some(&Token::Word(w)) if amount.is_none() => {
+                let n = match w.to_ascii_lowercase().as_str() {
+                    "now" | "today" => Some(0),
+                    "yesterday" => Some(-1),
+                    "tomorrow" => Some(1),
+                    _ => None,
+                };
+                if let Some(days) = n {
+                    pending.seconds = pending.seconds.checked_add(days * SECS_PER_DAY)?;
+                    i += 1;
+                    continue;
+                }
+                match w.to_ascii_lowercase().as_str() {
+                    "ago" => {
+                        total.months = total.months.checked_sub(pending.months)?;
+                        total.seconds = total.seconds.checked_sub(pending.seconds)?;
+                        pending = Offset::default();
+                        i += 1;
+                        continue;
+                    }
+                    "in" | "and" => {
+                        i += 1;
+                        continue;
+                    }
+                    "next" => {
+                        amount = Some(1);
+                        i += 1;
+                    }
+                    "last" => {
+                        amount = Some(-1);
+                        i += 1;
+                    }
+                    _ => (),
+                }
+            }
+            _ => return None,
+        }
+        let unit = match tokens.get(i) {
+            Some(&Token::Word(w)) => unit(w)?,
+            _ => return None,
+        };
+        i += 1;
+        let amount = amount.unwrap_or(1).checked_mul(sign)?;
+        match unit {
+            Unit::Seconds(n) => {
+                pending.seconds = pending.seconds.checked_add(amount.checked_mul(n)?)?;
+            }
+            Unit::Months(n) => {
+                pending.months = pending.months.checked_add(amount.checked_mul(n)?)?;
+            }
+        }
+    }
+    Some(Offset {
+        months: total.months.checked_add(pending.months)?,
+        seconds: total.seconds.checked_add(pending.seconds)?,
+    })
+}
+
+/// Returns the number of days since the Unix epoch for a date in the proleptic Gregorian
+/// calendar.
+///
+/// The month and day may be out of range, in which case they roll over into the following
+/// months and years. Returns `None` if the result does not fit in an `i64`.
+fn days_from_civil(year: i64, month: i64, day: i64) -> Option<i64> {
+    // Normalise the month, then count years from March, so that leap days come last.
+    let year = year.checked_add((month - 1).div_euclid(12))?;
+    let month = (month - 1).rem_euclid(12) + 1;
+    let year = if month <= 2 { year.checked_sub(1)? } else { year };
+    let era = year.div_euclid(400);
+    let year_of_era = year.rem_euclid(400);
+    let day_of_year = ((153 * ((month + 9) % 12) + 2) / 5).checked_add(day)? - 1;
+    let day_of_era = year_of_era * 365 + year_of_era / 4 - year_of_era / 100 + day_of_year;
+    era.checked_mul(146_097)?
+        .checked_add(day_of_era)?
+        .checked_sub(719_468)
+}
+
+/// Returns the year, month and day in the proleptic Gregorian calendar for a number of days
+/// since the Unix epoch.
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let days = days + 719_468;
+    let era = days.div_euclid(146_097);
+    let day_of_era = days.rem_euclid(146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Applies an offset to a point in time.
+fn apply(time: FileTime, offset: Offset) -> Option<FileTime> {
+    let mut seconds = time.unix_seconds();
+    if offset.months != 0 {
+        // Calendar months keep the time of day and day of the month, rolling over any days that
+        // do not exist in the resulting month.
+        let days = seconds.div_euclid(SECS_PER_DAY);
+        let (year, month, day) = civil_from_days(days);
+        let month = month.checked_add(offset.months)?;
+        let days = days_from_civil(year, month, day)?;
+        seconds = days
+            .checked_mul(SECS_PER_DAY)?
+            .checked_add(seconds.rem_euclid(SECS_PER_DAY))?;
+    }
+    seconds = seconds.checked_add(offset.seconds)?;
+    Some(FileTime::from_unix_time(seconds, time.nanoseconds()))
+}
+
+#[derive(Clone, Copy, Debug, Default)]
+/// A time of day.
+struct TimeOfDay {
+    /// Seconds since midnight.
+    seconds: i64,
+    /// Nanoseconds since the start of the second.
+    nanoseconds: u32,
+}
+
+/// Splits the leading ASCII digits off a string.
+fn split_digits(s: &str) -> (&str, &str) {
+    let len = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    s.split_at(len)
+}
+
+/// Parses a number made up of between `min` and `max` ASCII digits.
+fn number(digits: &str, min: usize, max: usize) -> Option<i64> {
+    if digits.len() < min || digits.len() > max || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    digits.parse().ok()
+}
+
+/// Parses the digits after a decimal point as nanoseconds.
+///
+/// Digits beyond nanosecond precision are ignored.
+fn fraction(digits: &str) -> Option<u32> {
+    if digits.is_empty() || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return None;
+    }
+    let nanoseconds = digits
+        .bytes()
+        .chain(iter::repeat(b'0'))
+        .take(9)
+        .fold(0, |n, b| n * 10 + u32::from(b - b'0'));
+    Some(nanoseconds)
+}
+
+/// Returns the number of days in a month.
+fn days_in_month(year: i64, month: i64) -> Option<i64> {
+    Some(days_from_civil(year, month + 1, 1)? - days_from_civil(year, month, 1)?)
+}
+
+/// Returns the number of days since the Unix epoch for a valid date.
+fn date(year: i64, month: i64, day: i64) -> Option<i64> {
+    if (1..=12).contains(&month) && day >= 1 && day <= days_in_month(year, month)? {
+        days_from_civil(year, month, day)
+    } else {
+        None
+    }
+}
+
+/// Parses an ISO 8601 calendar date, such as `2017-07-01`, returning the days since the Unix
+/// epoch and the rest of the string.
+fn iso_date(s: &str) -> Option<(i64, &str)> {
+    let (negative, s) = match s.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, s),
+    };
+    let (year, s) = split_digits(s);
+    let year = number(year, 4, 9)?;
+    let s = s.strip_prefix('-')?;
+    let (month, s) = split_digits(s);
+    let s = s.strip_prefix('-')?;
+    let (day, s) = split_digits(s);
+    let year = if negative { -year } else { year };
+    Some((date(year, number(month, 1, 2)?, number(day, 1, 2)?)?, s))
+}
+
+/// Parses a time of day, such as `12:30`, `12:30:45` or `12:30:45.123`, returning it and the rest
+/// of the string.
+fn time_of_day(s: &str) -> Option<(TimeOfDay, &str)> {
+    let (hour, s) = split_digits(s);
+    let hour = number(hour, 1, 2)?;
+    let s = s.strip_prefix(':')?;
+    let (minute, mut s) = split_digits(s);
+    let minute = number(minute, 2, 2)?;
+    let mut second = 0;
+    let mut nanoseconds = 0;
+    if let Some(rest) = s.strip_prefix(':') {
+        let (digits, rest) = split_digits(rest);
+        second = number(digits, 2, 2)?;
+        s = rest;
+        if let Some(rest) = s.strip_prefix('.').or_else(|| s.strip_prefix(',')) {
+            let (digits, rest) = split_digits(rest);
+            nanoseconds = fraction(digits)?;
+            s = rest;
+        }
+    }
+    // A second of 60 is a leap second, which is treated as the start of the next minute.
+    if hour > 23 || minute > 59 || second > 60 {
+        return None;
+    }
+    let time = TimeOfDay {
+        seconds: hour * 3600 + minute * 60 + second,
+        nanoseconds,
+    };
+    Some((time, s))
+}
+
+/// Parses a time zone designator, such as `Z`, `UTC`, `+01:00` or `-0500`, returning its offset
+/// from UTC in seconds.
+fn zone_offset(s: &str) -> Option<i64> {
+    match s {
+        "Z" | "z" | "UTC" | "utc" | "GMT" | "gmt" => return Some(0),
+        _ => (),
+    }
+    let (sign, s) = match s.strip_prefix('+') {
+        Some(rest) => (1, rest),
+        None => (-1, s.strip_prefix('-')?),
+    };
+    let (hours, s) = split_digits(s);
+    let (hours, minutes) = if s.is_empty() && hours.len() == 4 {
+        (number(&hours[..2], 2, 2)?, number(&hours[2..], 2, 2)?)
+    } else if s.is_empty() {
+        (number(hours, 1, 2)?, 0)
+    } else {
+        (number(hours, 1, 2)?, number(s.strip_prefix(':')?, 2, 2)?)
+    };
+    if hours > 24 || minutes > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60))
+}
+
+/// Parses a POSIX `TZ` value without daylight saving time rules, such as `EST5` or
+/// `<+0530>-5:30`, returning its offset from UTC in seconds.
+///
+/// POSIX offsets count westwards, so `EST5` is five hours behind UTC.
+fn posix_zone(s: &str) -> Option<i64> {
+    let rest = match s.strip_prefix('<') {
+        Some(rest) => &rest[rest.find('>')? + 1..],
+        None => {
+            let len = s.find(|c: char| !c.is_ascii_alphabetic()).unwrap_or(s.len());
+            if len < 3 {
+                return None;
+            }
+            &s[len..]
+        }
+    };
+    let (sign, rest) = match rest.strip_prefix('-') {
+        Some(rest) => (1, rest),
+        None => (-1, rest.strip_prefix('+').unwrap_or(rest)),
+    };
+    let mut fields = rest.split(':');
+    let hours = number(fields.next()?, 1, 2)?;
+    let minutes = fields.next().map_or(Some(0), |m| number(m, 2, 2))?;
+    let seconds = fields.next().map_or(Some(0), |s| number(s, 2, 2))?;
+    if fields.next().is_some() || hours > 24 || minutes > 59 || seconds > 59 {
+        return None;
+    }
+    Some(sign * (hours * 3600 + minutes * 60 + seconds))
+}
+
+/// Parses a number of seconds since the Unix epoch, written as `@N` or `@N.nnnnnnnnn`.
+///
+/// The number may be negative for times before the epoch, and may have up to nine digits after
+/// the decimal point; any further digits are ignored. For example, `@-1.5` is one and a half
+/// seconds before the epoch.
+pub fn epoch(s: &str) -> io::Result<FileTime> {
+    let rest = s
+        .strip_prefix('@')
+        .ok_or_else(|| invalid(s, "expected `@` followed by seconds"))?;
+    let (negative, rest) = match rest.strip_prefix('-') {
+        Some(rest) => (true, rest),
+        None => (false, rest.strip_prefix('+').unwrap_or(rest)),
+    };
+    let (digits, rest) = split_digits(rest);
+    let seconds = number(digits, 1, 19).ok_or_else(|| invalid(s, "invalid seconds"))?;
+    let nanoseconds = match rest {
+        "" => 0,
+        _ => rest
+            .strip_prefix('.')
+            .or_else(|| rest.strip_prefix(','))
+            .and_then(fraction)
+            .ok_or_else(|| invalid(s, "invalid fraction of a second"))?,
+    };
+    if !negative {
+        Ok(FileTime::from_unix_time(seconds, nanoseconds))
+    } else if nanoseconds == 0 {
+        Ok(FileTime::from_unix_time(-seconds, 0))
+    } else {
+        // The nanoseconds of a `FileTime` count forwards from the start of the second.
+        Ok(FileTime::from_unix_time(-seconds - 1, 1_000_000_000 - nanoseconds))
+    }
+}
+
+/// Parses a date string in the style of GNU `touch -d` and `date -d`, relative to `now`.
+///
+/// The string may contain, in any order and separated by spaces:
+///
+/// * A calendar date, such as `2017-07-01`. Without a date, the current date is used.
+/// * A time of day, such as `12:30`, `12:30:45` or `12:30:45.123456789`. Without a time of day,
+///   midnight is used if a date is given, and the current time otherwise.
+/// * A date and time of day joined by `T`, as in ISO 8601: `2017-07-01T12:30:45`.
+/// * A time zone, either directly after the time of day (`12:30Z`, `12:30+01:00`) or on its own
+///   (`UTC`, `+0100`). Without a time zone, the date and time are taken to be in UTC; use
+///   `gnu_date_in` to choose a different time zone.
+/// * Relative items, as accepted by `relative`, which are applied after everything else.
+///
+/// Alternatively, the string may start with seconds since the Unix epoch, as accepted by
+/// `epoch`, followed only by relative items.
+///
+/// For example, `2017-07-01 12:00:00`, `2017-07-01T12:00:00+02:00`, `tomorrow 09:00`,
+/// `2017-07-01 +2 days` and `@1498910400` are all accepted.
+pub fn gnu_date(s: &str, now: FileTime) -> io::Result<FileTime> {
+    gnu_date_in(s, now, Zone::Utc)
+}
+
+/// Parses a date string in the style of GNU `touch -d` and `date -d`, relative to `now`, taking
+/// dates and times without an explicit time zone to be in `zone`.
+///
+/// The current date, which is used if the string only contains a time of day, is also the
+/// current date in `zone`. See `gnu_date` for the accepted syntax.
+pub fn gnu_date_in(s: &str, now: FileTime, zone: Zone) -> io::Result<FileTime> {
+    if s.trim_start().starts_with('@') {
+        let mut words = s.split_whitespace();
+        let base = epoch(words.next().unwrap_or_default())?;
+        let rest = words.collect::<Vec<_>>().join(" ");
+        let offset = if rest.is_empty() {
+            Offset::default()
+        } else {
+            relative_offset(&tokenize(&rest)?).ok_or_else(|| invalid(s, "unrecognised items"))?
+        };
+        return apply(base, offset).ok_or_else(|| invalid(s, "time is out of range"));
+    }
+    let mut days = None;
+    let mut time = None;
+    let mut offset = None;
+    let mut rest = Vec::new();
+    for word in s.split_whitespace() {
+        let (date_part, time_part) = match word.find(['T', 't']) {
+            Some(i) if iso_date(&word[..i]).is_some() => (Some(&word[..i]), Some(&word[i + 1..])),
+            _ if iso_date(word).is_some() => (Some(word), None),
+            _ if time_of_day(word).is_some() => (None, Some(word)),
+            _ if time.is_some() && offset.is_none() && zone_offset(word).is_some() => {
+                offset = zone_offset(word);
+                continue;
+            }
+            _ => {
+                rest.push(word);
+                continue;
+            }
+        };
+        if let Some(date_part) = date_part {
+            match iso_date(date_part) {
+                Some((d, "")) if days.is_none() => days = Some(d),
+                _ => return Err(invalid(s, "invalid or repeated date")),
+            }
+        }
+        if let Some(time_part) = time_part {
+            match time_of_day(time_part) {
+                Some((t, z)) if time.is_none() => {
+                    time = Some(t);
+                    if !z.is_empty() {
+                        let z = zone_offset(z).ok_or_else(|| invalid(s, "invalid time zone"))?;
+                        offset = Some(z);
+                    }
+                }
+                _ => return Err(invalid(s, "invalid or repeated time of day")),
+            }
+        }
+    }
+    let relative = if rest.is_empty() {
+        Offset::default()
+    } else {
+        let joined = rest.join(" ");
+        relative_offset(&tokenize(&joined)?).ok_or_else(|| invalid(s, "unrecognised items"))?
+    };
+    let base = if days.is_none() && time.is_none() {
+        now
+    } else {
+        let days = days.unwrap_or_else(|| zone.today(now));
+        let time = time.unwrap_or_default();
+        let local = days
+            .checked_mul(SECS_PER_DAY)
+            .and_then(|s| s.checked_add(time.seconds))
+            .ok_or_else(|| invalid(s, "time is out of range"))?;
+        match offset {
+            Some(offset) => Zone::Fixed(offset as i32).to_utc(s, local, time.nanoseconds)?,
+            None => zone.to_utc(s, local, time.nanoseconds)?,
+        }
+    };
+    apply(base, relative).ok_or_else(|| invalid(s, "time is out of range"))
+}
+
+/// Parses a timestamp in the `[[CC]YY]MMDDhhmm[.SS]` format of POSIX `touch -t`.
+///
+/// If the century is omitted, years `69` to `99` are in the 1900s and years `00` to `68` are in
+/// the 2000s. If the year is omitted entirely, the year of `now` is used. A second of `60` is a
+/// leap second, which is treated as the start of the next minute. The timestamp is taken to be
+/// in UTC; use `posix_stamp_in` to choose a different time zone.
+pub fn posix_stamp(s: &str, now: FileTime) -> io::Result<FileTime> {
+    posix_stamp_in(s, now, Zone::Utc)
+}
+
+/// Parses a timestamp in the `[[CC]YY]MMDDhhmm[.SS]` format of POSIX `touch -t`, taking it to be
+/// in `zone`.
+///
+/// If the year is omitted, the current year in `zone` is used. See `posix_stamp` for the
+/// accepted syntax.
+pub fn posix_stamp_in(s: &str, now: FileTime, zone: Zone) -> io::Result<FileTime> {
+    let (digits, seconds) = match s.find('.') {
+        Some(i) => (&s[..i], Some(&s[i + 1..])),
+        None => (s, None),
+    };
+    if !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(invalid(s, "expected digits"));
+    }
+    let field = |i: usize| number(&digits[i..i + 2], 2, 2).unwrap_or(0);
+    let (year, rest) = match digits.len() {
+        8 => (civil_from_days(zone.today(now)).0, 0),
+        10 => {
+            let year = field(0);
+            (if year >= 69 { 1900 + year } else { 2000 + year }, 2)
+        }
+        12 => (field(0) * 100 + field(2), 4),
+        _ => return Err(invalid(s, "expected [[CC]YY]MMDDhhmm[.SS]")),
+    };
+    let second = match seconds {
+        Some(ss) => number(ss, 2, 2).ok_or_else(|| invalid(s, "invalid seconds"))?,
+        None => 0,
+    };
+    let (month, day) = (field(rest), field(rest + 2));
+    let (hour, minute) = (field(rest + 4), field(rest + 6));
+    let days = date(year, month, day).ok_or_else(|| invalid(s, "invalid date"))?;
+    if hour > 23 || minute > 59 || second > 60 {
+        return Err(invalid(s, "invalid time of day"));
+    }
+    zone.to_utc(s, days * SECS_PER_DAY + hour * 3600 + minute * 60 + second, 0)
+}
+
+/// Parses a time relative to `now`, such as `2 hours ago`, `yesterday`, `next week` or `+30m`.
+///
+/// A relative time is made up of one or more items, each of which is a unit optionally preceded
+/// by a signed number, such as `3 days`, `-2h` or `week`. Items may also be written without
+/// spaces, as in `1h30m`. A sign carries over to the items after it, so `-1h30m` is ninety
+/// minutes ago. `ago` negates the items before it, `next` and `last` stand for `+1` and `-1`,
+/// and `now`, `today`, `yesterday` and `tomorrow` are items in their own right.
+///
+/// Units of seconds, minutes, hours, days and weeks are fixed lengths of time. Months and years
+/// are calendar units: they change the month of the date but keep the day of the month and the
+/// time of day (in UTC), rolling over into the next month if that day does not exist.
+pub fn relative(s: &str, now: FileTime) -> io::Result<FileTime> {
+    let tokens = tokenize(s)?;
+    if tokens.is_empty() {
+        return Err(invalid(s, "expected a relative time"));
+    }
+    let offset = relative_offset(&tokens).ok_or_else(|| invalid(s, "expected a relative time"))?;
+    apply(now, offset).ok_or_else(|| invalid(s, "time is out of range"))
+}
@@ -0,0 +1,98 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Paths and timestamps that are converted once and reused across many updates.
+
+use Builder;
+use std::fmt::{self, Debug, Formatter};
+use std::io;
+use std::path::{Path, PathBuf};
+use sys::{EncodedPath, FileTimes, RawPath};
+
+#[derive(Clone, Debug)]
+/// A path that has already been converted into the form the operating system expects, so that
+/// it can be passed to `Builder::touch_prepared` any number of times without converting it again.
+///
+/// This suits programs that repeatedly update the same heartbeat or status files.
+pub struct PreparedPath {
+    /// The original path.
+    path: PathBuf,
+    /// The path as the operating system expects it.
+    encoded: EncodedPath,
+}
+
+/// A builder's timestamps, already converted into the form the operating system expects, so
+/// that they can be passed to `Builder::touch_with_prepared` any number of times without
+/// converting them again.
+///
+/// Operations on multiple paths, such as `Builder::touch_all`, already convert their timestamps
+/// only once; this is for callers that use `Builder::touch` in a loop instead.
+pub struct PreparedTimes {
+    /// The converted timestamps.
+    times: FileTimes,
+}
+
+impl PreparedPath {
+    #[inline]
+    /// Converts a path into the form the operating system expects.
+    ///
+    /// Fails with an error of kind `InvalidInput` if the path contains a NUL character. On
+    /// Windows, a relative path too long for the legacy path limit is resolved against the
+    /// current directory here, rather than each time it is updated.
+    pub fn new<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let path = path.as_ref();
+        Ok(Self {
+            path: path.to_path_buf(),
+            encoded: EncodedPath::new(path)?,
+        })
+    }
+
+    #[inline]
+    /// Returns the original path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    /// Returns the path as the operating system expects it.
+    pub(crate) fn as_raw(&self) -> &RawPath {
+        self.encoded.as_raw()
+    }
+}
+
+impl AsRef<Path> for PreparedPath {
+    #[inline]
+    fn as_ref(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl PreparedTimes {
+    #[inline]
+    /// Converts the timestamps that a builder sets, after clamping.
+    ///
+    /// The timestamps are captured as they are now: changing the builder's timestamps later has
+    /// no effect on them. A timestamp of `Timestamp::Now` is still read by the operating system
+    /// each time the timestamps are set.
+    pub fn new(builder: &Builder) -> Self {
+        Self {
+            times: FileTimes::from_builder(builder),
+        }
+    }
+
+    #[inline]
+    /// Returns the converted timestamps.
+    pub(crate) fn times(&self) -> &FileTimes {
+        &self.times
+    }
+}
+
+impl Debug for PreparedTimes {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("PreparedTimes").finish_non_exhaustive()
+    }
+}
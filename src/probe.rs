@@ -0,0 +1,212 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Discovering what a filesystem can do with timestamps.
+//!
+//! Filesystems differ in how precisely they store timestamps and which timestamps they store at
+//! all, and few of them report this directly. Instead, `probe` finds out by experiment: it
+//! creates a scratch file, sets timestamps on it, and reads back what was actually stored.
+
+use {read_times, Builder, CreationTarget, FileTime};
+use std::{fs, io, process};
+use std::convert::TryFrom;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
+#[cfg(windows)]
+use std::os::windows::fs::symlink_file as symlink;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::Duration;
+
+/// Distinguishes the scratch files of concurrent probes within a process.
+static NEXT_SCRATCH: AtomicUsize = AtomicUsize::new(0);
+
+/// Resolutions that filesystems commonly store timestamps with, finest first.
+const RESOLUTIONS: [Duration; 7] = [
+    Duration::from_nanos(1),
+    Duration::from_nanos(100),
+    Duration::from_micros(1),
+    Duration::from_millis(1),
+    Duration::from_millis(10),
+    Duration::from_secs(1),
+    Duration::from_secs(2),
+];
+
+/// Candidates for the earliest storable timestamp, in seconds since the Unix epoch, earliest
+/// first.
+const EARLIEST: [i64; 6] = [
+    // January 1, 1 (the earliest `SYSTEMTIME`).
+    -62_135_596_800,
+    // January 1, 1601 (the Windows epoch), plus one second.
+    -11_644_473_599,
+    // January 1, 1900.
+    -2_208_988_800,
+    // December 13, 1901 (the earliest 32-bit `time_t`).
+    -2_147_483_648,
+    // January 1, 1980 (the FAT epoch).
+    315_532_800,
+    // The Unix epoch.
+    0,
+];
+
+/// Candidates for the latest storable timestamp, in seconds since the Unix epoch, latest first.
+const LATEST: [i64; 5] = [
+    // December 31, 9999.
+    253_402_300_798,
+    // May 10, 2446 (the latest ext4 timestamp).
+    15_032_385_534,
+    // December 31, 2107 (the latest FAT timestamp).
+    4_354_819_198,
+    // February 7, 2106 (the latest unsigned 32-bit timestamp).
+    4_294_967_294,
+    // January 19, 2038 (the latest 32-bit `time_t`).
+    2_147_483_646,
+];
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// What a filesystem can do with timestamps, as discovered by `probe`.
+pub struct Capabilities {
+    /// The precision with which modification timestamps are stored.
+    resolution: Duration,
+    /// Whether the timestamps of symbolic links themselves can be set.
+    symlink_times: bool,
+    /// Whether creation timestamps can be set.
+    settable_creation_time: bool,
+    /// The earliest timestamp that could be stored.
+    earliest: FileTime,
+    /// The latest timestamp that could be stored.
+    latest: FileTime,
+}
+
+/// A scratch file that is removed when dropped.
+struct Scratch(PathBuf);
+
+impl Capabilities {
+    #[inline]
+    /// Returns the precision with which modification timestamps are stored.
+    ///
+    /// This is one of 1 nanosecond, 100 nanoseconds, 1 microsecond, 1 or 10 milliseconds, or 1
+    /// or 2 seconds, whichever is the finest that explains the stored timestamp.
+    pub fn resolution(&self) -> Duration {
+        self.resolution
+    }
+
+    #[inline]
+    /// Returns whether the timestamps of symbolic links themselves can be set, as opposed to
+    /// those of their targets.
+    ///
+    /// This is `false` if symbolic links cannot be created at all.
+    pub fn symlink_times(&self) -> bool {
+        self.symlink_times
+    }
+
+    #[inline]
+    /// Returns whether creation timestamps can be set.
+    ///
+    /// On Windows and FreeBSD, this is whether the filesystem records creation timestamps;
+    /// other platforms provide no way to set them.
+    pub fn settable_creation_time(&self) -> bool {
+        self.settable_creation_time
+    }
+
+    #[inline]
+    /// Returns the earliest timestamp that could be stored.
+    ///
+    /// Only a few well-known limits are tried, so earlier timestamps may also work.
+    pub fn earliest(&self) -> FileTime {
+        self.earliest
+    }
+
+    #[inline]
+    /// Returns the latest timestamp that could be stored.
+    ///
+    /// Only a few well-known limits are tried, so later timestamps may also work.
+    pub fn latest(&self) -> FileTime {
+        self.latest
+    }
+}
+
+impl Scratch {
+    /// Creates a new scratch path in a directory, without creating anything there.
+    fn new(dir: &Path, kind: &str) -> Self {
+        let n = NEXT_SCRATCH.fetch_add(1, Ordering::Relaxed);
+        Scratch(dir.join(format!(".nudge-probe-{}-{}-{}", process::id(), n, kind)))
+    }
+}
+
+impl Drop for Scratch {
+    #[inline]
+    fn drop(&mut self) {
+        // Removal is best-effort; the path may never have been created.
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Sets the modification timestamp of a path, returning what was actually stored.
+fn round_trip(path: &Path, time: FileTime, follow_symlinks: bool) -> io::Result<FileTime> {
+    let mut builder = Builder::new();
+    let _ = builder
+        .modified(Some(time))
+        .follow_symlinks(follow_symlinks);
+    let _ = builder.touch(path)?;
+    read_times(path, follow_symlinks).map(|t| t.modified())
+}
+
+/// Returns whether a timestamp can be stored, to within a resolution.
+fn stores(path: &Path, seconds: i64, resolution: Duration) -> bool {
+    let time = FileTime::from_unix_time(seconds, 0);
+    round_trip(path, time, true).is_ok_and(|stored| {
+        (stored.unix_nanos() - time.unix_nanos()).unsigned_abs() < resolution.as_nanos()
+    })
+}
+
+/// Returns whether the timestamps of a symbolic link itself can be set.
+fn symlink_times(dir: &Path, target: &Path) -> bool {
+    let link = Scratch::new(dir, "link");
+    let time = FileTime::from_unix_time(1_000_000_000, 0);
+    symlink(target, &link.0).is_ok()
+        && round_trip(&link.0, time, false).is_ok_and(|stored| stored == time)
+}
+
+/// Discovers what the filesystem containing a directory can do with timestamps.
+///
+/// This creates scratch files in the directory and removes them again afterwards, so the
+/// directory must be writable. Their names start with `.nudge-probe-`.
+pub fn probe<P: AsRef<Path>>(dir: P) -> io::Result<Capabilities> {
+    let dir = dir.as_ref();
+    let file = Scratch::new(dir, "file");
+    let _ = Builder::new()
+        .creation_target(CreationTarget::File)
+        .touch(&file.0)?;
+    // An odd number of seconds and every digit of nanoseconds distinguish the resolutions.
+    let time = FileTime::from_unix_time(1_000_000_001, 123_456_789);
+    let stored = round_trip(&file.0, time, true)?;
+    let error = (stored.unix_nanos() - time.unix_nanos()).unsigned_abs();
+    let resolution = RESOLUTIONS
+        .iter()
+        .cloned()
+        .find(|r| error < r.as_nanos())
+        .unwrap_or_else(|| Duration::from_nanos(u64::try_from(error).unwrap_or(u64::MAX)));
+    let earliest = EARLIEST
+        .iter()
+        .cloned()
+        .find(|&s| stores(&file.0, s, resolution))
+        .unwrap_or(0);
+    let latest = LATEST
+        .iter()
+        .cloned()
+        .find(|&s| stores(&file.0, s, resolution))
+        .unwrap_or(0);
+    Ok(Capabilities {
+        resolution,
+        symlink_times: symlink_times(dir, &file.0),
+        settable_creation_time: cfg!(any(windows, target_os = "freebsd")) &&
+            read_times(&file.0, true)?.created().is_some(),
+        earliest: FileTime::from_unix_time(earliest, 0),
+        latest: FileTime::from_unix_time(latest, 0),
+    })
+}
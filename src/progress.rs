@@ -0,0 +1,123 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Progress reporting for operations on multiple paths.
+
+use std::fmt::{self, Debug, Formatter};
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+#[derive(Clone, Copy, Debug)]
+/// A snapshot of how far an operation on multiple paths has got.
+pub struct Progress<'a> {
+    /// How many paths have been processed so far.
+    processed: usize,
+    /// How many of the processed paths could not be updated.
+    failed: usize,
+    /// The path that was most recently processed.
+    current: &'a Path,
+    /// How long the operation has been running.
+    elapsed: Duration,
+}
+
+#[derive(Clone)]
+/// Receives progress snapshots at regular intervals.
+pub struct ProgressSink {
+    /// The function that receives each snapshot.
+    callback: Arc<dyn Fn(&Progress) + Send + Sync>,
+    /// The minimum time between snapshots.
+    interval: Duration,
+}
+
+impl<'a> Progress<'a> {
+    #[inline]
+    /// Creates a new progress snapshot.
+    pub(crate) fn new(
+        processed: usize,
+        failed: usize,
+        current: &'a Path,
+        elapsed: Duration,
+    ) -> Self {
+        Self {
+            processed,
+            failed,
+            current,
+            elapsed,
+        }
+    }
+
+    #[inline]
+    /// Returns how many paths have been processed so far, including failures.
+    pub fn processed(&self) -> usize {
+        self.processed
+    }
+
+    #[inline]
+    /// Returns how many of the processed paths could not be updated.
+    pub fn failed(&self) -> usize {
+        self.failed
+    }
+
+    #[inline]
+    /// Returns the path that was most recently processed.
+    pub fn current_path(&self) -> &'a Path {
+        self.current
+    }
+
+    #[inline]
+    /// Returns how long the operation has been running.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    #[inline]
+    /// Returns the average number of paths processed per second so far.
+    pub fn rate(&self) -> f64 {
+        let secs = self.elapsed.as_secs_f64();
+        if secs > 0.0 {
+            self.processed as f64 / secs
+        } else {
+            0.0
+        }
+    }
+}
+
+impl ProgressSink {
+    #[inline]
+    /// Creates a new sink that passes snapshots to a function at most once per interval.
+    pub fn new<F>(interval: Duration, callback: F) -> Self
+    where
+        F: Fn(&Progress) + Send + Sync + 'static,
+    {
+        Self {
+            callback: Arc::new(callback),
+            interval,
+        }
+    }
+
+    #[inline]
+    /// Returns the minimum time between snapshots.
+    pub fn interval(&self) -> Duration {
+        self.interval
+    }
+
+    #[inline]
+    /// Passes a snapshot to the sink's function.
+    pub(crate) fn report(&self, progress: &Progress) {
+        (self.callback)(progress)
+    }
+}
+
+impl Debug for ProgressSink {
+    #[inline]
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("ProgressSink")
+            .field("interval", &self.interval)
+            .finish()
+    }
+}
@@ -0,0 +1,293 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Reports for operations on multiple paths.
+
+use Outcome;
+use std::{io, slice, vec};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+#[derive(Debug)]
+/// The result of updating the timestamps for a single path as part of a batch.
+pub struct ReportEntry {
+    /// The path that was updated.
+    path: PathBuf,
+    /// What happened to the path, or why updating it failed.
+    result: io::Result<Outcome>,
+}
+
+#[derive(Debug, Default)]
+/// A record of what happened to every path in a batch operation.
+pub struct Report {
+    /// The result for each path, in the order they were processed.
+    entries: Vec<ReportEntry>,
+    /// How long the operation took.
+    elapsed: Duration,
+    /// Whether the operation was cancelled.
+    cancelled: bool,
+}
+
+#[derive(Debug)]
+/// Every failure that occurred during a batch operation, collected into a single error.
+pub struct MultiError {
+    /// Each path that could not be updated, and why.
+    errors: Vec<(PathBuf, io::Error)>,
+}
+
+impl ReportEntry {
+    #[inline]
+    /// Creates a new report entry.
+    pub(crate) fn new(path: PathBuf, result: io::Result<Outcome>) -> Self {
+        Self { path, result }
+    }
+
+    #[inline]
+    /// Returns the path this entry refers to.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    /// Returns what happened to the path, or why updating it failed.
+    pub fn result(&self) -> &io::Result<Outcome> {
+        &self.result
+    }
+
+    #[inline]
+    /// Returns the outcome for the path, or `None` if updating it failed.
+    pub fn outcome(&self) -> Option<Outcome> {
+        self.result.as_ref().ok().cloned()
+    }
+
+    #[inline]
+    /// Returns the error that occurred while updating the path, if any.
+    pub fn error(&self) -> Option<&io::Error> {
+        self.result.as_ref().err()
+    }
+
+    #[inline]
+    /// Consumes the entry, returning its path and result.
+    pub fn into_parts(self) -> (PathBuf, io::Result<Outcome>) {
+        (self.path, self.result)
+    }
+}
+
+impl Report {
+    #[inline]
+    /// Creates a new report from a list of entries, the time taken to produce them, and whether
+    /// the operation was cancelled.
+    pub(crate) fn new(entries: Vec<ReportEntry>, elapsed: Duration, cancelled: bool) -> Self {
+        Self {
+            entries,
+            elapsed,
+            cancelled,
+        }
+    }
+
+    #[inline]
+    /// Returns the entries in this report, in the order their paths were processed.
+    pub fn entries(&self) -> &[ReportEntry] {
+        &self.entries
+    }
+
+    #[inline]
+    /// Returns an iterator over the entries in this report.
+    pub fn iter(&self) -> slice::Iter<'_, ReportEntry> {
+        self.entries.iter()
+    }
+
+    #[inline]
+    /// Returns the number of paths in this report.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    #[inline]
+    /// Returns whether this report contains no paths.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    #[inline]
+    /// Returns the number of existing paths whose timestamps were updated, including read-only
+    /// paths.
+    pub fn updated(&self) -> usize {
+        self.count(|o| o == Outcome::Updated || o == Outcome::UpdatedReadOnly)
+    }
+
+    #[inline]
+    /// Returns the number of paths that were created, including targets of symbolic links.
+    pub fn created(&self) -> usize {
+        self.count(|o| o == Outcome::Created || o == Outcome::CreatedSymlinkTarget)
+    }
+
+    #[inline]
+    /// Returns the number of paths that were skipped.
+    pub fn skipped(&self) -> usize {
+        self.count(|o| o == Outcome::Skipped)
+    }
+
+    #[inline]
+    /// Returns the number of paths that could not be updated.
+    pub fn failed(&self) -> usize {
+        self.entries.iter().filter(|e| e.result.is_err()).count()
+    }
+
+    #[inline]
+    /// Returns whether every path was processed successfully.
+    pub fn is_success(&self) -> bool {
+        self.entries.iter().all(|e| e.result.is_ok())
+    }
+
+    #[inline]
+    /// Returns an iterator over the paths that could not be updated, and why.
+    pub fn errors(&self) -> impl Iterator<Item = (&Path, &io::Error)> {
+        self.entries
+            .iter()
+            .filter_map(|e| e.error().map(|err| (e.path(), err)))
+    }
+
+    #[inline]
+    /// Returns how long the operation took.
+    pub fn elapsed(&self) -> Duration {
+        self.elapsed
+    }
+
+    #[inline]
+    /// Returns whether the operation was cancelled before it finished.
+    ///
+    /// If so, the entries show how far the operation got: paths that are not listed were not
+    /// updated.
+    pub fn is_cancelled(&self) -> bool {
+        self.cancelled
+    }
+
+    /// Converts this report into a `Result`, which is an error if any path could not be updated.
+    ///
+    /// The error only contains the failures; use the report itself to inspect the outcome for
+    /// every path.
+    pub fn into_result(self) -> Result<Self, MultiError> {
+        if self.is_success() {
+            return Ok(self);
+        }
+        let errors = self.entries
+            .into_iter()
+            .filter_map(|e| match e.result {
+                Ok(_) => None,
+                Err(err) => Some((e.path, err)),
+            })
+            .collect();
+        Err(MultiError { errors })
+    }
+
+    #[inline]
+    /// Counts the successful entries whose outcome matches a predicate.
+    fn count<F: Fn(Outcome) -> bool>(&self, f: F) -> usize {
+        self.entries
+            .iter()
+            .filter(|e| e.outcome().is_some_and(&f))
+            .count()
+    }
+}
+
+impl IntoIterator for Report {
+    type Item = ReportEntry;
+    type IntoIter = vec::IntoIter<ReportEntry>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a Report {
+    type Item = &'a ReportEntry;
+    type IntoIter = slice::Iter<'a, ReportEntry>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.entries.iter()
+    }
+}
+
+impl MultiError {
+    #[inline]
+    /// Returns the number of paths that could not be updated.
+    pub fn len(&self) -> usize {
+        self.errors.len()
+    }
+
+    #[inline]
+    /// Returns whether this error contains no failures.
+    ///
+    /// This is never the case for an error returned by `Report::into_result`.
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    #[inline]
+    /// Returns an iterator over each path that could not be updated, and why.
+    pub fn iter(&self) -> slice::Iter<'_, (PathBuf, io::Error)> {
+        self.errors.iter()
+    }
+}
+
+impl Display for MultiError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        // The first error is left to `source`, so that it is not printed twice in a chain.
+        match self.errors.len() {
+            0 => f.write_str("no paths failed to update"),
+            1 => write!(f, "could not update {}", self.errors[0].0.display()),
+            n => write!(
+                f,
+                "could not update {} paths, starting with {}",
+                n,
+                self.errors[0].0.display()
+            ),
+        }
+    }
+}
+
+impl Error for MultiError {
+    #[inline]
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self.errors.first() {
+            Some((_, e)) => Some(e),
+            None => None,
+        }
+    }
+}
+
+impl From<MultiError> for io::Error {
+    #[inline]
+    fn from(err: MultiError) -> Self {
+        io::Error::other(err)
+    }
+}
+
+impl IntoIterator for MultiError {
+    type Item = (PathBuf, io::Error);
+    type IntoIter = vec::IntoIter<(PathBuf, io::Error)>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.into_iter()
+    }
+}
+
+impl<'a> IntoIterator for &'a MultiError {
+    type Item = &'a (PathBuf, io::Error);
+    type IntoIter = slice::Iter<'a, (PathBuf, io::Error)>;
+
+    #[inline]
+    fn into_iter(self) -> Self::IntoIter {
+        self.errors.iter()
+    }
+}
@@ -0,0 +1,84 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Retrying updates that fail for transient reasons.
+
+use error;
+use std::io;
+use std::thread;
+use std::time::Duration;
+use sys;
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// How often, and how patiently, to retry updating a path that is temporarily locked.
+///
+/// On Windows, antivirus scanners and search indexers briefly open files without sharing them,
+/// which makes opening the file fail with a sharing or lock violation. Such failures are retried
+/// after a delay that doubles with each attempt, up to a maximum. Other platforms do not lock
+/// open files, so nothing is retried there.
+pub struct RetryPolicy {
+    /// The maximum number of times to retry after the first attempt.
+    max_retries: u32,
+    /// How long to wait before the first retry.
+    initial_delay: Duration,
+    /// The longest to wait between two attempts.
+    max_delay: Duration,
+}
+
+impl RetryPolicy {
+    #[inline]
+    /// Creates a new policy that retries up to `max_retries` times, waiting `initial_delay`
+    /// before the first retry.
+    ///
+    /// By default, the delay doubles without limit.
+    pub fn new(max_retries: u32, initial_delay: Duration) -> Self {
+        Self {
+            max_retries,
+            initial_delay,
+            max_delay: Duration::MAX,
+        }
+    }
+
+    #[inline]
+    /// Specifies the longest to wait between two attempts.
+    pub fn max_delay(mut self, delay: Duration) -> Self {
+        self.max_delay = delay;
+        self
+    }
+
+    #[inline]
+    /// Returns the maximum number of times to retry after the first attempt.
+    pub fn max_retries(&self) -> u32 {
+        self.max_retries
+    }
+
+    #[inline]
+    /// Returns how long to wait before the first retry.
+    pub fn initial_delay(&self) -> Duration {
+        self.initial_delay
+    }
+
+    /// Runs an operation, retrying it while it fails for a transient reason.
+    ///
+    /// Once the retries are used up, the last error is returned.
+    pub(crate) fn run<T, F: FnMut() -> io::Result<T>>(&self, mut f: F) -> io::Result<T> {
+        let mut delay = self.initial_delay.min(self.max_delay);
+        let mut retries = 0;
+        loop {
+            match f() {
+                Err(ref e)
+                    if retries < self.max_retries && sys::is_transient(error::original(e)) =>
+                {
+                    thread::sleep(delay);
+                    delay = delay.saturating_mul(2).min(self.max_delay);
+                    retries += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
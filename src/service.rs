@@ -0,0 +1,101 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! A long-lived pool of threads for updating timestamps.
+
+use {Builder, ReportEntry};
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::thread::{self, JoinHandle};
+
+#[derive(Debug)]
+/// A pool of worker threads that update the timestamps for paths as they are submitted.
+///
+/// Every path is updated using the same builder options, as if by `Builder::touch`. Options
+/// that only apply to operations on multiple paths, such as deduplication and rate limits, have
+/// no effect.
+///
+/// Dropping the service waits for every submitted path to be updated.
+pub struct TouchService {
+    /// Queues paths for the worker threads.
+    sender: Option<Sender<PathBuf>>,
+    /// The worker threads.
+    workers: Vec<JoinHandle<()>>,
+}
+
+impl TouchService {
+    /// Starts a new service with the given number of worker threads, or one per available core
+    /// if `threads` is zero.
+    ///
+    /// Also returns a receiver for the result of each submitted path, in the order they were
+    /// updated. Results accumulate until they are received, so the receiver should either be
+    /// drained regularly or dropped, in which case results are discarded.
+    pub fn new(builder: Builder, threads: usize) -> (Self, Receiver<ReportEntry>) {
+        let threads = if threads == 0 {
+            thread::available_parallelism().map_or(1, |n| n.get())
+        } else {
+            threads
+        };
+        let builder = Arc::new(builder);
+        let (sender, paths) = mpsc::channel::<PathBuf>();
+        let paths = Arc::new(Mutex::new(paths));
+        let (results, receiver) = mpsc::channel();
+        let workers = (0..threads)
+            .map(|_| {
+                let builder = Arc::clone(&builder);
+                let paths = Arc::clone(&paths);
+                let results = results.clone();
+                thread::spawn(move || loop {
+                    let path = match paths.lock() {
+                        Ok(paths) => paths.recv(),
+                        Err(e) => e.into_inner().recv(),
+                    };
+                    let path = match path {
+                        Ok(p) => p,
+                        Err(_) => return,
+                    };
+                    let result = builder.touch(&path);
+                    // The receiver may have been dropped, in which case results are discarded.
+                    let _ = results.send(ReportEntry::new(path, result));
+                })
+            })
+            .collect();
+        let service = Self {
+            sender: Some(sender),
+            workers,
+        };
+        (service, receiver)
+    }
+
+    #[inline]
+    /// Submits a path to be updated by one of the worker threads.
+    ///
+    /// This never blocks: the path is queued until a worker thread is free.
+    pub fn submit<P: Into<PathBuf>>(&self, path: P) {
+        if let Some(ref sender) = self.sender {
+            // Worker threads only stop once the sender is dropped.
+            let _ = sender.send(path.into());
+        }
+    }
+
+    #[inline]
+    /// Stops accepting paths, and waits for every submitted path to be updated.
+    pub fn shutdown(self) {}
+}
+
+impl Drop for TouchService {
+    #[inline]
+    fn drop(&mut self) {
+        // Dropping the sender tells the worker threads to stop once the queue is empty.
+        drop(self.sender.take());
+        for worker in self.workers.drain(..) {
+            // A worker thread only panics if `touch` does, which is already reported.
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,53 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Refusing configurations that would leave every path unchanged.
+
+use {Builder, CreationTarget};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+/// An error returned when `Builder::strict` is set, but the builder sets no timestamps and
+/// creates nothing.
+///
+/// This is converted into an `io::Error` of kind `InvalidInput`, from which it can be recovered
+/// using `Error::downcast_ref`.
+pub struct NothingToDo;
+
+/// Checks that a builder changes something if `strict` was given.
+pub(crate) fn check(builder: &Builder) -> io::Result<()> {
+    if !builder.strict {
+        return Ok(());
+    }
+    #[cfg(feature = "change-time")]
+    let changed = builder.changed.is_some();
+    #[cfg(not(feature = "change-time"))]
+    let changed = false;
+    let sets_times = builder.accessed.is_some() || builder.modified.is_some() ||
+        builder.created.is_some() || changed;
+    match builder.creation_target {
+        CreationTarget::None if !sets_times => Err(NothingToDo.into()),
+        _ => Ok(()),
+    }
+}
+
+impl Display for NothingToDo {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str("no timestamp is set and nothing is created, so no path would be changed")
+    }
+}
+
+impl Error for NothingToDo {}
+
+impl From<NothingToDo> for io::Error {
+    #[inline]
+    fn from(err: NothingToDo) -> Self {
+        io::Error::new(io::ErrorKind::InvalidInput, err)
+    }
+}
@@ -7,12 +7,48 @@
 
 //! Platform-specific utilities.
 
-#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), not(feature = "rustix")))]
 mod posix;
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), feature = "rustix"))]
+mod rustix;
 #[cfg(windows)]
 mod windows;
 
-#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios"))))]
-pub use self::posix::*;
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), not(feature = "rustix")))]
+pub use self::posix::{coarse_now, failure, file_id, filesystem, handle_limit, is_hidden,
+                      is_junction, is_symlink_error, is_transient, read_times, touch_now,
+                      DirHandle, EncodedPath, FileId, FileTimes, RawPath, RESOLUTION};
+#[cfg(all(unix, not(any(target_os = "macos", target_os = "ios")), feature = "rustix"))]
+pub use self::rustix::{coarse_now, failure, file_id, filesystem, handle_limit, is_hidden,
+                       is_junction, is_symlink_error, is_transient, read_times, touch_now,
+                       DirHandle, EncodedPath, FileId, FileTimes, RawPath, RESOLUTION};
 #[cfg(windows)]
-pub use self::windows::*;
+pub use self::windows::{coarse_now, failure, file_id, filesystem, handle_limit, is_hidden,
+                        is_junction, is_symlink_error, is_transient, read_times, touch_now,
+                        DirHandle, EncodedPath, FileId, FileTimes, RawPath, RESOLUTION};
+#[cfg(all(test, target_os = "linux", not(feature = "rustix")))]
+pub use self::posix::{close, retry_interrupted, stat_times};
+#[cfg(all(test, target_os = "linux", feature = "rustix"))]
+pub use self::rustix::{close, retry_interrupted, stat_times};
+
+/// The most file descriptors or handles that updating a single path may hold open at once.
+///
+/// Listing a directory and synchronising a path to storage need one each, and creating a
+/// missing path may also open its parent directory.
+pub const HANDLES_PER_OPERATION: usize = 3;
+
+#[inline]
+/// Returns how many paths may be updated at once without exhausting the process's file
+/// descriptors or handles, if that is limited.
+///
+/// Half of the limit is left for the rest of the process, which may hold many descriptors
+/// open itself.
+pub fn max_concurrent_operations() -> Option<usize> {
+    operations_within(handle_limit())
+}
+
+#[inline]
+/// Returns how many paths may be updated at once within a limit on file descriptors or handles.
+pub fn operations_within(handle_limit: Option<usize>) -> Option<usize> {
+    handle_limit.map(|limit| (limit / 2 / HANDLES_PER_OPERATION).max(1))
+}
@@ -9,107 +9,602 @@
 
 #![allow(unsafe_code)]
 
-use {Builder, CreationTarget};
-use libc::{self, c_char, c_int, c_long, time_t, timespec, AT_FDCWD, AT_SYMLINK_NOFOLLOW, O_CREAT,
-           O_TRUNC, O_WRONLY, S_IRGRP, S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR, UTIME_OMIT};
-use std::{io, iter, mem};
+use {Builder, CreationTarget, DanglingSymlinks, Failure, FileTime, Operation, Outcome,
+     Timestamp, TimestampKind, TimestampOutOfRange, Timestamps, UnsupportedTimestamp};
+use error::{context, invalid_path};
+use granularity::Filesystem;
+use libc::{self, c_char, c_int, c_long, time_t, timespec, AT_FDCWD, AT_SYMLINK_NOFOLLOW, EDQUOT,
+           EINTR, ELOOP, ENOSPC, EPERM, EROFS, O_ACCMODE, O_CLOEXEC, O_CREAT, O_DIRECTORY,
+           O_EXCL, O_NOFOLLOW, O_NONBLOCK, O_RDONLY, O_WRONLY, S_IFLNK, S_IFMT, S_IRGRP,
+           S_IROTH, S_IRUSR, S_IWGRP, S_IWOTH, S_IWUSR, UTIME_NOW, UTIME_OMIT};
+#[cfg(target_os = "linux")]
+use libc::{AT_EMPTY_PATH, EINVAL};
+use smallvec::SmallVec;
+use std::{fs, io, iter, mem, ptr};
+use std::convert::TryFrom;
+use std::ffi::{CStr, OsStr};
 use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+#[cfg(target_os = "linux")]
+use std::os::unix::io::{AsRawFd, BorrowedFd};
 use std::path::Path;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::Duration;
 
 /// A safe wrapper around a file descriptor.
 struct FileHandle(c_int);
 
+/// An open directory, whose entries can be updated without resolving their full paths.
+pub struct DirHandle(FileHandle);
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// Uniquely identifies a file by its device and inode numbers.
+pub struct FileId(u64, u64);
+
+#[derive(Clone, Debug)]
+/// A path converted into a C string once, so that it can be passed to FFI calls repeatedly.
+pub struct EncodedPath(CPath);
+
+/// A path in the form FFI calls expect, borrowed from an `EncodedPath` or the caller.
+pub type RawPath = CStr;
+
+/// A path converted into a C string, which is kept on the stack unless it is unusually long.
+type CPath = SmallVec<[c_char; INLINE_PATH_LEN]>;
+
+impl FileId {
+    #[inline]
+    /// Returns the device number of the filesystem containing the file.
+    pub fn device(&self) -> u64 {
+        self.0
+    }
+}
+
+/// How many bytes a path may take up, including its terminating NUL, before converting it into
+/// a C string needs a heap allocation.
+const INLINE_PATH_LEN: usize = 256;
+
+/// The resolution of timestamps on this platform.
+pub const RESOLUTION: Duration = Duration::from_nanos(1);
+
+#[cfg(target_os = "linux")]
+/// Stops the access timestamp of an opened file from being updated by reading it.
+const O_NOATIME: c_int = libc::O_NOATIME;
+
+#[cfg(not(target_os = "linux"))]
+/// `O_NOATIME` is only available on Linux, so nothing is passed elsewhere.
+const O_NOATIME: c_int = 0;
+
+#[cfg(target_os = "linux")]
+/// The `statfs` magic number of FAT filesystems.
+const MSDOS_SUPER_MAGIC: i128 = 0x4d44;
+
+#[cfg(target_os = "linux")]
+/// The `statfs` magic number of exFAT filesystems.
+const EXFAT_SUPER_MAGIC: i128 = 0x2011_bab0;
+
 /// Holds Unix timestamps for a file.
-struct FileTimes([timespec; 2]);
+pub struct FileTimes {
+    /// The access and modification timestamps, in the order `utimensat` expects.
+    times: [timespec; 2],
+    /// A requested timestamp that cannot be set here, if any.
+    unsupported: Option<TimestampKind>,
+    #[cfg(target_os = "freebsd")]
+    /// The creation timestamp, which is set separately.
+    created: timespec,
+}
 
 #[inline]
 #[cfg_attr(feature = "clippy", allow(cast_possible_wrap))]
 /// Converts a path into a C string for use in FFI calls.
-fn into_c_string<P: AsRef<Path>>(path: P) -> Vec<c_char> {
-    path.as_ref()
-        .as_os_str()
-        .as_bytes()
-        .iter()
-        .map(|c| *c as c_char)
-        .chain(iter::once(0))
-        .collect()
+///
+/// Paths shorter than `INLINE_PATH_LEN` bytes are converted without allocating.
+///
+/// Fails with an error of kind `InvalidInput` if the path contains a NUL byte, which would
+/// otherwise end it early.
+fn into_c_string<P: AsRef<OsStr>>(path: P) -> io::Result<CPath> {
+    let bytes = path.as_ref().as_bytes();
+    if bytes.contains(&0) {
+        return Err(invalid_path("path contains an interior NUL byte"));
+    }
+    Ok(bytes.iter().map(|c| *c as c_char).chain(iter::once(0)).collect())
 }
 
 #[inline]
-/// Safely wraps the POSIX `futimens` function.
-fn futimens(fd: &FileHandle, times: *const timespec) -> io::Result<()> {
-    if unsafe { libc::futimens(fd.0, times) } == 0 {
-        Ok(())
+/// Returns a value that uniquely identifies the file a path refers to.
+pub fn file_id(path: &Path, follow_symlinks: bool) -> io::Result<FileId> {
+    let metadata = if follow_symlinks {
+        fs::metadata(path)
     } else {
-        Err(io::Error::last_os_error())
+        fs::symlink_metadata(path)
+    };
+    metadata.map(|m| FileId(m.dev(), m.ino()))
+}
+
+#[inline]
+/// Sets the access and modification timestamps of an existing path to the current time.
+pub fn touch_now(path: &Path) -> io::Result<()> {
+    let p = into_c_string(path)?;
+    retry_interrupted(|| unsafe { libc::utimensat(AT_FDCWD, p.as_ptr(), ptr::null(), 0) })
+        .map(|_| ())
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+#[inline]
+/// Returns the current time from `CLOCK_REALTIME_COARSE`.
+pub fn coarse_now() -> FileTime {
+    let mut now = mem::MaybeUninit::uninit();
+    if unsafe { libc::clock_gettime(libc::CLOCK_REALTIME_COARSE, now.as_mut_ptr()) } != 0 {
+        return FileTime::now();
     }
+    FileTime::from(unsafe { now.assume_init() })
 }
 
-// Some platforms have a buggy implementation of `utimensat` that succeeds unconditionally
-// if both timestamps are omitted.
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+#[inline]
+/// Returns the current time, since there is no coarse clock here.
+pub fn coarse_now() -> FileTime {
+    FileTime::now()
+}
+
+#[inline]
+#[cfg_attr(feature = "clippy", allow(cast_possible_truncation))]
+/// Returns how many file descriptors this process may open, if there is a practical limit.
+pub fn handle_limit() -> Option<usize> {
+    let mut limit = mem::MaybeUninit::uninit();
+    if unsafe { libc::getrlimit(libc::RLIMIT_NOFILE, limit.as_mut_ptr()) } != 0 {
+        return None;
+    }
+    let limit = unsafe { limit.assume_init() };
+    if limit.rlim_cur == libc::RLIM_INFINITY {
+        None
+    } else {
+        Some(limit.rlim_cur as usize)
+    }
+}
+
+#[inline]
+/// Returns whether an error may go away if the operation is retried.
+///
+/// Open files are never locked against updating their timestamps, so no error is transient.
+pub fn is_transient(_: &io::Error) -> bool {
+    false
+}
+
+#[inline]
+/// Returns whether an error number means that a symbolic link was not followed.
+///
+/// `O_NOFOLLOW` makes `open` fail with `ELOOP` if the path is a symbolic link.
+pub fn is_symlink_error(code: i32) -> bool {
+    code == ELOOP
+}
+
+#[inline]
+/// Classifies a POSIX error number.
+pub fn failure(code: i32) -> Failure {
+    match code {
+        EROFS => Failure::ReadOnlyFilesystem,
+        ENOSPC => Failure::NoSpace,
+        EDQUOT => Failure::QuotaExceeded,
+        _ => Failure::Other,
+    }
+}
 
 #[cfg(target_os = "linux")]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    let p = into_c_string(path)?;
+    let mut buf = mem::MaybeUninit::<libc::statfs>::uninit();
+    let _ = retry_interrupted(|| unsafe { libc::statfs(p.as_ptr(), buf.as_mut_ptr()) })?;
+    // The type of `f_type` differs between architectures.
+    match i128::from(unsafe { buf.assume_init() }.f_type) {
+        MSDOS_SUPER_MAGIC => Ok(Filesystem::Fat),
+        EXFAT_SUPER_MAGIC => Ok(Filesystem::ExFat),
+        _ => Ok(Filesystem::Other),
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    let p = into_c_string(path)?;
+    let mut buf = mem::MaybeUninit::<libc::statfs>::uninit();
+    let _ = retry_interrupted(|| unsafe { libc::statfs(p.as_ptr(), buf.as_mut_ptr()) })?;
+    let name = unsafe { buf.assume_init() }.f_fstypename;
+    let name = name.iter().take_while(|&&c| c != 0).map(|&c| c as u8);
+    match name.collect::<Vec<u8>>().as_slice() {
+        b"msdosfs" => Ok(Filesystem::Fat),
+        b"exfat" => Ok(Filesystem::ExFat),
+        _ => Ok(Filesystem::Other),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
 #[inline]
-/// Safely wraps the POSIX `utimensat` function.
-fn utimensat(path: *const c_char, times: *const timespec, flag: c_int) -> io::Result<()> {
-    unsafe {
-        if times.is_null() ||
-            ((*times).tv_nsec == UTIME_OMIT && (*times.offset(1)).tv_nsec == UTIME_OMIT)
-        {
-            let mut st = mem::uninitialized();
-            if libc::fstatat(AT_FDCWD, path, &mut st, flag) != 0 {
-                return Err(io::Error::last_os_error());
+/// Returns what kind of filesystem contains a path, following symbolic links.
+///
+/// Filesystems are only identified on Linux and FreeBSD, so every filesystem is reported as
+/// `Other` elsewhere.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    fs::metadata(path).map(|_| Filesystem::Other)
+}
+
+#[inline]
+/// Returns whether a path is hidden, i.e. whether its name starts with a `.`.
+pub fn is_hidden(path: &Path, _: &fs::Metadata) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_bytes().first() == Some(&b'.'))
+}
+
+#[inline]
+/// Returns whether a path is an NTFS junction.
+///
+/// Junctions only exist on Windows.
+pub fn is_junction(_: &Path) -> bool {
+    false
+}
+
+/// Reads every timestamp of a path using `statx`, which also reports when the path was created.
+///
+/// Returns `None` if `statx` is unavailable, in which case `stat` should be used instead.
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+fn statx_times(path: &Path, follow_symlinks: bool) -> Option<io::Result<Timestamps>> {
+    let p = match into_c_string(path) {
+        Ok(p) => p,
+        Err(e) => return Some(Err(e)),
+    };
+    let flags = if follow_symlinks {
+        libc::AT_STATX_SYNC_AS_STAT
+    } else {
+        libc::AT_STATX_SYNC_AS_STAT | AT_SYMLINK_NOFOLLOW
+    };
+    let mask = libc::STATX_ATIME | libc::STATX_MTIME | libc::STATX_CTIME | libc::STATX_BTIME;
+    let mut buf = mem::MaybeUninit::<libc::statx>::uninit();
+    let result = retry_interrupted(|| unsafe {
+        libc::statx(AT_FDCWD, p.as_ptr(), flags, mask, buf.as_mut_ptr())
+    });
+    if let Err(e) = result {
+        // Old kernels and some sandboxes reject `statx` outright.
+        return match e.raw_os_error() {
+            Some(libc::ENOSYS) | Some(EPERM) => None,
+            _ => Some(Err(e)),
+        };
+    }
+    let buf = unsafe { buf.assume_init() };
+    let time = |t: libc::statx_timestamp| FileTime::from_unix_time(t.tv_sec, t.tv_nsec);
+    let created = if buf.stx_mask & libc::STATX_BTIME != 0 {
+        Some(time(buf.stx_btime))
+    } else {
+        None
+    };
+    Some(Ok(Timestamps::new(
+        time(buf.stx_atime),
+        time(buf.stx_mtime),
+        time(buf.stx_ctime),
+        created,
+    )))
+}
+
+#[cfg(not(all(target_os = "linux", any(target_env = "gnu", target_env = "musl"))))]
+#[inline]
+/// Reads every timestamp of a path using `statx`, which is only available on Linux.
+fn statx_times(_: &Path, _: bool) -> Option<io::Result<Timestamps>> {
+    None
+}
+
+/// Reads every timestamp of a path, optionally following symbolic links.
+pub fn read_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    match statx_times(path, follow_symlinks) {
+        Some(result) => result,
+        None => stat_times(path, follow_symlinks),
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Reads the timestamps of a path using `fstatat`, for kernels without `statx`.
+///
+/// `stat` does not report when a path was created, so no creation timestamp is returned.
+pub fn stat_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    let p = into_c_string(path)?;
+    let flag = if follow_symlinks {
+        0
+    } else {
+        AT_SYMLINK_NOFOLLOW
+    };
+    let st = stat_at(AT_FDCWD, p.as_ptr(), flag)?;
+    // The types of these fields differ between architectures.
+    let time = |seconds: time_t, nanoseconds: c_long| {
+        FileTime::from_unix_nanos(i128::from(seconds) * 1_000_000_000 + i128::from(nanoseconds))
+    };
+    Ok(Timestamps::new(
+        time(st.st_atime, st.st_atime_nsec),
+        time(st.st_mtime, st.st_mtime_nsec),
+        time(st.st_ctime, st.st_ctime_nsec),
+        None,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+/// Reads the timestamps of a path, including when it was created where the platform records it.
+fn stat_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    let metadata = if follow_symlinks {
+        fs::metadata(path)?
+    } else {
+        fs::symlink_metadata(path)?
+    };
+    let time = |seconds: i64, nanoseconds: i64| {
+        FileTime::from_unix_time(seconds, u32::try_from(nanoseconds).unwrap_or(0))
+    };
+    Ok(Timestamps::new(
+        time(metadata.atime(), metadata.atime_nsec()),
+        time(metadata.mtime(), metadata.mtime_nsec()),
+        time(metadata.ctime(), metadata.ctime_nsec()),
+        metadata.created().ok().map(FileTime::from),
+    ))
+}
+
+#[inline]
+/// Calls a function that returns `-1` and sets `errno` on failure, retrying it for as long as it
+/// is interrupted by a signal.
+pub fn retry_interrupted<F: FnMut() -> c_int>(mut f: F) -> io::Result<c_int> {
+    loop {
+        match f() {
+            -1 => {
+                let e = io::Error::last_os_error();
+                if e.raw_os_error() != Some(EINTR) {
+                    return Err(e);
+                }
             }
+            result => return Ok(result),
         }
-        if libc::utimensat(AT_FDCWD, path, times, flag) == 0 {
-            Ok(())
-        } else {
-            Err(io::Error::last_os_error())
+    }
+}
+
+#[inline]
+/// Safely wraps the POSIX `futimens` function.
+fn futimens(fd: c_int, times: *const timespec) -> io::Result<()> {
+    retry_interrupted(|| unsafe { libc::futimens(fd, times) }).map(|_| ())
+}
+
+#[inline]
+/// Safely wraps the POSIX `close` function.
+pub fn close(fd: c_int) -> io::Result<()> {
+    // `close` must not be retried when it is interrupted, since the descriptor may already have
+    // been released and reused by another thread; it is simply treated as closed.
+    if unsafe { libc::close(fd) } != 0 {
+        let e = io::Error::last_os_error();
+        if e.raw_os_error() != Some(EINTR) {
+            return Err(e);
         }
     }
+    Ok(())
+}
+
+#[inline]
+/// Safely wraps the POSIX `fsync` function.
+fn fsync(fd: c_int) -> io::Result<()> {
+    retry_interrupted(|| unsafe { libc::fsync(fd) }).map(|_| ())
+}
+
+#[inline]
+/// Synchronises an existing path to storage.
+///
+/// Symbolic links that are not followed cannot be opened, and are skipped.
+fn sync_path(
+    dirfd: c_int,
+    path: *const c_char,
+    follow_symlinks: bool,
+    flags: c_int,
+) -> io::Result<()> {
+    let flags = if follow_symlinks { flags } else { flags | O_NOFOLLOW };
+    match FileHandle::open_at(dirfd, path, O_RDONLY | O_NONBLOCK | flags) {
+        Ok(fd) => fsync(fd.0),
+        Err(ref e) if !follow_symlinks && e.raw_os_error() == Some(ELOOP) => Ok(()),
+        Err(e) => Err(e),
+    }
+}
+
+#[inline]
+/// Synchronises the parent directory of a path, relative to a directory, to storage.
+fn sync_parent_dir(dirfd: c_int, path: &Path, flags: c_int) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if p.as_os_str().is_empty() => Path::new("."),
+        Some(p) => p,
+        None => path,
+    };
+    let p = into_c_string(parent)?;
+    FileHandle::open_at(dirfd, p.as_ptr(), O_RDONLY | O_DIRECTORY | flags)
+        .and_then(|dir| fsync(dir.0))
+}
+
+#[cfg(target_os = "linux")]
+#[inline]
+/// Removes `O_NOATIME` from a set of flags, returning `None` if it is not included.
+fn without_noatime(flags: c_int) -> Option<c_int> {
+    if flags & O_NOATIME == 0 {
+        None
+    } else {
+        Some(flags & !O_NOATIME)
+    }
 }
 
 #[cfg(not(target_os = "linux"))]
 #[inline]
+/// Removes `O_NOATIME` from a set of flags, which never include it outside Linux.
+fn without_noatime(_: c_int) -> Option<c_int> {
+    None
+}
+
+#[inline]
+/// Safely wraps the POSIX `fstatat` function.
+fn stat_at(dirfd: c_int, path: *const c_char, flag: c_int) -> io::Result<libc::stat> {
+    let mut st = mem::MaybeUninit::<libc::stat>::uninit();
+    let _ = retry_interrupted(|| unsafe { libc::fstatat(dirfd, path, st.as_mut_ptr(), flag) })?;
+    Ok(unsafe { st.assume_init() })
+}
+
+#[cfg(all(target_os = "linux", any(target_env = "gnu", target_env = "musl")))]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+fn is_immutable_at(dirfd: c_int, path: *const c_char, flag: c_int) -> bool {
+    let attributes = (libc::STATX_ATTR_IMMUTABLE | libc::STATX_ATTR_APPEND) as u64;
+    let mut buf = mem::MaybeUninit::<libc::statx>::uninit();
+    let flags = flag | libc::AT_STATX_SYNC_AS_STAT;
+    let result =
+        retry_interrupted(|| unsafe { libc::statx(dirfd, path, flags, 0, buf.as_mut_ptr()) });
+    result.is_ok_and(|_| {
+        let buf = unsafe { buf.assume_init() };
+        buf.stx_attributes & buf.stx_attributes_mask & attributes != 0
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+fn is_immutable_at(dirfd: c_int, path: *const c_char, flag: c_int) -> bool {
+    let flags = libc::UF_IMMUTABLE | libc::UF_APPEND | libc::SF_IMMUTABLE | libc::SF_APPEND;
+    stat_at(dirfd, path, flag).is_ok_and(|st| libc::c_ulong::from(st.st_flags) & flags != 0)
+}
+
+#[cfg(not(any(
+    all(target_os = "linux", any(target_env = "gnu", target_env = "musl")),
+    target_os = "freebsd"
+)))]
+#[inline]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+///
+/// Such flags are only detected on Linux and FreeBSD.
+fn is_immutable_at(_: c_int, _: *const c_char, _: c_int) -> bool {
+    false
+}
+
+#[inline]
+/// Returns whether a path, relative to a directory, is a symbolic link.
+fn is_symlink_at(dirfd: c_int, path: *const c_char) -> bool {
+    stat_at(dirfd, path, AT_SYMLINK_NOFOLLOW).is_ok_and(|st| st.st_mode & S_IFMT == S_IFLNK)
+}
+
+// Some platforms have a buggy implementation of `utimensat` that succeeds unconditionally
+// if both timestamps are omitted.
+
+#[cfg(target_os = "linux")]
+#[inline]
 /// Safely wraps the POSIX `utimensat` function.
-fn utimensat(path: *const c_char, times: *const timespec, flag: c_int) -> io::Result<()> {
-    if unsafe { libc::utimensat(AT_FDCWD, path, times, flag) } == 0 {
-        Ok(())
-    } else {
-        Err(io::Error::last_os_error())
+fn utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const timespec,
+    flag: c_int,
+) -> io::Result<()> {
+    if times.is_null() ||
+        unsafe { (*times).tv_nsec == UTIME_OMIT && (*times.offset(1)).tv_nsec == UTIME_OMIT }
+    {
+        let _ = stat_at(dirfd, path, flag)?;
     }
+    retry_interrupted(|| unsafe { libc::utimensat(dirfd, path, times, flag) }).map(|_| ())
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+/// Safely wraps the POSIX `utimensat` function.
+fn utimensat(
+    dirfd: c_int,
+    path: *const c_char,
+    times: *const timespec,
+    flag: c_int,
+) -> io::Result<()> {
+    retry_interrupted(|| unsafe { libc::utimensat(dirfd, path, times, flag) }).map(|_| ())
 }
 
 impl FileHandle {
     #[inline]
     #[cfg_attr(feature = "clippy", allow(cast_possible_wrap))]
-    /// Opens a path.
-    pub fn open(path: *const c_char) -> io::Result<Self> {
-        let fd = unsafe {
-            libc::open(
+    /// Opens an existing path, relative to a directory, with the given flags.
+    ///
+    /// Existing paths are only ever opened for reading; `create_at` is the only place where
+    /// write access may be requested. `O_NOATIME` is dropped if the path is not owned by the
+    /// calling process, which is not permitted to use it then.
+    pub fn open_at(dirfd: c_int, path: *const c_char, flags: c_int) -> io::Result<Self> {
+        debug_assert_eq!(O_RDONLY, flags & O_ACCMODE, "opened an existing path for writing");
+        let result = retry_interrupted(|| unsafe { libc::openat(dirfd, path, O_CLOEXEC | flags) });
+        match (result, without_noatime(flags)) {
+            (Err(ref e), Some(flags)) if e.raw_os_error() == Some(EPERM) => {
+                Self::open_at(dirfd, path, flags)
+            }
+            (result, _) => result.map(FileHandle),
+        }
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "clippy", allow(cast_possible_wrap))]
+    /// Opens a path, relative to a directory, with the given flags, creating it if it does not
+    /// exist.
+    ///
+    /// The file is opened for writing unless `write` is `false`, in which case it is opened for
+    /// reading only, which is still enough to set its timestamps as its owner. It is never
+    /// truncated, since without `O_EXCL` it may have been created by someone else in the
+    /// meantime. As with `open_at`, `O_NOATIME` is dropped where it is not permitted.
+    pub fn create_at(
+        dirfd: c_int,
+        path: *const c_char,
+        write: bool,
+        flags: c_int,
+    ) -> io::Result<Self> {
+        let access = if write { O_WRONLY } else { O_RDONLY };
+        let result = retry_interrupted(|| unsafe {
+            libc::openat(
+                dirfd,
                 path,
-                O_WRONLY | O_CREAT | O_TRUNC,
+                access | O_CREAT | O_CLOEXEC | flags,
                 (S_IRUSR | S_IWUSR | S_IRGRP | S_IWGRP | S_IROTH | S_IWOTH) as c_int,
             )
-        };
-        if fd >= 0 {
-            Ok(FileHandle(fd))
-        } else {
-            Err(io::Error::last_os_error())
+        });
+        match (result, without_noatime(flags)) {
+            (Err(ref e), Some(flags)) if e.raw_os_error() == Some(EPERM) => {
+                Self::create_at(dirfd, path, write, flags)
+            }
+            (result, _) => result.map(FileHandle),
         }
     }
+
+    #[inline]
+    /// Closes the file descriptor, returning any error that `close` reports.
+    ///
+    /// Some filesystems, such as NFS, only report that writing to a file failed once it is
+    /// closed, so this is used wherever a path may have been written to.
+    pub fn close(self) -> io::Result<()> {
+        let fd = self.0;
+        mem::forget(self);
+        close(fd)
+    }
+}
+
+impl DirHandle {
+    #[inline]
+    /// Opens an existing directory.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let p = into_c_string(path)?;
+        FileHandle::open_at(AT_FDCWD, p.as_ptr(), O_RDONLY | O_DIRECTORY).map(DirHandle)
+    }
 }
 
 impl Drop for FileHandle {
     #[inline]
     fn drop(&mut self) {
-        if unsafe { libc::close(self.0) } != 0 {
-            panic!(
-                "could not close file descriptor: {}",
-                io::Error::last_os_error()
-            );
-        }
+        // Errors cannot be returned from here, and panicking would abort the process if it is
+        // already unwinding. The descriptor is released either way.
+        let _ = close(self.0);
+    }
+}
+
+impl EncodedPath {
+    #[inline]
+    /// Converts a path into a C string.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        into_c_string(path).map(EncodedPath)
+    }
+
+    #[inline]
+    /// Returns the path as a C string.
+    pub fn as_raw(&self) -> &RawPath {
+        // `into_c_string` always appends the only NUL.
+        unsafe { CStr::from_ptr(self.0.as_ptr()) }
     }
 }
 
@@ -117,63 +612,378 @@ impl FileTimes {
     #[inline]
     /// Obtains a set of Unix timestamps from a `Builder`.
     pub fn from_builder(builder: &Builder) -> Self {
-        FileTimes([
-            Self::systemtime_into_filetime(builder.accessed),
-            Self::systemtime_into_filetime(builder.modified),
-        ])
+        FileTimes {
+            times: [
+                Self::into_timespec(builder.accessed_time()),
+                Self::into_timespec(builder.modified_time()),
+            ],
+            unsupported: Self::unsupported(builder),
+            #[cfg(target_os = "freebsd")]
+            created: Self::into_timespec(builder.created_time()),
+        }
+    }
+
+    #[inline]
+    /// Returns an error if a requested timestamp cannot be stored.
+    ///
+    /// Timestamps are clamped to the range of `time_t` instead, so this only fails if a creation
+    /// or change timestamp was requested.
+    pub fn check(&self) -> io::Result<()> {
+        match self.unsupported {
+            Some(kind) => Err(UnsupportedTimestamp::new(kind).into()),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    /// Returns a timestamp requested by a `Builder` that cannot be set here, if any.
+    fn unsupported(builder: &Builder) -> Option<TimestampKind> {
+        #[cfg(feature = "change-time")]
+        {
+            if builder.changed_time().is_some() {
+                return Some(TimestampKind::Changed);
+            }
+        }
+        if cfg!(target_os = "freebsd") {
+            None
+        } else {
+            builder.created_time().map(|_| TimestampKind::Created)
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    /// Returns an error if the requested creation timestamp, if any, cannot be set on a path.
+    ///
+    /// This must be called before any other timestamp is set, since setting an earlier
+    /// modification timestamp also moves the creation timestamp back. `stat` reads the path's
+    /// metadata; a path that does not exist yet is left for the caller to create.
+    fn check_created<S>(&self, stat: S) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<libc::stat>,
+    {
+        if self.created.tv_nsec == UTIME_OMIT {
+            return Ok(());
+        }
+        let st = match stat() {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            result => result?,
+        };
+        // Filesystems that do not record creation timestamps report `VNOVAL`.
+        if st.st_birthtime == -1 {
+            return Err(UnsupportedTimestamp::new(TimestampKind::Created).into());
+        }
+        if (self.created.tv_sec, self.created.tv_nsec) > (st.st_birthtime, st.st_birthtime_nsec) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "creation timestamps can only be moved back in time on FreeBSD",
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    #[inline]
+    /// Checks that the requested creation timestamp can be set, which is only possible on
+    /// FreeBSD.
+    fn check_created<S>(&self, _: S) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<libc::stat>,
+    {
+        Ok(())
+    }
+
+    #[cfg(target_os = "freebsd")]
+    /// Moves the creation timestamp of a file back to the requested one, if any.
+    ///
+    /// FreeBSD has no call for setting creation timestamps, but moves one back whenever an
+    /// earlier modification timestamp is set. The requested creation timestamp is therefore set
+    /// as the modification timestamp, which is then restored. `check_created` must have been
+    /// called first. `stat` reads the file's metadata and `set` sets its timestamps.
+    fn set_created<S, U>(&self, stat: S, mut set: U) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<libc::stat>,
+        U: FnMut(*const timespec) -> io::Result<()>,
+    {
+        if self.created.tv_nsec == UTIME_OMIT {
+            return Ok(());
+        }
+        let st = stat()?;
+        let omit = timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        };
+        let modified = timespec {
+            tv_sec: st.st_mtime,
+            tv_nsec: st.st_mtime_nsec,
+        };
+        set([omit, self.created].as_ptr())?;
+        set([omit, modified].as_ptr())
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    #[inline]
+    /// Sets the creation timestamp of a file, which is only possible on FreeBSD.
+    fn set_created<S, U>(&self, _: S, _: U) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<libc::stat>,
+        U: FnMut(*const timespec) -> io::Result<()>,
+    {
+        Ok(())
     }
 
     #[inline]
     /// Returns a raw pointer suitable for use in time-related functions.
     pub fn as_ptr(&self) -> *const timespec {
-        &self.0[0]
+        &self.times[0]
     }
 
     #[inline]
     #[cfg_attr(feature = "clippy", allow(cast_possible_wrap))]
-    /// Converts a Rust timestamp into a Unix timestamp.
-    fn systemtime_into_filetime(time: Option<SystemTime>) -> timespec {
-        if let Some(t) = time {
-            match t.duration_since(UNIX_EPOCH) {
-                Ok(d) => timespec {
-                    tv_sec: d.as_secs() as time_t,
-                    tv_nsec: d.subsec_nanos() as c_long,
+    /// Converts a timestamp into a Unix timestamp.
+    ///
+    /// Timestamps beyond the range of `time_t` are clamped to it. The current time is left for
+    /// the kernel to read when the timestamps are set.
+    ///
+    /// `FileTime` counts nanoseconds forwards from the start of the second, so `tv_nsec` is never
+    /// negative, even for timestamps before the Unix epoch; `utimensat` rejects those with
+    /// `EINVAL`.
+    fn into_timespec(time: Option<Timestamp>) -> timespec {
+        match time {
+            Some(Timestamp::At(t)) => timespec::try_from(t).unwrap_or(timespec {
+                tv_sec: if t.unix_seconds() < 0 {
+                    time_t::MIN
+                } else {
+                    time_t::MAX
                 },
-                Err(e) => timespec {
-                    tv_sec: -(e.duration().as_secs() as time_t),
-                    tv_nsec: -(e.duration().subsec_nanos() as c_long),
-                },
-            }
-        } else {
-            timespec {
+                tv_nsec: t.nanoseconds() as c_long,
+            }),
+            Some(Timestamp::Now) => timespec {
+                tv_sec: 0,
+                tv_nsec: UTIME_NOW,
+            },
+            None => timespec {
                 tv_sec: 0,
                 tv_nsec: UTIME_OMIT,
-            }
+            },
         }
     }
 }
 
+impl From<timespec> for FileTime {
+    #[inline]
+    fn from(time: timespec) -> Self {
+        // `tv_nsec` should be less than one second, but any excess carries over into `tv_sec`.
+        FileTime::from_unix_nanos(
+            i128::from(time.tv_sec) * 1_000_000_000 + i128::from(time.tv_nsec),
+        )
+    }
+}
+
+impl TryFrom<FileTime> for timespec {
+    type Error = TimestampOutOfRange;
+
+    #[inline]
+    #[cfg_attr(feature = "clippy", allow(cast_possible_wrap))]
+    fn try_from(time: FileTime) -> Result<Self, Self::Error> {
+        let seconds =
+            time_t::try_from(time.unix_seconds()).map_err(|_| TimestampOutOfRange::new(time))?;
+        Ok(timespec {
+            tv_sec: seconds,
+            tv_nsec: time.nanoseconds() as c_long,
+        })
+    }
+}
+
 impl Builder {
     #[inline]
     /// Implementation details.
-    pub(crate) fn touch_sys<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let p = into_c_string(path);
-        let times = FileTimes::from_builder(self);
-        let utimensat_flag = if self.follow_symlinks {
+    pub(crate) fn touch_sys(
+        &self,
+        path: &Path,
+        p: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_in(AT_FDCWD, path, p, times)
+    }
+
+    #[cfg(test)]
+    /// Creates a missing file as `touch_sys` does once it has found the path to be missing.
+    pub(crate) fn create_sys(
+        &self,
+        path: &Path,
+        p: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.create_in(AT_FDCWD, path, p, times)
+    }
+
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_at_sys(
+        &self,
+        dir: &DirHandle,
+        path: &Path,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_in((dir.0).0, path, EncodedPath::new(path)?.as_raw(), times)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_fd_sys(&self, fd: BorrowedFd, times: &FileTimes) -> io::Result<Outcome> {
+        let fd = fd.as_raw_fd();
+        let empty: [c_char; 1] = [0];
+        match utimensat(fd, empty.as_ptr(), times.as_ptr(), AT_EMPTY_PATH) {
+            // Older kernels only accept `AT_EMPTY_PATH` when reading metadata, but can still
+            // update descriptors that were not opened with `O_PATH`.
+            Err(ref e) if e.raw_os_error() == Some(EINVAL) => {
+                futimens(fd, times.as_ptr())?;
+            }
+            result => result?,
+        }
+        if self.sync_file {
+            fsync(fd)?;
+        }
+        Ok(Outcome::Updated)
+    }
+
+    #[inline]
+    /// Returns the flags to pass when opening any path.
+    fn open_flags(&self) -> c_int {
+        if self.no_atime {
+            O_NOATIME
+        } else {
+            0
+        }
+    }
+
+    #[inline]
+    /// Returns the flags to pass to `utimensat` and `fstatat`.
+    fn utimensat_flag(&self) -> c_int {
+        if self.follow_symlinks {
             0
         } else {
             AT_SYMLINK_NOFOLLOW
+        }
+    }
+
+    /// Updates the timestamps for a path relative to a directory, which may be `AT_FDCWD`.
+    ///
+    /// `p` is the path converted into a C string.
+    fn touch_in(
+        &self,
+        dirfd: c_int,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flag = self.utimensat_flag();
+        times
+            .check_created(|| stat_at(dirfd, p.as_ptr(), utimensat_flag))
+            .map_err(context(Operation::SetTimes, path))?;
+        let outcome = match utimensat(dirfd, p.as_ptr(), times.as_ptr(), utimensat_flag) {
+            Ok(()) => self.finish_update(dirfd, path, p, times)?,
+            // Immutable and append-only files cannot be updated even by their owners, which is
+            // otherwise indistinguishable from not owning them.
+            Err(ref e)
+                if e.raw_os_error() == Some(EPERM) &&
+                    is_immutable_at(dirfd, p.as_ptr(), utimensat_flag) =>
+            {
+                return Err(Failure::Immutable.into());
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(context(Operation::SetTimes, path)(e))
+            }
+            Err(e) => match self.creation_target {
+                CreationTarget::None => return Err(context(Operation::SetTimes, path)(e)),
+                CreationTarget::File => self.create_in(dirfd, path, p, times)?,
+            },
         };
-        utimensat(p.as_ptr(), times.as_ptr(), utimensat_flag)
-            .or_else(|e| if e.kind() == io::ErrorKind::NotFound {
-                match self.creation_target {
-                    CreationTarget::None => Err(e),
-                    CreationTarget::File => {
-                        FileHandle::open(p.as_ptr()).and_then(|fd| futimens(&fd, times.as_ptr()))
-                    }
-                }
-            } else {
-                Err(e)
+        if self.sync_parent_dir {
+            sync_parent_dir(dirfd, path, self.open_flags())
+                .map_err(context(Operation::Sync, path))?;
+        }
+        Ok(outcome)
+    }
+
+    /// Finishes updating a path that already existed, once its access and modification
+    /// timestamps have been set.
+    fn finish_update(
+        &self,
+        dirfd: c_int,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flag = self.utimensat_flag();
+        times
+            .set_created(
+                || stat_at(dirfd, p.as_ptr(), utimensat_flag),
+                |t| utimensat(dirfd, p.as_ptr(), t, utimensat_flag),
+            )
+            .map_err(context(Operation::SetTimes, path))?;
+        if self.sync_file {
+            sync_path(dirfd, p.as_ptr(), self.follow_symlinks, self.open_flags())
+                .map_err(context(Operation::Sync, path))?;
+        }
+        Ok(Outcome::Updated)
+    }
+
+    /// Creates a missing file relative to a directory and sets its timestamps.
+    ///
+    /// If another process creates the path first, its timestamps are set instead, and it is
+    /// reported as updated.
+    fn create_in(
+        &self,
+        dirfd: c_int,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flag = self.utimensat_flag();
+        // Symbolic links are only left dangling here if they are followed.
+        let dangling = is_symlink_at(dirfd, p.as_ptr());
+        // `O_NOFOLLOW` makes `open` fail with `ELOOP` if the path is a dangling symbolic link,
+        // instead of creating its target.
+        let mut open_flags = match self.dangling_symlinks {
+            DanglingSymlinks::CreateTarget => self.open_flags(),
+            DanglingSymlinks::Refuse => self.open_flags() | O_NOFOLLOW,
+        };
+        // `O_EXCL` makes `open` fail if another process creates the path first, so that it is
+        // reported as updated rather than created. It cannot be used for dangling symbolic
+        // links, which it always refuses to follow.
+        if !dangling {
+            open_flags |= O_EXCL;
+        }
+        let fd = match FileHandle::create_at(dirfd, p.as_ptr(), self.open_for_writing, open_flags) {
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && !dangling => {
+                times
+                    .check_created(|| stat_at(dirfd, p.as_ptr(), utimensat_flag))
+                    .and_then(|_| utimensat(dirfd, p.as_ptr(), times.as_ptr(), utimensat_flag))
+                    .map_err(context(Operation::SetTimes, path))?;
+                return self.finish_update(dirfd, path, p, times);
+            }
+            result => result.map_err(context(Operation::Create, path))?,
+        };
+        times
+            .check_created(|| stat_at(dirfd, p.as_ptr(), utimensat_flag))
+            .and_then(|_| futimens(fd.0, times.as_ptr()))
+            .and_then(|_| {
+                times.set_created(
+                    || stat_at(dirfd, p.as_ptr(), utimensat_flag),
+                    |t| futimens(fd.0, t),
+                )
             })
+            .map_err(context(Operation::SetTimes, path))?;
+        if self.sync_file {
+            fsync(fd.0).map_err(context(Operation::Sync, path))?;
+        }
+        fd.close().map_err(context(Operation::Close, path))?;
+        if dangling {
+            Ok(Outcome::CreatedSymlinkTarget)
+        } else {
+            Ok(Outcome::Created)
+        }
     }
 }
@@ -0,0 +1,866 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Unix-specific utilities, implemented using rustix instead of raw `libc` calls.
+//!
+//! This behaves like the `libc` backend, but leaves the system call wrappers, and the layout of
+//! the structures they use, to rustix, so no unsafe code is needed here apart from closing
+//! descriptors, which rustix otherwise does without reporting errors.
+
+use {Builder, CreationTarget, DanglingSymlinks, Failure, FileTime, Operation, Outcome,
+     Timestamp, TimestampKind, Timestamps, UnsupportedTimestamp};
+use error::{context, invalid_path};
+use granularity::Filesystem;
+use rustix::fs::{self as rfs, AtFlags, FileType, Mode, OFlags, Timespec, CWD, UTIME_NOW,
+                 UTIME_OMIT};
+use rustix::io::Errno;
+use rustix::process::{getrlimit, Resource};
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+use rustix::time::{clock_gettime, ClockId};
+use rustix::path::Arg;
+use smallvec::SmallVec;
+use std::{fs, io, iter};
+use std::convert::TryFrom;
+use std::ffi::CStr;
+use std::os::unix::ffi::OsStrExt;
+use std::os::unix::fs::MetadataExt;
+use std::os::unix::io::{AsFd, BorrowedFd, IntoRawFd, OwnedFd, RawFd};
+use std::path::Path;
+use std::time::Duration;
+
+/// An open directory, whose entries can be updated without resolving their full paths.
+pub struct DirHandle(OwnedFd);
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// Uniquely identifies a file by its device and inode numbers.
+pub struct FileId(u64, u64);
+
+#[derive(Clone, Debug)]
+/// A path converted into a C string once, so that it can be passed to system calls repeatedly.
+pub struct EncodedPath(SmallVec<[u8; INLINE_PATH_LEN]>);
+
+/// A path in the form system calls expect, borrowed from an `EncodedPath` or the caller.
+pub type RawPath = CStr;
+
+impl FileId {
+    #[inline]
+    /// Returns the device number of the filesystem containing the file.
+    pub fn device(&self) -> u64 {
+        self.0
+    }
+}
+
+/// How many bytes a path may take up, including its terminating NUL, before converting it into
+/// a C string needs a heap allocation.
+const INLINE_PATH_LEN: usize = 256;
+
+/// The resolution of timestamps on this platform.
+pub const RESOLUTION: Duration = Duration::from_nanos(1);
+
+#[cfg(target_os = "linux")]
+/// Stops the access timestamp of an opened file from being updated by reading it.
+const NOATIME: OFlags = OFlags::NOATIME;
+
+#[cfg(not(target_os = "linux"))]
+/// `O_NOATIME` is only available on Linux, so nothing is passed elsewhere.
+const NOATIME: OFlags = OFlags::empty();
+
+#[cfg(target_os = "linux")]
+/// The `statfs` magic number of FAT filesystems.
+const MSDOS_SUPER_MAGIC: i128 = 0x4d44;
+
+#[cfg(target_os = "linux")]
+/// The `statfs` magic number of exFAT filesystems.
+const EXFAT_SUPER_MAGIC: i128 = 0x2011_bab0;
+
+/// The permissions of created files, before the process's umask is applied.
+const CREATE_MODE: Mode = Mode::RUSR
+    .union(Mode::WUSR)
+    .union(Mode::RGRP)
+    .union(Mode::WGRP)
+    .union(Mode::ROTH)
+    .union(Mode::WOTH);
+
+/// Holds Unix timestamps for a file.
+pub struct FileTimes {
+    /// The access and modification timestamps.
+    times: rfs::Timestamps,
+    /// A requested timestamp that cannot be set here, if any.
+    unsupported: Option<TimestampKind>,
+    #[cfg(target_os = "freebsd")]
+    /// The creation timestamp, which is set separately.
+    created: Timespec,
+}
+
+#[inline]
+/// Returns a value that uniquely identifies the file a path refers to.
+pub fn file_id(path: &Path, follow_symlinks: bool) -> io::Result<FileId> {
+    let metadata = if follow_symlinks {
+        fs::metadata(path)
+    } else {
+        fs::symlink_metadata(path)
+    };
+    metadata.map(|m| FileId(m.dev(), m.ino()))
+}
+
+#[inline]
+/// Sets the access and modification timestamps of an existing path to the current time.
+pub fn touch_now(path: &Path) -> io::Result<()> {
+    let now = Timespec {
+        tv_sec: 0,
+        tv_nsec: UTIME_NOW,
+    };
+    let times = rfs::Timestamps {
+        last_access: now,
+        last_modification: now,
+    };
+    Ok(retry_interrupted(|| rfs::utimensat(CWD, path, &times, AtFlags::empty()))?)
+}
+
+#[cfg(any(target_os = "linux", target_os = "freebsd"))]
+#[inline]
+/// Returns the current time from `CLOCK_REALTIME_COARSE`.
+pub fn coarse_now() -> FileTime {
+    FileTime::from(clock_gettime(ClockId::RealtimeCoarse))
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+#[inline]
+/// Returns the current time, since there is no coarse clock here.
+pub fn coarse_now() -> FileTime {
+    FileTime::now()
+}
+
+#[inline]
+/// Returns how many file descriptors this process may open, if there is a practical limit.
+pub fn handle_limit() -> Option<usize> {
+    getrlimit(Resource::Nofile)
+        .current
+        .map(|limit| usize::try_from(limit).unwrap_or(usize::MAX))
+}
+
+#[inline]
+/// Returns whether an error may go away if the operation is retried.
+///
+/// Open files are never locked against updating their timestamps, so no error is transient.
+pub fn is_transient(_: &io::Error) -> bool {
+    false
+}
+
+#[inline]
+/// Returns whether an error number means that a symbolic link was not followed.
+///
+/// `O_NOFOLLOW` makes `open` fail with `ELOOP` if the path is a symbolic link.
+pub fn is_symlink_error(code: i32) -> bool {
+    code == Errno::LOOP.raw_os_error()
+}
+
+#[inline]
+/// Classifies a POSIX error number.
+pub fn failure(code: i32) -> Failure {
+    match Errno::from_raw_os_error(code) {
+        Errno::ROFS => Failure::ReadOnlyFilesystem,
+        Errno::NOSPC => Failure::NoSpace,
+        Errno::DQUOT => Failure::QuotaExceeded,
+        _ => Failure::Other,
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    // The type of `f_type` differs between architectures.
+    match i128::from(retry_interrupted(|| rfs::statfs(path))?.f_type) {
+        MSDOS_SUPER_MAGIC => Ok(Filesystem::Fat),
+        EXFAT_SUPER_MAGIC => Ok(Filesystem::ExFat),
+        _ => Ok(Filesystem::Other),
+    }
+}
+
+#[cfg(target_os = "freebsd")]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    let name = retry_interrupted(|| rfs::statfs(path))?.f_fstypename;
+    let name = name.iter().take_while(|&&c| c != 0).map(|&c| c as u8);
+    match name.collect::<Vec<u8>>().as_slice() {
+        b"msdosfs" => Ok(Filesystem::Fat),
+        b"exfat" => Ok(Filesystem::ExFat),
+        _ => Ok(Filesystem::Other),
+    }
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+#[inline]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+///
+/// Filesystems are only identified on Linux and FreeBSD, so every filesystem is reported as
+/// `Other` elsewhere.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    fs::metadata(path).map(|_| Filesystem::Other)
+}
+
+#[inline]
+/// Returns whether a path is hidden, i.e. whether its name starts with a `.`.
+pub fn is_hidden(path: &Path, _: &fs::Metadata) -> bool {
+    path.file_name()
+        .is_some_and(|name| name.as_bytes().first() == Some(&b'.'))
+}
+
+#[inline]
+/// Returns whether a path is an NTFS junction.
+///
+/// Junctions only exist on Windows.
+pub fn is_junction(_: &Path) -> bool {
+    false
+}
+
+/// Reads every timestamp of a path using `statx`, which also reports when the path was created.
+///
+/// Returns `None` if `statx` is unavailable, in which case `stat` should be used instead.
+#[cfg(target_os = "linux")]
+fn statx_times(path: &Path, follow_symlinks: bool) -> Option<io::Result<Timestamps>> {
+    use rustix::fs::{statx, StatxFlags, StatxTimestamp};
+
+    let flags = if follow_symlinks {
+        AtFlags::STATX_SYNC_AS_STAT
+    } else {
+        AtFlags::STATX_SYNC_AS_STAT | AtFlags::SYMLINK_NOFOLLOW
+    };
+    let mask = StatxFlags::ATIME | StatxFlags::MTIME | StatxFlags::CTIME | StatxFlags::BTIME;
+    let buf = match retry_interrupted(|| statx(CWD, path, flags, mask)) {
+        Ok(buf) => buf,
+        // Old kernels and some sandboxes reject `statx` outright.
+        Err(Errno::NOSYS | Errno::PERM) => return None,
+        Err(e) => return Some(Err(e.into())),
+    };
+    let time = |t: StatxTimestamp| FileTime::from_unix_time(t.tv_sec, t.tv_nsec);
+    let created = if buf.stx_mask & StatxFlags::BTIME.bits() != 0 {
+        Some(time(buf.stx_btime))
+    } else {
+        None
+    };
+    Some(Ok(Timestamps::new(
+        time(buf.stx_atime),
+        time(buf.stx_mtime),
+        time(buf.stx_ctime),
+        created,
+    )))
+}
+
+#[cfg(not(target_os = "linux"))]
+#[inline]
+/// Reads every timestamp of a path using `statx`, which is only available on Linux.
+fn statx_times(_: &Path, _: bool) -> Option<io::Result<Timestamps>> {
+    None
+}
+
+/// Reads every timestamp of a path, optionally following symbolic links.
+pub fn read_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    match statx_times(path, follow_symlinks) {
+        Some(result) => result,
+        None => stat_times(path, follow_symlinks),
+    }
+}
+
+#[cfg(target_os = "linux")]
+/// Reads the timestamps of a path using `fstatat`, for kernels without `statx`.
+///
+/// `stat` does not report when a path was created, so no creation timestamp is returned.
+pub fn stat_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    let flags = if follow_symlinks {
+        AtFlags::empty()
+    } else {
+        AtFlags::SYMLINK_NOFOLLOW
+    };
+    let st = retry_interrupted(|| rfs::statat(CWD, path, flags))?;
+    // The types of these fields differ between architectures.
+    let time = |seconds, nanoseconds| {
+        FileTime::from_unix_nanos(i128::from(seconds) * 1_000_000_000 + i128::from(nanoseconds))
+    };
+    Ok(Timestamps::new(
+        time(st.st_atime, st.st_atime_nsec),
+        time(st.st_mtime, st.st_mtime_nsec),
+        time(st.st_ctime, st.st_ctime_nsec),
+        None,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+/// Reads the timestamps of a path, including when it was created where the platform records it.
+fn stat_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    let metadata = if follow_symlinks {
+        fs::metadata(path)?
+    } else {
+        fs::symlink_metadata(path)?
+    };
+    let time = |seconds: i64, nanoseconds: i64| {
+        FileTime::from_unix_time(seconds, u32::try_from(nanoseconds).unwrap_or(0))
+    };
+    Ok(Timestamps::new(
+        time(metadata.atime(), metadata.atime_nsec()),
+        time(metadata.mtime(), metadata.mtime_nsec()),
+        time(metadata.ctime(), metadata.ctime_nsec()),
+        metadata.created().ok().map(FileTime::from),
+    ))
+}
+
+#[inline]
+/// Calls a function, retrying it for as long as it is interrupted by a signal.
+pub fn retry_interrupted<T, F>(mut f: F) -> rustix::io::Result<T>
+where
+    F: FnMut() -> rustix::io::Result<T>,
+{
+    loop {
+        match f() {
+            Err(Errno::INTR) => (),
+            result => return result,
+        }
+    }
+}
+
+#[allow(unsafe_code)]
+/// Closes a descriptor, reporting any error, which dropping an `OwnedFd` would ignore.
+///
+/// The descriptor must not be used again, even if this fails.
+pub fn close(fd: RawFd) -> io::Result<()> {
+    // `close` must not be retried when it is interrupted, since the descriptor may already have
+    // been released and reused by another thread; it is simply treated as closed.
+    match unsafe { rustix::io::try_close(fd) } {
+        Ok(()) | Err(Errno::INTR) => Ok(()),
+        Err(e) => Err(e.into()),
+    }
+}
+
+#[inline]
+/// Opens an existing path, relative to a directory, with the given flags.
+///
+/// Existing paths are only ever opened for reading; `create_at` is the only place where write
+/// access may be requested. `O_NOATIME` is dropped if the path is not owned by the calling
+/// process, which is not permitted to use it then.
+fn open_at<Fd: AsFd, P: Arg + Copy>(dirfd: Fd, path: P, flags: OFlags) -> io::Result<OwnedFd> {
+    debug_assert!(
+        !flags.intersects(OFlags::WRONLY | OFlags::RDWR),
+        "opened an existing path for writing"
+    );
+    let dirfd = dirfd.as_fd();
+    match retry_interrupted(|| rfs::openat(dirfd, path, OFlags::CLOEXEC | flags, Mode::empty())) {
+        Err(Errno::PERM) if flags.intersects(NOATIME) => open_at(dirfd, path, flags - NOATIME),
+        result => Ok(result?),
+    }
+}
+
+#[inline]
+/// Opens a path, relative to a directory, with the given flags, creating it if it does not
+/// exist.
+///
+/// The file is opened for writing unless `write` is `false`, in which case it is opened for
+/// reading only, which is still enough to set its timestamps as its owner. It is never
+/// truncated, since without `O_EXCL` it may have been created by someone else in the meantime.
+/// As with `open_at`, `O_NOATIME` is dropped where it is not permitted.
+fn create_at<Fd: AsFd, P: Arg + Copy>(
+    dirfd: Fd,
+    path: P,
+    write: bool,
+    flags: OFlags,
+) -> io::Result<OwnedFd> {
+    let access = if write {
+        OFlags::WRONLY
+    } else {
+        OFlags::RDONLY
+    };
+    let dirfd = dirfd.as_fd();
+    let all_flags = access | OFlags::CREATE | OFlags::CLOEXEC | flags;
+    match retry_interrupted(|| rfs::openat(dirfd, path, all_flags, CREATE_MODE)) {
+        Err(Errno::PERM) if flags.intersects(NOATIME) => {
+            create_at(dirfd, path, write, flags - NOATIME)
+        }
+        result => Ok(result?),
+    }
+}
+
+#[inline]
+/// Synchronises an existing path to storage.
+///
+/// Symbolic links that are not followed cannot be opened, and are skipped.
+fn sync_path<Fd: AsFd, P: Arg + Copy>(
+    dirfd: Fd,
+    path: P,
+    follow_symlinks: bool,
+    flags: OFlags,
+) -> io::Result<()> {
+    let flags = if follow_symlinks {
+        flags
+    } else {
+        flags | OFlags::NOFOLLOW
+    };
+    match open_at(dirfd, path, OFlags::RDONLY | OFlags::NONBLOCK | flags) {
+        Ok(fd) => Ok(retry_interrupted(|| rfs::fsync(&fd))?),
+        Err(ref e) if !follow_symlinks && e.raw_os_error() == Some(Errno::LOOP.raw_os_error()) => {
+            Ok(())
+        }
+        Err(e) => Err(e),
+    }
+}
+
+#[inline]
+/// Synchronises the parent directory of a path, relative to a directory, to storage.
+fn sync_parent_dir<Fd: AsFd>(dirfd: Fd, path: &Path, flags: OFlags) -> io::Result<()> {
+    let parent = match path.parent() {
+        Some(p) if p.as_os_str().is_empty() => Path::new("."),
+        Some(p) => p,
+        None => path,
+    };
+    let dir = open_at(dirfd, parent, OFlags::RDONLY | OFlags::DIRECTORY | flags)?;
+    Ok(retry_interrupted(|| rfs::fsync(&dir))?)
+}
+
+#[cfg(target_os = "linux")]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+fn is_immutable_at<P: Arg + Copy>(dirfd: BorrowedFd, path: P, flags: AtFlags) -> bool {
+    use rustix::fs::{statx, StatxAttributes, StatxFlags};
+
+    let attributes = StatxAttributes::IMMUTABLE | StatxAttributes::APPEND;
+    let flags = flags | AtFlags::STATX_SYNC_AS_STAT;
+    retry_interrupted(|| statx(dirfd, path, flags, StatxFlags::empty())).is_ok_and(|buf| {
+        buf.stx_attributes
+            .intersection(buf.stx_attributes_mask)
+            .intersects(attributes)
+    })
+}
+
+#[cfg(target_os = "freebsd")]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+fn is_immutable_at<P: Arg + Copy>(dirfd: BorrowedFd, path: P, flags: AtFlags) -> bool {
+    // `UF_IMMUTABLE`, `UF_APPEND`, `SF_IMMUTABLE` and `SF_APPEND`.
+    let immutable = 0x0002 | 0x0004 | 0x0002_0000 | 0x0004_0000;
+    retry_interrupted(|| rfs::statat(dirfd, path, flags))
+        .is_ok_and(|st| st.st_flags & immutable != 0)
+}
+
+#[cfg(not(any(target_os = "linux", target_os = "freebsd")))]
+#[inline]
+/// Returns whether a path, relative to a directory, is flagged as immutable or append-only.
+///
+/// Such flags are only detected on Linux and FreeBSD.
+fn is_immutable_at<P: Arg + Copy>(_: BorrowedFd, _: P, _: AtFlags) -> bool {
+    false
+}
+
+#[inline]
+/// Returns whether a path, relative to a directory, is a symbolic link.
+fn is_symlink_at<Fd: AsFd, P: Arg + Copy>(dirfd: Fd, path: P) -> bool {
+    let dirfd = dirfd.as_fd();
+    retry_interrupted(|| rfs::statat(dirfd, path, AtFlags::SYMLINK_NOFOLLOW))
+        .is_ok_and(|st| FileType::from_raw_mode(st.st_mode) == FileType::Symlink)
+}
+
+#[inline]
+/// Updates the timestamps for a path relative to a directory.
+///
+/// Some platforms have a buggy implementation of `utimensat` that succeeds unconditionally if
+/// both timestamps are omitted, so the path is checked first in that case.
+fn utimensat<Fd: AsFd, P: Arg + Copy>(
+    dirfd: Fd,
+    path: P,
+    times: &rfs::Timestamps,
+    flags: AtFlags,
+) -> io::Result<()> {
+    if times.last_access.tv_nsec == UTIME_OMIT && times.last_modification.tv_nsec == UTIME_OMIT {
+        let _ = retry_interrupted(|| rfs::statat(dirfd.as_fd(), path, flags))?;
+    }
+    Ok(retry_interrupted(|| rfs::utimensat(dirfd.as_fd(), path, times, flags))?)
+}
+
+impl DirHandle {
+    #[inline]
+    /// Opens an existing directory.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        open_at(CWD, path, OFlags::RDONLY | OFlags::DIRECTORY).map(DirHandle)
+    }
+}
+
+impl EncodedPath {
+    #[inline]
+    /// Converts a path into a C string.
+    ///
+    /// Fails with an error of kind `InvalidInput` if the path contains a NUL byte, which would
+    /// otherwise end it early.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        let bytes = path.as_os_str().as_bytes();
+        if bytes.contains(&0) {
+            return Err(invalid_path("path contains an interior NUL byte"));
+        }
+        Ok(EncodedPath(bytes.iter().cloned().chain(iter::once(0)).collect()))
+    }
+
+    #[inline]
+    /// Returns the path as a C string.
+    pub fn as_raw(&self) -> &RawPath {
+        CStr::from_bytes_with_nul(&self.0).expect("encoded path is not a C string")
+    }
+}
+
+impl FileTimes {
+    #[inline]
+    /// Obtains a set of Unix timestamps from a `Builder`.
+    pub fn from_builder(builder: &Builder) -> Self {
+        FileTimes {
+            times: rfs::Timestamps {
+                last_access: Self::into_timespec(builder.accessed_time()),
+                last_modification: Self::into_timespec(builder.modified_time()),
+            },
+            unsupported: Self::unsupported(builder),
+            #[cfg(target_os = "freebsd")]
+            created: Self::into_timespec(builder.created_time()),
+        }
+    }
+
+    #[inline]
+    /// Returns an error if a requested timestamp cannot be stored.
+    ///
+    /// Every access and modification timestamp can be stored, so this only fails if a creation
+    /// or change timestamp was requested.
+    pub fn check(&self) -> io::Result<()> {
+        match self.unsupported {
+            Some(kind) => Err(UnsupportedTimestamp::new(kind).into()),
+            None => Ok(()),
+        }
+    }
+
+    #[inline]
+    /// Returns a timestamp requested by a `Builder` that cannot be set here, if any.
+    fn unsupported(builder: &Builder) -> Option<TimestampKind> {
+        #[cfg(feature = "change-time")]
+        {
+            if builder.changed_time().is_some() {
+                return Some(TimestampKind::Changed);
+            }
+        }
+        if cfg!(target_os = "freebsd") {
+            None
+        } else {
+            builder.created_time().map(|_| TimestampKind::Created)
+        }
+    }
+
+    #[cfg(target_os = "freebsd")]
+    /// Returns an error if the requested creation timestamp, if any, cannot be set on a path.
+    ///
+    /// This must be called before any other timestamp is set, since setting an earlier
+    /// modification timestamp also moves the creation timestamp back. `stat` reads the path's
+    /// metadata; a path that does not exist yet is left for the caller to create.
+    fn check_created<S>(&self, stat: S) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<rfs::Stat>,
+    {
+        if self.created.tv_nsec == UTIME_OMIT {
+            return Ok(());
+        }
+        let st = match stat() {
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            result => result?,
+        };
+        // Filesystems that do not record creation timestamps report `VNOVAL`.
+        if st.st_birthtime == -1 {
+            return Err(UnsupportedTimestamp::new(TimestampKind::Created).into());
+        }
+        if (self.created.tv_sec, self.created.tv_nsec) > (st.st_birthtime, st.st_birthtime_nsec) {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "creation timestamps can only be moved back in time on FreeBSD",
+            ));
+        }
+        Ok(())
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    #[inline]
+    /// Checks that the requested creation timestamp can be set, which is only possible on
+    /// FreeBSD.
+    fn check_created<S>(&self, _: S) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<rfs::Stat>,
+    {
+        Ok(())
+    }
+
+    #[cfg(target_os = "freebsd")]
+    /// Moves the creation timestamp of a file back to the requested one, if any.
+    ///
+    /// FreeBSD has no call for setting creation timestamps, but moves one back whenever an
+    /// earlier modification timestamp is set. The requested creation timestamp is therefore set
+    /// as the modification timestamp, which is then restored. `check_created` must have been
+    /// called first. `stat` reads the file's metadata and `set` sets its timestamps.
+    fn set_created<S, U>(&self, stat: S, mut set: U) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<rfs::Stat>,
+        U: FnMut(&rfs::Timestamps) -> io::Result<()>,
+    {
+        if self.created.tv_nsec == UTIME_OMIT {
+            return Ok(());
+        }
+        let st = stat()?;
+        let omit = Timespec {
+            tv_sec: 0,
+            tv_nsec: UTIME_OMIT,
+        };
+        set(&rfs::Timestamps {
+            last_access: omit,
+            last_modification: self.created,
+        })?;
+        set(&rfs::Timestamps {
+            last_access: omit,
+            last_modification: Timespec {
+                tv_sec: st.st_mtime,
+                tv_nsec: st.st_mtime_nsec,
+            },
+        })
+    }
+
+    #[cfg(not(target_os = "freebsd"))]
+    #[inline]
+    /// Sets the creation timestamp of a file, which is only possible on FreeBSD.
+    fn set_created<S, U>(&self, _: S, _: U) -> io::Result<()>
+    where
+        S: FnOnce() -> io::Result<rfs::Stat>,
+        U: FnMut(&rfs::Timestamps) -> io::Result<()>,
+    {
+        Ok(())
+    }
+
+    #[inline]
+    /// Converts a timestamp into a Unix timestamp.
+    ///
+    /// The current time is left for the kernel to read when the timestamps are set.
+    fn into_timespec(time: Option<Timestamp>) -> Timespec {
+        match time {
+            Some(Timestamp::At(t)) => Timespec::from(t),
+            Some(Timestamp::Now) => Timespec {
+                tv_sec: 0,
+                tv_nsec: UTIME_NOW,
+            },
+            None => Timespec {
+                tv_sec: 0,
+                tv_nsec: UTIME_OMIT,
+            },
+        }
+    }
+}
+
+impl From<Timespec> for FileTime {
+    #[inline]
+    fn from(time: Timespec) -> Self {
+        // `tv_nsec` should be less than one second, but any excess carries over into `tv_sec`.
+        FileTime::from_unix_nanos(
+            i128::from(time.tv_sec) * 1_000_000_000 + i128::from(time.tv_nsec),
+        )
+    }
+}
+
+impl From<FileTime> for Timespec {
+    /// Converts a timestamp into a Unix timestamp.
+    ///
+    /// rustix always uses 64-bit seconds, so every timestamp fits. `FileTime` counts nanoseconds
+    /// forwards from the start of the second, so `tv_nsec` is never negative, even for
+    /// timestamps before the Unix epoch; `utimensat` rejects those with `EINVAL`.
+    #[inline]
+    fn from(time: FileTime) -> Self {
+        Timespec {
+            tv_sec: time.unix_seconds(),
+            tv_nsec: time.nanoseconds().into(),
+        }
+    }
+}
+
+impl Builder {
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_sys(
+        &self,
+        path: &Path,
+        p: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_in(CWD, path, p, times)
+    }
+
+    #[cfg(test)]
+    /// Creates a missing file as `touch_sys` does once it has found the path to be missing.
+    pub(crate) fn create_sys(
+        &self,
+        path: &Path,
+        p: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.create_in(CWD, path, p, times)
+    }
+
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_at_sys(
+        &self,
+        dir: &DirHandle,
+        path: &Path,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        self.touch_in(dir.0.as_fd(), path, EncodedPath::new(path)?.as_raw(), times)
+    }
+
+    #[cfg(target_os = "linux")]
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_fd_sys(&self, fd: BorrowedFd, times: &FileTimes) -> io::Result<Outcome> {
+        match utimensat(fd, Path::new(""), &times.times, AtFlags::EMPTY_PATH) {
+            // Older kernels only accept `AT_EMPTY_PATH` when reading metadata, but can still
+            // update descriptors that were not opened with `O_PATH`.
+            Err(ref e) if e.raw_os_error() == Some(Errno::INVAL.raw_os_error()) => {
+                retry_interrupted(|| rfs::futimens(fd, &times.times))?
+            }
+            result => result?,
+        }
+        if self.sync_file {
+            retry_interrupted(|| rfs::fsync(fd))?;
+        }
+        Ok(Outcome::Updated)
+    }
+
+    #[inline]
+    /// Returns the flags to pass when opening any path.
+    fn open_flags(&self) -> OFlags {
+        if self.no_atime {
+            NOATIME
+        } else {
+            OFlags::empty()
+        }
+    }
+
+    #[inline]
+    /// Returns the flags to pass to `utimensat` and `fstatat`.
+    fn utimensat_flags(&self) -> AtFlags {
+        if self.follow_symlinks {
+            AtFlags::empty()
+        } else {
+            AtFlags::SYMLINK_NOFOLLOW
+        }
+    }
+
+    /// Updates the timestamps for a path relative to a directory, which may be `CWD`.
+    ///
+    /// `p` is the path converted into a C string.
+    fn touch_in(
+        &self,
+        dirfd: BorrowedFd,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flags = self.utimensat_flags();
+        times
+            .check_created(|| Ok(retry_interrupted(|| rfs::statat(dirfd, p, utimensat_flags))?))
+            .map_err(context(Operation::SetTimes, path))?;
+        let outcome = match utimensat(dirfd, p, &times.times, utimensat_flags) {
+            Ok(()) => self.finish_update(dirfd, path, p, times)?,
+            // Immutable and append-only files cannot be updated even by their owners, which is
+            // otherwise indistinguishable from not owning them.
+            Err(ref e)
+                if e.raw_os_error() == Some(Errno::PERM.raw_os_error()) &&
+                    is_immutable_at(dirfd, p, utimensat_flags) =>
+            {
+                return Err(Failure::Immutable.into());
+            }
+            Err(e) if e.kind() != io::ErrorKind::NotFound => {
+                return Err(context(Operation::SetTimes, path)(e))
+            }
+            Err(e) => match self.creation_target {
+                CreationTarget::None => return Err(context(Operation::SetTimes, path)(e)),
+                CreationTarget::File => self.create_in(dirfd, path, p, times)?,
+            },
+        };
+        if self.sync_parent_dir {
+            sync_parent_dir(dirfd, path, self.open_flags())
+                .map_err(context(Operation::Sync, path))?;
+        }
+        Ok(outcome)
+    }
+
+    /// Finishes updating a path that already existed, once its access and modification
+    /// timestamps have been set.
+    fn finish_update(
+        &self,
+        dirfd: BorrowedFd,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flags = self.utimensat_flags();
+        let stat = || Ok(retry_interrupted(|| rfs::statat(dirfd, p, utimensat_flags))?);
+        times
+            .set_created(stat, |t| utimensat(dirfd, p, t, utimensat_flags))
+            .map_err(context(Operation::SetTimes, path))?;
+        if self.sync_file {
+            sync_path(dirfd, p, self.follow_symlinks, self.open_flags())
+                .map_err(context(Operation::Sync, path))?;
+        }
+        Ok(Outcome::Updated)
+    }
+
+    /// Creates a missing file relative to a directory and sets its timestamps.
+    ///
+    /// If another process creates the path first, its timestamps are set instead, and it is
+    /// reported as updated.
+    fn create_in(
+        &self,
+        dirfd: BorrowedFd,
+        path: &Path,
+        p: &CStr,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let utimensat_flags = self.utimensat_flags();
+        let stat = || Ok(retry_interrupted(|| rfs::statat(dirfd, p, utimensat_flags))?);
+        // Symbolic links are only left dangling here if they are followed.
+        let dangling = is_symlink_at(dirfd, p);
+        // `O_NOFOLLOW` makes `open` fail with `ELOOP` if the path is a dangling symbolic link,
+        // instead of creating its target.
+        let mut open_flags = match self.dangling_symlinks {
+            DanglingSymlinks::CreateTarget => self.open_flags(),
+            DanglingSymlinks::Refuse => self.open_flags() | OFlags::NOFOLLOW,
+        };
+        // `O_EXCL` makes `open` fail if another process creates the path first, so that it is
+        // reported as updated rather than created. It cannot be used for dangling symbolic
+        // links, which it always refuses to follow.
+        if !dangling {
+            open_flags |= OFlags::EXCL;
+        }
+        let fd = match create_at(dirfd, p, self.open_for_writing, open_flags) {
+            Err(ref e) if e.kind() == io::ErrorKind::AlreadyExists && !dangling => {
+                times
+                    .check_created(stat)
+                    .and_then(|_| utimensat(dirfd, p, &times.times, utimensat_flags))
+                    .map_err(context(Operation::SetTimes, path))?;
+                return self.finish_update(dirfd, path, p, times);
+            }
+            result => result.map_err(context(Operation::Create, path))?,
+        };
+        times
+            .check_created(stat)
+            .and_then(|_| Ok(retry_interrupted(|| rfs::futimens(&fd, &times.times))?))
+            .and_then(|_| {
+                times.set_created(stat, |t| Ok(retry_interrupted(|| rfs::futimens(&fd, t))?))
+            })
+            .map_err(context(Operation::SetTimes, path))?;
+        if self.sync_file {
+            retry_interrupted(|| rfs::fsync(&fd))
+                .map_err(|e| context(Operation::Sync, path)(e.into()))?;
+        }
+        close(fd.into_raw_fd()).map_err(context(Operation::Close, path))?;
+        if dangling {
+            Ok(Outcome::CreatedSymlinkTarget)
+        } else {
+            Ok(Outcome::Created)
+        }
+    }
+}
@@ -9,47 +9,553 @@
 
 #![allow(unsafe_code)]
 
-use {Builder, CreationTarget};
-use kernel32;
-use std::{io, iter, ptr};
-use std::path::Path;
+use {Builder, CreationTarget, DanglingSymlinks, Failure, FileTime, Operation, Outcome,
+     ShareMode, Timestamp, TimestampOutOfRange, Timestamps};
+use convert;
+use error::{context, invalid_path};
+use granularity::Filesystem;
+use smallvec::SmallVec;
+use std::{fs, io, iter, mem, ptr};
+use std::convert::TryFrom;
+use std::ffi::c_void;
+use std::path::{Component, Path, PathBuf, Prefix};
+use std::sync::OnceLock;
+use std::time::Duration;
 use std::os::windows::ffi::OsStrExt;
-use std::time::{Duration, SystemTime, UNIX_EPOCH};
-use winapi::{DWORD, FILETIME, FILE_FLAG_BACKUP_SEMANTICS, FILE_FLAG_OPEN_REPARSE_POINT,
-             FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE, FILE_WRITE_ATTRIBUTES, HANDLE,
-             INVALID_HANDLE_VALUE, LPCWSTR, OPEN_ALWAYS, OPEN_EXISTING, WCHAR};
+use std::os::windows::fs::MetadataExt;
+#[cfg(feature = "change-time")]
+use windows_sys::Wdk::Storage::FileSystem::{FileBasicInformation, NtSetInformationFile};
+use windows_sys::Win32::Foundation::{CloseHandle, GetLastError, ERROR_ALREADY_EXISTS,
+                                     ERROR_CANT_RESOLVE_FILENAME, ERROR_DISK_FULL,
+                                     ERROR_DISK_QUOTA_EXCEEDED,
+                                     ERROR_HANDLE_DISK_FULL, ERROR_LOCK_VIOLATION,
+                                     ERROR_NOT_ALL_ASSIGNED, ERROR_SHARING_VIOLATION,
+                                     ERROR_WRITE_PROTECT, FALSE, FILETIME, GENERIC_WRITE, HANDLE,
+                                     INVALID_HANDLE_VALUE, LUID, SYSTEMTIME};
+#[cfg(feature = "change-time")]
+use windows_sys::Win32::Foundation::RtlNtStatusToDosError;
+use windows_sys::Win32::Security::{AdjustTokenPrivileges, LookupPrivilegeValueW,
+                                   LUID_AND_ATTRIBUTES, SE_PRIVILEGE_ENABLED,
+                                   TOKEN_ADJUST_PRIVILEGES, TOKEN_PRIVILEGES};
+use windows_sys::Win32::Storage::FileSystem::{CreateFileW, FileAttributeTagInfo, FileBasicInfo,
+                                              FlushFileBuffers, GetFileAttributesW,
+                                              GetFileInformationByHandle,
+                                              GetFileInformationByHandleEx, GetFullPathNameW,
+                                              GetVolumeInformationByHandleW,
+                                              SetFileInformationByHandle, SetFileTime,
+                                              BY_HANDLE_FILE_INFORMATION, FILE_ATTRIBUTE_HIDDEN,
+                                              FILE_ATTRIBUTE_NORMAL, FILE_ATTRIBUTE_READONLY,
+                                              FILE_ATTRIBUTE_REPARSE_POINT, FILE_ATTRIBUTE_TAG_INFO,
+                                              FILE_BASIC_INFO, FILE_FLAG_BACKUP_SEMANTICS,
+                                              FILE_FLAG_OPEN_REPARSE_POINT, FILE_READ_ATTRIBUTES,
+                                              FILE_SHARE_DELETE, FILE_SHARE_READ, FILE_SHARE_WRITE,
+                                              FILE_WRITE_ATTRIBUTES, INVALID_FILE_ATTRIBUTES,
+                                              OPEN_ALWAYS, OPEN_EXISTING};
+#[cfg(feature = "change-time")]
+use windows_sys::Win32::System::IO::IO_STATUS_BLOCK;
+use windows_sys::Win32::System::SystemInformation::{GetSystemTimeAsFileTime,
+                                                    GetSystemTimePreciseAsFileTime};
+use windows_sys::Win32::System::SystemServices::{FILE_NAMED_STREAMS, IO_REPARSE_TAG_MOUNT_POINT};
+use windows_sys::Win32::System::Threading::{GetCurrentProcess, OpenProcessToken};
+use windows_sys::Win32::System::Time::{FileTimeToSystemTime, SystemTimeToFileTime};
+use windows_sys::core::PCWSTR;
 
 /// A safe wrapper around a Windows file handle.
 struct FileHandle(HANDLE);
 
+/// An open directory, whose entries can be updated together.
+///
+/// Opening a path relative to a directory handle requires the native NT API, so this keeps the
+/// directory open to stop it from being removed or renamed, and updates its entries by path.
+pub struct DirHandle {
+    /// Keeps the directory open.
+    _handle: FileHandle,
+    /// The path of the directory.
+    path: PathBuf,
+}
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// Uniquely identifies a file by its volume serial number and file index.
+pub struct FileId(u64, u64);
+
+#[derive(Clone, Debug)]
+/// A path converted into a wide string once, so that it can be passed to Windows API calls
+/// repeatedly.
+pub struct EncodedPath(WidePath);
+
+/// A NUL-terminated wide string, borrowed from an `EncodedPath` or the caller.
+pub type RawPath = [u16];
+
+/// A path converted into a wide string, which is kept on the stack unless it is unusually long.
+type WidePath = SmallVec<[u16; INLINE_PATH_LEN]>;
+
+impl FileId {
+    #[inline]
+    /// Returns the volume serial number of the filesystem containing the file.
+    pub fn device(&self) -> u64 {
+        self.0
+    }
+}
+
+/// A `TOKEN_PRIVILEGES` structure with room for the backup and restore privileges.
+#[repr(C)]
+struct BackupPrivileges {
+    /// The number of privileges, which is always two.
+    count: u32,
+    /// The backup and restore privileges, and whether to enable them.
+    privileges: [LUID_AND_ATTRIBUTES; 2],
+}
+
+/// How many UTF-16 code units a path may take up, including its terminating NUL, before
+/// converting it into a wide string needs a heap allocation.
+const INLINE_PATH_LEN: usize = 256;
+
+/// The resolution of timestamps on this platform.
+pub const RESOLUTION: Duration = Duration::from_nanos(100);
+
 /// Holds Windows timestamps for a file.
-struct FileTimes {
+pub struct FileTimes {
     /// The access timestamp.
     accessed: FILETIME,
     /// The modification timestamp.
     modified: FILETIME,
+    /// The creation timestamp.
+    created: FILETIME,
+    #[cfg(feature = "change-time")]
+    /// The change timestamp, if it should be set.
+    changed: Option<FILETIME>,
+    /// Whether the access timestamp should be the current time.
+    accessed_now: bool,
+    /// Whether the modification timestamp should be the current time.
+    modified_now: bool,
+    /// Whether to read the current time from the coarse clock.
+    coarse_clock: bool,
+    /// Whether the access timestamp should be read and written back unchanged.
+    preserve_accessed: bool,
+    /// A requested timestamp that Windows cannot store, if any.
+    out_of_range: Option<FileTime>,
+}
+
+/// The longest path, in UTF-16 code units, that is passed to Windows unchanged.
+///
+/// `CreateFileW` accepts up to `MAX_PATH` (260) characters, but directory paths are limited to
+/// 248, so the smaller limit is used for every path.
+const LEGACY_MAX_PATH: usize = 248;
+
+/// The path separator.
+const SEP: u16 = b'\\' as u16;
+
+/// The prefix for extended-length paths, `\\?\`.
+const VERBATIM_PREFIX: [u16; 4] = [SEP, SEP, b'?' as u16, SEP];
+
+/// The prefix for extended-length UNC paths, `\\?\UNC\`.
+const UNC_PREFIX: [u16; 8] = [
+    SEP,
+    SEP,
+    b'?' as u16,
+    SEP,
+    b'U' as u16,
+    b'N' as u16,
+    b'C' as u16,
+    SEP,
+];
+
+/// The prefix for device paths, `\\.\`.
+const DEVICE_PREFIX: [u16; 4] = [SEP, SEP, b'.' as u16, SEP];
+
+/// Lets other handles read, write and delete a path while it is open.
+const SHARE_ALL: u32 = FILE_SHARE_DELETE | FILE_SHARE_READ | FILE_SHARE_WRITE;
+
+/// Converts a path into a NUL-terminated Windows wide string for use in FFI calls.
+///
+/// Short paths are converted without allocating, unless they need to be made absolute.
+///
+/// Paths that are too long for the legacy Win32 limits are made absolute and given the
+/// extended-length prefix, which lifts the limit to about 32,767 characters. Extended-length
+/// paths are passed to the filesystem without any processing, so they are normalised first: `/`
+/// becomes `\` and `.` and `..` components are resolved, as Win32 would do for shorter paths.
+/// UNC paths such as `\\server\share\file` become `\\?\UNC\server\share\file`.
+///
+/// Paths that are already extended-length, including `\\?\UNC\` paths and volume GUID paths
+/// such as `\\?\Volume{...}\file`, only have any `/` separators converted, since Windows
+/// never allows `/` in names. Device paths starting with `\\.\` are left unchanged.
+///
+/// Volume roots such as `\\.\C:` or `\\?\Volume{...}` are given a trailing separator, since
+/// without one they name the volume itself, which cannot be opened without administrator rights,
+/// rather than its root directory.
+///
+/// Fails with an error of kind `InvalidInput` if the path contains a NUL character, which would
+/// otherwise end it early.
+fn into_wide_string<P: AsRef<Path>>(path: P) -> io::Result<WidePath> {
+    let path = path.as_ref();
+    let mut wide = path.as_os_str().encode_wide().collect::<WidePath>();
+    if wide.contains(&0) {
+        return Err(invalid_path("path contains an interior NUL character"));
+    }
+    if is_volume_root(path) && !wide.last().is_some_and(|&c| c == SEP || c == b'/' as u16) {
+        wide.push(SEP);
+    }
+    wide.push(0);
+    if wide.starts_with(&VERBATIM_PREFIX) {
+        return Ok(wide.into_iter()
+            .map(|c| if c == b'/' as u16 { SEP } else { c })
+            .collect());
+    } else if wide.len() <= LEGACY_MAX_PATH || wide.starts_with(&DEVICE_PREFIX) {
+        return Ok(wide);
+    }
+    let full = full_path_name(&wide)?;
+    if full.get(1..3) == Some(&[b':' as u16, SEP][..]) {
+        Ok(VERBATIM_PREFIX.iter().chain(&full).cloned().collect())
+    } else if full.starts_with(&[SEP, SEP]) {
+        Ok(UNC_PREFIX.iter().chain(&full[2..]).cloned().collect())
+    } else {
+        Ok(wide)
+    }
+}
+
+/// Resolves a NUL-terminated path into a NUL-terminated absolute path, using the same rules as
+/// Win32 functions that accept paths.
+fn full_path_name(path: &[u16]) -> io::Result<Vec<u16>> {
+    let mut buffer = Vec::new();
+    loop {
+        // On success, this returns the length without the NUL terminator; if the buffer is too
+        // small, it returns the length needed including the terminator.
+        let len = unsafe {
+            GetFullPathNameW(
+                path.as_ptr(),
+                buffer.len() as u32,
+                buffer.as_mut_ptr(),
+                ptr::null_mut(),
+            )
+        } as usize;
+        if len == 0 {
+            return Err(io::Error::last_os_error());
+        } else if len < buffer.len() {
+            buffer.truncate(len + 1);
+            return Ok(buffer);
+        }
+        buffer.resize(len, 0);
+    }
+}
+
+#[inline]
+/// Converts a share mode into the flags accepted by `CreateFileW`.
+fn share_flags(mode: ShareMode) -> u32 {
+    let mut flags = 0;
+    if mode.shares_read() {
+        flags |= FILE_SHARE_READ;
+    }
+    if mode.shares_write() {
+        flags |= FILE_SHARE_WRITE;
+    }
+    if mode.shares_delete() {
+        flags |= FILE_SHARE_DELETE;
+    }
+    flags
+}
+
+#[inline]
+/// Returns whether a path refers to a reparse point, such as a symbolic link.
+///
+/// Reparse points are not followed, so this also returns `true` for dangling symbolic links.
+fn is_reparse_point(path: PCWSTR) -> bool {
+    let attrs = unsafe { GetFileAttributesW(path) };
+    attrs != INVALID_FILE_ATTRIBUTES && attrs & FILE_ATTRIBUTE_REPARSE_POINT != 0
+}
+
+/// Returns whether a path names the root directory of a volume, such as `C:\`, `\\.\C:`,
+/// `\\?\Volume{...}\` or `\\server\share`.
+///
+/// A drive letter without a separator, such as `C:`, names the current directory on that drive
+/// instead, as it does everywhere else on Windows.
+fn is_volume_root(path: &Path) -> bool {
+    let mut components = path.components();
+    let prefix = match components.next() {
+        Some(Component::Prefix(prefix)) => prefix.kind(),
+        _ => return false,
+    };
+    let has_root = match components.next() {
+        Some(Component::RootDir) => true,
+        Some(_) => return false,
+        None => false,
+    };
+    if components.next().is_some() {
+        return false;
+    }
+    match prefix {
+        Prefix::Disk(_) => has_root,
+        Prefix::VerbatimDisk(_) | Prefix::UNC(..) | Prefix::VerbatimUNC(..) => true,
+        Prefix::DeviceNS(name) => {
+            name.len() == 2 && name.to_str().is_some_and(|name| name.ends_with(':'))
+        }
+        Prefix::Verbatim(name) => name.to_str().is_some_and(|name| name.starts_with("Volume{")),
+    }
+}
+
+#[inline]
+/// Returns a value that uniquely identifies the file a path refers to.
+pub fn file_id(path: &Path, follow_symlinks: bool) -> io::Result<FileId> {
+    let p = into_wide_string(path)?;
+    let flags = if follow_symlinks {
+        0
+    } else {
+        FILE_FLAG_OPEN_REPARSE_POINT
+    };
+    FileHandle::open(p.as_ptr(), FILE_READ_ATTRIBUTES, SHARE_ALL, OPEN_EXISTING, flags)
+        .and_then(|(fd, _)| fd.file_id())
+}
+
+#[inline]
+/// Reads every timestamp of a path, optionally following symbolic links.
+pub fn read_times(path: &Path, follow_symlinks: bool) -> io::Result<Timestamps> {
+    let p = into_wide_string(path)?;
+    let flags = if follow_symlinks {
+        0
+    } else {
+        FILE_FLAG_OPEN_REPARSE_POINT
+    };
+    FileHandle::open(p.as_ptr(), FILE_READ_ATTRIBUTES, SHARE_ALL, OPEN_EXISTING, flags)
+        .and_then(|(fd, _)| fd.read_times())
+}
+
+/// Explains a failure to open a named stream, such as `file.txt:stream`, if the filesystem
+/// containing it does not support named streams at all.
+///
+/// Windows reports this as an invalid or missing path, which is misleading.
+fn stream_error(path: &Path, e: io::Error) -> io::Error {
+    let is_stream = path.file_name()
+        .is_some_and(|name| name.encode_wide().any(|c| c == b':' as u16));
+    let dir = match path.parent() {
+        Some(dir) if is_stream && dir.as_os_str().is_empty() => Path::new("."),
+        Some(dir) if is_stream => dir,
+        _ => return e,
+    };
+    let supported = into_wide_string(dir).and_then(|p| {
+        FileHandle::open(p.as_ptr(), FILE_READ_ATTRIBUTES, SHARE_ALL, OPEN_EXISTING, 0)
+            .and_then(|(fd, _)| fd.supports_named_streams())
+    });
+    match supported {
+        Ok(false) => io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!(
+                "{} is a named stream, which the filesystem containing it does not support",
+                path.display()
+            ),
+        ),
+        _ => e,
+    }
+}
+
+/// Clears the read-only attribute of an existing path, if it is set.
+///
+/// If the attribute was cleared, returns a handle through which to restore the original
+/// attributes, and the attributes themselves.
+fn clear_readonly(path: PCWSTR, flags: u32) -> io::Result<Option<(FileHandle, u32)>> {
+    let access = FILE_READ_ATTRIBUTES | FILE_WRITE_ATTRIBUTES;
+    let fd = match FileHandle::open(path, access, SHARE_ALL, OPEN_EXISTING, flags) {
+        Ok((fd, _)) => fd,
+        // A path that is yet to be created is not read-only.
+        Err(ref e) if e.kind() == io::ErrorKind::NotFound => return Ok(None),
+        Err(e) => return Err(e),
+    };
+    let attrs = fd.attributes()?;
+    if attrs & FILE_ATTRIBUTE_READONLY == 0 {
+        return Ok(None);
+    }
+    fd.set_attributes(attrs & !FILE_ATTRIBUTE_READONLY)?;
+    Ok(Some((fd, attrs)))
+}
+
+/// Enables the backup and restore privileges for this process, unless that was already tried.
+///
+/// Privileges apply to the whole process, so this is only attempted once; any failure is
+/// remembered and reported again every time.
+fn enable_backup_privileges() -> io::Result<()> {
+    static RESULT: OnceLock<Result<(), i32>> = OnceLock::new();
+    let result = RESULT.get_or_init(|| {
+        adjust_backup_privileges().map_err(|e| e.raw_os_error().unwrap_or(0))
+    });
+    result.map_err(io::Error::from_raw_os_error)
+}
+
+/// Enables the backup and restore privileges in this process's access token.
+fn adjust_backup_privileges() -> io::Result<()> {
+    let mut token = ptr::null_mut();
+    let access = TOKEN_ADJUST_PRIVILEGES;
+    if unsafe { OpenProcessToken(GetCurrentProcess(), access, &mut token) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    // Access tokens are closed the same way as file handles.
+    let token = FileHandle(token);
+    let privilege = |name: &str| {
+        lookup_privilege(name).map(|luid| LUID_AND_ATTRIBUTES {
+            Luid: luid,
+            Attributes: SE_PRIVILEGE_ENABLED,
+        })
+    };
+    let privileges = BackupPrivileges {
+        count: 2,
+        privileges: [privilege("SeBackupPrivilege")?, privilege("SeRestorePrivilege")?],
+    };
+    let ptr: *const BackupPrivileges = &privileges;
+    if unsafe {
+        AdjustTokenPrivileges(
+            token.0,
+            FALSE,
+            ptr as *const TOKEN_PRIVILEGES,
+            0,
+            ptr::null_mut(),
+            ptr::null_mut(),
+        )
+    } == 0
+    {
+        return Err(io::Error::last_os_error());
+    }
+    // Not holding a privilege is not treated as a failure, but is reported this way instead.
+    match unsafe { GetLastError() } {
+        ERROR_NOT_ALL_ASSIGNED => Err(io::Error::from_raw_os_error(ERROR_NOT_ALL_ASSIGNED as i32)),
+        _ => Ok(()),
+    }
+}
+
+#[inline]
+/// Looks up the identifier of a privilege on the local system.
+fn lookup_privilege(name: &str) -> io::Result<LUID> {
+    let name = name.encode_utf16().chain(iter::once(0)).collect::<Vec<_>>();
+    let mut luid = LUID {
+        LowPart: 0,
+        HighPart: 0,
+    };
+    if unsafe { LookupPrivilegeValueW(ptr::null(), name.as_ptr(), &mut luid) } == 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(luid)
+    }
+}
+
+#[inline]
+/// Sets the access and modification timestamps of an existing path to the current time.
+pub fn touch_now(path: &Path) -> io::Result<()> {
+    let p = into_wide_string(path)?;
+    let access = FILE_WRITE_ATTRIBUTES;
+    let (fd, _) = FileHandle::open(p.as_ptr(), access, SHARE_ALL, OPEN_EXISTING, 0)?;
+    let mut now = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    unsafe { GetSystemTimePreciseAsFileTime(&mut now) };
+    if unsafe { SetFileTime(fd.0, ptr::null(), &now, &now) } == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(())
+}
+
+#[inline]
+/// Returns the current time from `GetSystemTimeAsFileTime`, which advances once per timer tick.
+pub fn coarse_now() -> FileTime {
+    let mut now = FILETIME {
+        dwLowDateTime: 0,
+        dwHighDateTime: 0,
+    };
+    unsafe { GetSystemTimeAsFileTime(&mut now) };
+    FileTime::from(now)
+}
+
+#[inline]
+/// Returns how many handles this process may open, if there is a practical limit.
+///
+/// Windows allows millions of handles per process, so there is no practical limit.
+pub fn handle_limit() -> Option<usize> {
+    None
+}
+
+#[inline]
+/// Returns whether an error may go away if the operation is retried.
+///
+/// Sharing and lock violations occur while another process, such as an antivirus scanner, has
+/// the file open without sharing it.
+pub fn is_transient(e: &io::Error) -> bool {
+    match e.raw_os_error() {
+        Some(code) => {
+            code == ERROR_SHARING_VIOLATION as i32 || code == ERROR_LOCK_VIOLATION as i32
+        }
+        None => false,
+    }
+}
+
+#[inline]
+/// Returns whether an error code means that a symbolic link or junction could not be followed.
+///
+/// Links that point to each other, or that nest too deeply, cannot be resolved.
+pub fn is_symlink_error(code: i32) -> bool {
+    code == ERROR_CANT_RESOLVE_FILENAME as i32
 }
 
 #[inline]
-/// Converts a path into a Windows wide string for use in FFI calls.
-fn into_wide_string<P: AsRef<Path>>(path: P) -> Vec<WCHAR> {
-    path.as_ref()
-        .as_os_str()
-        .encode_wide()
-        .chain(iter::once(0))
-        .collect()
+/// Classifies a Windows error code.
+pub fn failure(code: i32) -> Failure {
+    match u32::try_from(code) {
+        Ok(ERROR_WRITE_PROTECT) => Failure::WriteProtected,
+        Ok(ERROR_DISK_FULL) | Ok(ERROR_HANDLE_DISK_FULL) => Failure::NoSpace,
+        Ok(ERROR_DISK_QUOTA_EXCEEDED) => Failure::QuotaExceeded,
+        _ => Failure::Other,
+    }
+}
+
+#[inline]
+/// Returns what kind of filesystem contains a path, following symbolic links.
+pub fn filesystem(path: &Path) -> io::Result<Filesystem> {
+    let p = into_wide_string(path)?;
+    let name = FileHandle::open(p.as_ptr(), FILE_READ_ATTRIBUTES, SHARE_ALL, OPEN_EXISTING, 0)
+        .and_then(|(fd, _)| fd.filesystem_name())?;
+    match name.as_str() {
+        "FAT" | "FAT12" | "FAT16" | "FAT32" => Ok(Filesystem::Fat),
+        "exFAT" => Ok(Filesystem::ExFat),
+        _ => Ok(Filesystem::Other),
+    }
+}
+
+#[inline]
+/// Returns whether a path is hidden, i.e. whether it has the hidden attribute.
+pub fn is_hidden(_: &Path, metadata: &fs::Metadata) -> bool {
+    metadata.file_attributes() & FILE_ATTRIBUTE_HIDDEN != 0
+}
+
+#[inline]
+/// Returns whether a path is an NTFS junction or volume mount point, rather than a symbolic
+/// link or an ordinary file or directory.
+pub fn is_junction(path: &Path) -> bool {
+    let p = match into_wide_string(path) {
+        Ok(p) => p,
+        Err(_) => return false,
+    };
+    if !is_reparse_point(p.as_ptr()) {
+        return false;
+    }
+    let flags = FILE_FLAG_OPEN_REPARSE_POINT;
+    FileHandle::open(p.as_ptr(), FILE_READ_ATTRIBUTES, SHARE_ALL, OPEN_EXISTING, flags)
+        .and_then(|(fd, _)| fd.reparse_tag())
+        .is_ok_and(|tag| tag == IO_REPARSE_TAG_MOUNT_POINT)
 }
 
 impl FileHandle {
     #[inline]
-    /// Creates a file handle to a path with the given flags.
-    pub fn open(path: LPCWSTR, disp: DWORD, flags: DWORD) -> io::Result<FileHandle> {
+    /// Creates a file handle to a path with the given access rights, share mode and flags.
+    ///
+    /// On success, also returns whether the path was created.
+    pub fn open(
+        path: PCWSTR,
+        access: u32,
+        share: u32,
+        disp: u32,
+        flags: u32,
+    ) -> io::Result<(FileHandle, bool)> {
         let fd = unsafe {
-            kernel32::CreateFileW(
+            CreateFileW(
                 path,
-                FILE_WRITE_ATTRIBUTES,
-                FILE_SHARE_DELETE | FILE_SHARE_READ | FILE_SHARE_WRITE,
-                ptr::null_mut(),
+                access,
+                share,
+                ptr::null(),
                 disp,
                 FILE_FLAG_BACKUP_SEMANTICS | flags,
                 ptr::null_mut(),
@@ -58,15 +564,142 @@ impl FileHandle {
         if fd == INVALID_HANDLE_VALUE {
             Err(io::Error::last_os_error())
         } else {
-            Ok(FileHandle(fd))
+            // `OPEN_ALWAYS` signals that the path already existed via the thread's last error.
+            let created =
+                disp == OPEN_ALWAYS && unsafe { GetLastError() } != ERROR_ALREADY_EXISTS;
+            Ok((FileHandle(fd), created))
         }
     }
 
     #[inline]
-    /// Updates the timestamps for a file.
-    pub fn update_timestamps(&mut self, times: &FileTimes) -> io::Result<()> {
+    /// Returns a value that uniquely identifies the file this handle refers to.
+    pub fn file_id(&self) -> io::Result<FileId> {
+        let mut info = unsafe { mem::zeroed::<BY_HANDLE_FILE_INFORMATION>() };
+        if unsafe { GetFileInformationByHandle(self.0, &mut info) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(FileId(
+                u64::from(info.dwVolumeSerialNumber),
+                u64::from(info.nFileIndexHigh) << 32 | u64::from(info.nFileIndexLow),
+            ))
+        }
+    }
+
+    #[inline]
+    /// Returns whether the filesystem containing this handle's file supports named streams.
+    pub fn supports_named_streams(&self) -> io::Result<bool> {
+        let mut flags = 0;
+        if unsafe {
+            GetVolumeInformationByHandleW(
+                self.0,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                &mut flags,
+                ptr::null_mut(),
+                0,
+            )
+        } == 0
+        {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(flags & FILE_NAMED_STREAMS != 0)
+        }
+    }
+
+    #[inline]
+    /// Returns the name of the filesystem containing this handle's file, such as `NTFS`.
+    pub fn filesystem_name(&self) -> io::Result<String> {
+        // Filesystem names are limited to `MAX_PATH` characters, plus the NUL terminator.
+        let mut name = [0; 261];
+        if unsafe {
+            GetVolumeInformationByHandleW(
+                self.0,
+                ptr::null_mut(),
+                0,
+                ptr::null_mut(),
+                ptr::null_mut(),
+                ptr::null_mut(),
+                name.as_mut_ptr(),
+                name.len() as u32,
+            )
+        } == 0
+        {
+            return Err(io::Error::last_os_error());
+        }
+        let len = name.iter().position(|&c| c == 0).unwrap_or(name.len());
+        Ok(String::from_utf16_lossy(&name[..len]))
+    }
+
+    #[inline]
+    /// Returns the reparse tag of the file this handle refers to, which identifies what kind of
+    /// reparse point it is.
+    ///
+    /// The tag is meaningless if the file is not a reparse point.
+    pub fn reparse_tag(&self) -> io::Result<u32> {
+        let mut info = unsafe { mem::zeroed::<FILE_ATTRIBUTE_TAG_INFO>() };
+        let ptr: *mut FILE_ATTRIBUTE_TAG_INFO = &mut info;
+        if unsafe {
+            GetFileInformationByHandleEx(
+                self.0,
+                FileAttributeTagInfo,
+                ptr as *mut c_void,
+                size_of::<FILE_ATTRIBUTE_TAG_INFO>() as u32,
+            )
+        } == 0
+        {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(info.ReparseTag)
+        }
+    }
+
+    #[inline]
+    /// Reads every timestamp of the file this handle refers to.
+    pub fn read_times(&self) -> io::Result<Timestamps> {
+        let info = self.basic_info()?;
+        // Filesystems that do not record creation times report zero.
+        let created = if info.CreationTime == 0 {
+            None
+        } else {
+            Some(convert::from_windows_ticks(info.CreationTime))
+        };
+        Ok(Timestamps::new(
+            convert::from_windows_ticks(info.LastAccessTime),
+            convert::from_windows_ticks(info.LastWriteTime),
+            convert::from_windows_ticks(info.ChangeTime),
+            created,
+        ))
+    }
+
+    #[inline]
+    /// Returns the attributes of the file this handle refers to.
+    pub fn attributes(&self) -> io::Result<u32> {
+        self.basic_info().map(|info| info.FileAttributes)
+    }
+
+    #[inline]
+    /// Replaces the attributes of the file this handle refers to, leaving its timestamps
+    /// unchanged.
+    ///
+    /// The handle must have been opened with `FILE_WRITE_ATTRIBUTES` access.
+    pub fn set_attributes(&self, attrs: u32) -> io::Result<()> {
+        let mut info = unsafe { mem::zeroed::<FILE_BASIC_INFO>() };
+        // Zero leaves the attributes unchanged, so a file without any is marked as normal.
+        info.FileAttributes = if attrs == 0 {
+            FILE_ATTRIBUTE_NORMAL
+        } else {
+            attrs
+        };
+        let ptr: *const FILE_BASIC_INFO = &info;
         if unsafe {
-            kernel32::SetFileTime(self.0, ptr::null(), times.accessed(), times.modified())
+            SetFileInformationByHandle(
+                self.0,
+                FileBasicInfo,
+                ptr as *const c_void,
+                size_of::<FILE_BASIC_INFO>() as u32,
+            )
         } == 0
         {
             Err(io::Error::last_os_error())
@@ -74,14 +707,157 @@ impl FileHandle {
             Ok(())
         }
     }
+
+    #[inline]
+    /// Reads the timestamps and attributes of the file this handle refers to.
+    fn basic_info(&self) -> io::Result<FILE_BASIC_INFO> {
+        let mut info = unsafe { mem::zeroed::<FILE_BASIC_INFO>() };
+        let ptr: *mut FILE_BASIC_INFO = &mut info;
+        if unsafe {
+            GetFileInformationByHandleEx(
+                self.0,
+                FileBasicInfo,
+                ptr as *mut c_void,
+                size_of::<FILE_BASIC_INFO>() as u32,
+            )
+        } == 0
+        {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(info)
+        }
+    }
+
+    #[inline]
+    /// Flushes a file to storage.
+    ///
+    /// The handle must have been opened with `GENERIC_WRITE` access.
+    pub fn flush(&mut self) -> io::Result<()> {
+        if unsafe { FlushFileBuffers(self.0) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    /// Closes the handle, returning any error that `CloseHandle` reports.
+    pub fn close(self) -> io::Result<()> {
+        let handle = self.0;
+        mem::forget(self);
+        if unsafe { CloseHandle(handle) } == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(())
+        }
+    }
+
+    #[inline]
+    #[cfg_attr(feature = "clippy", allow(cast_possible_truncation, cast_sign_loss))]
+    /// Updates the timestamps for a file.
+    pub fn update_timestamps(&mut self, times: &FileTimes) -> io::Result<()> {
+        let (mut accessed, modified) = times.resolve();
+        if times.preserve_accessed {
+            let ticks = self.basic_info()?.LastAccessTime;
+            accessed = FILETIME {
+                dwLowDateTime: ticks as u32,
+                dwHighDateTime: (ticks >> 32) as u32,
+            };
+        }
+        if unsafe { SetFileTime(self.0, &times.created, &accessed, &modified) } == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        // Setting the other timestamps updates the change timestamp, so it must be set last.
+        #[cfg(feature = "change-time")]
+        {
+            if let Some(changed) = times.changed {
+                self.set_change_time(changed)?;
+            }
+        }
+        Ok(())
+    }
+
+    #[cfg(feature = "change-time")]
+    #[inline]
+    /// Sets the change timestamp for a file.
+    ///
+    /// `SetFileTime` cannot do this, so the native NT API is used instead. Zeroed timestamps and
+    /// attributes are left unchanged by it.
+    fn set_change_time(&mut self, time: FILETIME) -> io::Result<()> {
+        let mut info = unsafe { mem::zeroed::<FILE_BASIC_INFO>() };
+        info.ChangeTime =
+            (i64::from(time.dwHighDateTime) << 32) | i64::from(time.dwLowDateTime);
+        let ptr: *const FILE_BASIC_INFO = &info;
+        let mut io_status = unsafe { mem::zeroed::<IO_STATUS_BLOCK>() };
+        let status = unsafe {
+            NtSetInformationFile(
+                self.0,
+                &mut io_status,
+                ptr as *const c_void,
+                size_of::<FILE_BASIC_INFO>() as u32,
+                FileBasicInformation,
+            )
+        };
+        // Negative status codes indicate errors.
+        if status < 0 {
+            let code = unsafe { RtlNtStatusToDosError(status) };
+            Err(io::Error::from_raw_os_error(code as i32))
+        } else {
+            Ok(())
+        }
+    }
+}
+
+impl EncodedPath {
+    #[inline]
+    /// Converts a path into a wide string.
+    ///
+    /// Long relative paths are resolved against the current directory at this point.
+    pub fn new(path: &Path) -> io::Result<Self> {
+        into_wide_string(path).map(EncodedPath)
+    }
+
+    #[inline]
+    /// Returns the path as a NUL-terminated wide string.
+    pub fn as_raw(&self) -> &RawPath {
+        &self.0
+    }
+}
+
+impl DirHandle {
+    #[inline]
+    /// Opens an existing directory.
+    pub fn open(path: &Path) -> io::Result<Self> {
+        let p = into_wide_string(path)?;
+        // Sharing everything except deletion keeps the directory in place while it is open.
+        let fd = unsafe {
+            CreateFileW(
+                p.as_ptr(),
+                FILE_READ_ATTRIBUTES,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                ptr::null(),
+                OPEN_EXISTING,
+                FILE_FLAG_BACKUP_SEMANTICS,
+                ptr::null_mut(),
+            )
+        };
+        if fd == INVALID_HANDLE_VALUE {
+            Err(io::Error::last_os_error())
+        } else {
+            Ok(DirHandle {
+                _handle: FileHandle(fd),
+                path: path.to_path_buf(),
+            })
+        }
+    }
 }
 
 impl Drop for FileHandle {
     #[inline]
     fn drop(&mut self) {
-        if unsafe { kernel32::CloseHandle(self.0) } == 0 {
-            panic!("{}", io::Error::last_os_error());
-        }
+        // Errors cannot be returned from here, and panicking would abort the process if it is
+        // already unwinding.
+        let _ = unsafe { CloseHandle(self.0) };
     }
 }
 
@@ -89,46 +865,139 @@ impl FileTimes {
     #[inline]
     /// Obtains a set of Windows timestamps from a `Builder`.
     pub fn from_builder(builder: &Builder) -> Self {
+        let accessed = builder.accessed_time();
+        let modified = builder.modified_time();
+        let created = builder.created_time();
+        #[cfg(feature = "change-time")]
+        let changed = builder.changed_time();
+        #[cfg(not(feature = "change-time"))]
+        let changed = None;
+        let out_of_range = [accessed, modified, created, changed].iter().find_map(|t| match *t {
+            Some(Timestamp::At(t)) if FILETIME::try_from(t).is_err() => Some(t),
+            _ => None,
+        });
         FileTimes {
-            accessed: Self::systemtime_into_filetime(builder.accessed),
-            modified: Self::systemtime_into_filetime(builder.modified),
+            accessed: Self::into_filetime(accessed),
+            modified: Self::into_filetime(modified),
+            created: Self::into_filetime(created),
+            #[cfg(feature = "change-time")]
+            changed: changed.map(|t| Self::into_filetime(Some(t))),
+            accessed_now: accessed == Some(Timestamp::Now),
+            modified_now: modified == Some(Timestamp::Now),
+            coarse_clock: builder.coarse_clock,
+            preserve_accessed: builder.preserve_accessed && accessed.is_none(),
+            out_of_range,
+        }
+    }
+
+    #[inline]
+    /// Returns an error if a requested timestamp cannot be stored by Windows.
+    pub fn check(&self) -> io::Result<()> {
+        match self.out_of_range {
+            Some(t) => Err(TimestampOutOfRange::new(t).into()),
+            None => Ok(()),
         }
     }
 
     #[inline]
-    /// Returns a reference to the access timestamp.
-    pub fn accessed(&self) -> &FILETIME {
-        &self.accessed
+    /// Returns the access and modification timestamps to set, reading the current time if
+    /// either of them should be the current time.
+    ///
+    /// Unlike on POSIX platforms, `SetFileTime` cannot read the current time itself, so this is
+    /// done immediately before calling it.
+    fn resolve(&self) -> (FILETIME, FILETIME) {
+        if !self.accessed_now && !self.modified_now {
+            return (self.accessed, self.modified);
+        }
+        let mut now = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+        if self.coarse_clock {
+            unsafe { GetSystemTimeAsFileTime(&mut now) };
+        } else {
+            unsafe { GetSystemTimePreciseAsFileTime(&mut now) };
+        }
+        (
+            if self.accessed_now { now } else { self.accessed },
+            if self.modified_now { now } else { self.modified },
+        )
     }
 
     #[inline]
-    /// Returns a reference to the modification timestamp.
-    pub fn modified(&self) -> &FILETIME {
-        &self.modified
+    /// Converts a timestamp into a Windows timestamp.
+    ///
+    /// The current time is resolved later, by `resolve`, and timestamps that are out of range are
+    /// reported by `check`; both are left unchanged here.
+    fn into_filetime(time: Option<Timestamp>) -> FILETIME {
+        let file_time = match time {
+            Some(Timestamp::At(t)) => FILETIME::try_from(t).ok(),
+            _ => None,
+        };
+        file_time.unwrap_or(FILETIME {
+            dwLowDateTime: 0xFFFF_FFFF,
+            dwHighDateTime: 0xFFFF_FFFF,
+        })
     }
+}
 
+impl From<FILETIME> for FileTime {
     #[inline]
-    #[cfg_attr(feature = "clippy", allow(cast_possible_truncation))]
-    /// Converts a Rust timestamp into a Windows timestamp.
-    fn systemtime_into_filetime(time: Option<SystemTime>) -> FILETIME {
-        if let Some(t) = time {
-            // Windows does not use the Unix epoch! The Windows epoch is January 1, 1601 (UTC).
-            let unix_epoch = Duration::from_secs(11_644_473_600);
-            let duration = match t.duration_since(UNIX_EPOCH) {
-                Ok(d) => d + unix_epoch,
-                Err(e) => unix_epoch - e.duration(),
-            };
-            // Windows timestamps have a resolution of 100 nanoseconds.
-            let nanos = duration.as_secs() * 10_000_000 + (duration.subsec_nanos() / 100) as u64;
-            FILETIME {
-                dwLowDateTime: nanos as DWORD,
-                dwHighDateTime: (nanos >> 32) as DWORD,
-            }
+    fn from(time: FILETIME) -> Self {
+        let ticks = (u64::from(time.dwHighDateTime) << 32) | u64::from(time.dwLowDateTime);
+        // Windows rejects timestamps with the top bit set, so they are never read.
+        convert::from_windows_ticks(i64::try_from(ticks).unwrap_or(i64::MAX))
+    }
+}
+
+impl TryFrom<FileTime> for FILETIME {
+    type Error = TimestampOutOfRange;
+
+    #[inline]
+    #[cfg_attr(feature = "clippy", allow(cast_possible_truncation, cast_sign_loss))]
+    fn try_from(time: FileTime) -> Result<Self, Self::Error> {
+        // Windows timestamps have a resolution of 100 nanoseconds. A zero timestamp is rejected
+        // along with earlier ones, because `SetFileTime` leaves a timestamp unchanged if given
+        // zero; positive values never reach the `0xFFFFFFFF` sentinels it also treats specially.
+        let ticks = convert::to_windows_ticks(time)
+            .filter(|&t| t > 0)
+            .ok_or_else(|| TimestampOutOfRange::new(time))?;
+        Ok(FILETIME {
+            dwLowDateTime: ticks as u32,
+            dwHighDateTime: (ticks >> 32) as u32,
+        })
+    }
+}
+
+impl TryFrom<SYSTEMTIME> for FileTime {
+    type Error = io::Error;
+
+    #[inline]
+    fn try_from(time: SYSTEMTIME) -> io::Result<Self> {
+        let mut file_time = FILETIME {
+            dwLowDateTime: 0,
+            dwHighDateTime: 0,
+        };
+        if unsafe { SystemTimeToFileTime(&time, &mut file_time) } == 0 {
+            Err(io::Error::last_os_error())
         } else {
-            FILETIME {
-                dwLowDateTime: 0xFFFF_FFFF,
-                dwHighDateTime: 0xFFFF_FFFF,
-            }
+            Ok(file_time.into())
+        }
+    }
+}
+
+impl TryFrom<FileTime> for SYSTEMTIME {
+    type Error = TimestampOutOfRange;
+
+    #[inline]
+    fn try_from(time: FileTime) -> Result<Self, Self::Error> {
+        // `SYSTEMTIME` only has millisecond precision, so any further precision is truncated.
+        let file_time = FILETIME::try_from(time)?;
+        let mut system_time = unsafe { mem::zeroed::<SYSTEMTIME>() };
+        if unsafe { FileTimeToSystemTime(&file_time, &mut system_time) } == 0 {
+            Err(TimestampOutOfRange::new(time))
+        } else {
+            Ok(system_time)
         }
     }
 }
@@ -136,18 +1005,102 @@ impl FileTimes {
 impl Builder {
     #[inline]
     /// Implementation details.
-    pub(crate) fn touch_sys<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
-        let p = into_wide_string(path);
-        let times = FileTimes::from_builder(self);
+    pub(crate) fn touch_sys(
+        &self,
+        path: &Path,
+        p: &RawPath,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let follow = self.follows(path);
+        let flags = if follow {
+            0
+        } else {
+            FILE_FLAG_OPEN_REPARSE_POINT
+        };
         let disp = match self.creation_target {
             CreationTarget::None => OPEN_EXISTING,
+            // A volume root always exists, and asking to create one can be refused even though
+            // its timestamps may be changed.
+            CreationTarget::File if is_volume_root(path) => OPEN_EXISTING,
+            // Opening an existing reparse point without creating anything fails if its target
+            // does not exist, instead of creating the target.
+            CreationTarget::File
+                if follow && self.dangling_symlinks == DanglingSymlinks::Refuse
+                    && is_reparse_point(p.as_ptr()) =>
+            {
+                OPEN_EXISTING
+            }
             CreationTarget::File => OPEN_ALWAYS,
         };
-        let flags = if self.follow_symlinks {
-            0
+        // Flushing requires write access to the file itself, not just its attributes.
+        let mut access = if self.sync_file {
+            FILE_WRITE_ATTRIBUTES | GENERIC_WRITE
         } else {
-            FILE_FLAG_OPEN_REPARSE_POINT
+            FILE_WRITE_ATTRIBUTES
         };
-        FileHandle::open(p.as_ptr(), disp, flags).and_then(|mut fd| fd.update_timestamps(&times))
+        if times.preserve_accessed {
+            access |= FILE_READ_ATTRIBUTES;
+        }
+        times.check()?;
+        // Paths are always opened with backup intent, which only has an effect once the
+        // privileges are enabled.
+        if self.backup_privileges {
+            enable_backup_privileges()?;
+        }
+        let readonly = if self.clear_readonly {
+            clear_readonly(p.as_ptr(), flags)?
+        } else {
+            None
+        };
+        let share = share_flags(self.share_mode);
+        // A path that would have been created is only opened without creating it if it is a
+        // volume root, which always exists, or a symbolic link whose target may not be created.
+        // Refusing to create the target fails the same way as `O_NOFOLLOW` does elsewhere.
+        let refused = |e: io::Error| match self.creation_target {
+            CreationTarget::File
+                if disp == OPEN_EXISTING && e.kind() == io::ErrorKind::NotFound =>
+            {
+                io::Error::from_raw_os_error(ERROR_CANT_RESOLVE_FILENAME as i32)
+            }
+            _ => e,
+        };
+        let update = || -> io::Result<bool> {
+            let (mut fd, created) = FileHandle::open(p.as_ptr(), access, share, disp, flags)
+                .map_err(|e| context(Operation::Open, path)(stream_error(path, refused(e))))?;
+            fd.update_timestamps(times)
+                .map_err(context(Operation::SetTimes, path))?;
+            if self.sync_file {
+                fd.flush().map_err(context(Operation::Sync, path))?;
+            }
+            fd.close().map_err(context(Operation::Close, path))?;
+            Ok(created)
+        };
+        let created = update();
+        if let Some((fd, attrs)) = readonly {
+            // The attribute is restored even if updating failed, but that failure is reported
+            // first.
+            let restored = fd.set_attributes(attrs);
+            let _ = created?;
+            return restored.map(|_| Outcome::UpdatedReadOnly);
+        }
+        if !created? {
+            Ok(Outcome::Updated)
+        } else if follow && is_reparse_point(p.as_ptr()) {
+            Ok(Outcome::CreatedSymlinkTarget)
+        } else {
+            Ok(Outcome::Created)
+        }
+    }
+
+    #[inline]
+    /// Implementation details.
+    pub(crate) fn touch_at_sys(
+        &self,
+        dir: &DirHandle,
+        path: &Path,
+        times: &FileTimes,
+    ) -> io::Result<Outcome> {
+        let path = dir.path.join(path);
+        self.touch_sys(&path, EncodedPath::new(&path)?.as_raw(), times)
     }
 }
@@ -0,0 +1,86 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Rate limiting for operations on multiple paths.
+
+use batch::lock;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Limits how quickly and how many paths are updated at once.
+pub struct Throttle {
+    /// The minimum time between the start of consecutive updates, if any.
+    interval: Option<Duration>,
+    /// When the next update may start.
+    next: Mutex<Instant>,
+    /// The maximum number of updates that may run at once, if any.
+    max_in_flight: Option<usize>,
+    /// How many updates are running.
+    in_flight: Mutex<usize>,
+    /// Signalled whenever an update finishes.
+    finished: Condvar,
+}
+
+/// Marks an update as running until it is dropped.
+pub struct Permit<'a>(&'a Throttle);
+
+impl Throttle {
+    #[inline]
+    /// Creates a new throttle.
+    ///
+    /// Limits of zero are treated as no limit.
+    pub fn new(ops_per_second: Option<u32>, max_in_flight: Option<usize>) -> Self {
+        Self {
+            interval: ops_per_second
+                .filter(|&n| n > 0)
+                .map(|n| Duration::from_secs(1) / n),
+            next: Mutex::new(Instant::now()),
+            max_in_flight: max_in_flight.filter(|&n| n > 0),
+            in_flight: Mutex::new(0),
+            finished: Condvar::new(),
+        }
+    }
+
+    /// Blocks until another update may start, returning a permit that must be held until the
+    /// update finishes.
+    pub fn acquire(&self) -> Permit<'_> {
+        if let Some(max) = self.max_in_flight {
+            let mut in_flight = lock(&self.in_flight);
+            while *in_flight >= max {
+                in_flight = self.finished
+                    .wait(in_flight)
+                    .unwrap_or_else(|e| e.into_inner());
+            }
+            *in_flight += 1;
+        }
+        if let Some(interval) = self.interval {
+            // Each update reserves the next free slot, so concurrent updates are spread out too.
+            let slot = {
+                let mut next = lock(&self.next);
+                let slot = (*next).max(Instant::now());
+                *next = slot + interval;
+                slot
+            };
+            let now = Instant::now();
+            if slot > now {
+                thread::sleep(slot - now);
+            }
+        }
+        Permit(self)
+    }
+}
+
+impl<'a> Drop for Permit<'a> {
+    #[inline]
+    fn drop(&mut self) {
+        if self.0.max_in_flight.is_some() {
+            *lock(&self.0.in_flight) -= 1;
+            self.0.finished.notify_one();
+        }
+    }
+}
@@ -0,0 +1,131 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Reading every timestamp of a path at once.
+
+use {FileTime, TimestampKind};
+use std::error::Error;
+use std::fmt::{self, Display, Formatter};
+use std::io;
+use std::path::Path;
+use sys;
+
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+/// The timestamps of a path, as returned by `read_times`.
+pub struct Timestamps {
+    /// When the path was last accessed.
+    accessed: FileTime,
+    /// When the contents of the path were last modified.
+    modified: FileTime,
+    /// When the path's metadata was last changed.
+    changed: FileTime,
+    /// When the path was created, if known.
+    created: Option<FileTime>,
+}
+
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+/// An error returned when a requested timestamp cannot be set on this platform.
+///
+/// This is converted into an `io::Error` of kind `Unsupported`, from which it can be recovered
+/// using `Error::downcast_ref`.
+pub struct UnsupportedTimestamp {
+    /// The timestamp that cannot be set.
+    kind: TimestampKind,
+}
+
+impl Timestamps {
+    #[inline]
+    /// Creates a new set of timestamps, such as for a `Backend` to return from `read_times`.
+    pub fn new(
+        accessed: FileTime,
+        modified: FileTime,
+        changed: FileTime,
+        created: Option<FileTime>,
+    ) -> Self {
+        Self {
+            accessed,
+            modified,
+            changed,
+            created,
+        }
+    }
+
+    #[inline]
+    /// Returns when the path was last accessed.
+    pub fn accessed(&self) -> FileTime {
+        self.accessed
+    }
+
+    #[inline]
+    /// Returns when the contents of the path were last modified.
+    pub fn modified(&self) -> FileTime {
+        self.modified
+    }
+
+    #[inline]
+    /// Returns when the path's metadata was last changed.
+    ///
+    /// This is the POSIX `ctime`, or the NTFS change time on Windows. It can only be set on
+    /// Windows, using `Builder::changed`; elsewhere, updating any other timestamp also updates
+    /// it.
+    pub fn changed(&self) -> FileTime {
+        self.changed
+    }
+
+    #[inline]
+    /// Returns when the path was created, or `None` if the platform or filesystem does not
+    /// record it.
+    pub fn created(&self) -> Option<FileTime> {
+        self.created
+    }
+}
+
+impl UnsupportedTimestamp {
+    #[cfg(any(not(windows), feature = "cap"))]
+    #[inline]
+    /// Creates a new error for a timestamp that cannot be set.
+    ///
+    /// Windows can set every timestamp, so this is only needed there for `cap-std` directories.
+    pub(crate) fn new(kind: TimestampKind) -> Self {
+        Self { kind }
+    }
+
+    #[inline]
+    /// Returns which timestamp cannot be set.
+    pub fn kind(&self) -> TimestampKind {
+        self.kind
+    }
+}
+
+impl Display for UnsupportedTimestamp {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{} timestamps cannot be set on this platform",
+            self.kind.name()
+        )
+    }
+}
+
+impl Error for UnsupportedTimestamp {}
+
+impl From<UnsupportedTimestamp> for io::Error {
+    #[inline]
+    fn from(err: UnsupportedTimestamp) -> Self {
+        io::Error::new(io::ErrorKind::Unsupported, err)
+    }
+}
+
+#[inline]
+/// Reads the access, modification, change and creation timestamps of a path, optionally
+/// following symbolic links.
+///
+/// On Linux, this uses `statx`, falling back to `fstatat` on kernels without it, which cannot
+/// report a creation timestamp; on Windows, it uses `GetFileInformationByHandleEx`.
+pub fn read_times<P: AsRef<Path>>(path: P, follow_symlinks: bool) -> io::Result<Timestamps> {
+    sys::read_times(path.as_ref(), follow_symlinks)
+}
@@ -0,0 +1,473 @@
+// Copyright (c) 2017 FaultyRAM
+//
+// Licensed under the Apache License, Version 2.0 <LICENSE-APACHE or
+// http://www.apache.org/licenses/LICENSE-2.0> or the MIT license <LICENSE-MIT
+// or http://opensource.org/licenses/MIT>, at your option. This file may not be
+// copied, modified, or distributed except according to those terms.
+
+//! Directory tree traversal.
+
+use Junctions;
+use filter::Filter;
+#[cfg(feature = "gitignore")]
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::cmp::Ordering;
+use std::ffi::OsStr;
+use std::fs::{self, Metadata, ReadDir};
+use std::io;
+#[cfg(windows)]
+use std::os::windows::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::vec;
+use sys::{self, FileId};
+
+/// An entry in a directory tree, or the path that could not be visited and why.
+pub type WalkResult = Result<Entry, (PathBuf, io::Error)>;
+
+/// A path found while walking a directory tree.
+pub struct Entry {
+    /// The path of the entry.
+    path: PathBuf,
+    /// How many levels below the root the entry is. The root itself has a depth of zero.
+    depth: usize,
+    /// The metadata of the entry, or of its target if it is a followed symbolic link.
+    metadata: Metadata,
+}
+
+#[derive(Clone, Debug)]
+/// Options that control which parts of a directory tree are visited.
+pub struct WalkOptions {
+    /// The minimum depth of entries to yield.
+    pub min_depth: usize,
+    /// The maximum depth of entries to yield, and of directories to descend into.
+    pub max_depth: usize,
+    /// Which entries to yield.
+    pub filter: Filter,
+    #[cfg(feature = "gitignore")]
+    /// Whether to skip entries matched by `.gitignore` and `.ignore` files.
+    pub respect_ignore_files: bool,
+    /// Whether to skip entries on a different filesystem than the root.
+    pub one_file_system: bool,
+    /// Whether to skip hidden entries.
+    pub skip_hidden: bool,
+    /// Whether to visit the contents of each directory in order of their names.
+    pub sorted: bool,
+}
+
+/// The contents of a directory, in the order they are visited.
+pub enum Contents {
+    /// The contents in the order the operating system lists them.
+    Unsorted(ReadDir),
+    /// The contents sorted by name, with any errors first.
+    Sorted(vec::IntoIter<io::Result<PathBuf>>),
+}
+
+#[derive(Debug, Default)]
+/// Information about a directory that applies to the paths beneath it.
+pub struct DirInfo {
+    /// Identifies the directory, for detecting loops through symbolic links.
+    id: Option<FileId>,
+    #[cfg(feature = "gitignore")]
+    /// The rules from ignore files in the directory, if any.
+    ignore: Option<Gitignore>,
+}
+
+/// What to do with a path that was visited.
+pub enum Visit {
+    /// The path should be neither yielded nor descended into.
+    Skip,
+    /// The path should be yielded without descending into it.
+    Leaf(Entry),
+    /// The path is a directory whose contents should be visited before it is yielded.
+    Descend(Entry, Contents, DirInfo),
+    /// The path is a directory whose contents could not be listed, but it should still be
+    /// yielded.
+    Unreadable(Entry, io::Error),
+    /// The path could not be visited.
+    Failed(PathBuf, io::Error),
+}
+
+/// The state shared by everything walking a single directory tree.
+pub struct Tree {
+    /// The root of the tree.
+    root: PathBuf,
+    /// The filesystem containing the root, if entries on other filesystems are skipped.
+    root_device: Option<u64>,
+    /// Whether to follow symbolic links.
+    follow_symlinks: bool,
+    /// Whether to follow NTFS junctions.
+    junctions: Junctions,
+    /// Which parts of the tree to visit.
+    options: WalkOptions,
+}
+
+/// A directory whose contents are being visited.
+struct Dir {
+    /// The directory itself, which is yielded once its contents have been visited.
+    entry: Entry,
+    /// The remaining contents of the directory.
+    contents: Contents,
+    /// Information that applies to the contents of the directory.
+    info: DirInfo,
+}
+
+/// An iterator over every path in a directory tree.
+///
+/// The contents of a directory are yielded before the directory itself, since listing a
+/// directory may update its access timestamp.
+pub struct Walk {
+    /// The tree being walked.
+    tree: Tree,
+    /// Whether the root has been visited yet.
+    started: bool,
+    /// The directories currently being visited, innermost last.
+    stack: Vec<Dir>,
+    /// An entry that is ready to be yielded.
+    pending: Option<WalkResult>,
+}
+
+impl Entry {
+    #[inline]
+    /// Returns the path of the entry.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    #[inline]
+    /// Returns how many levels below the root the entry is.
+    pub fn depth(&self) -> usize {
+        self.depth
+    }
+}
+
+impl WalkOptions {
+    /// Returns whether an entry of a directory should be updated, without descending into it.
+    ///
+    /// Only the options that apply to a single entry are checked: the entry is treated as if it
+    /// were immediately beneath the root of a tree.
+    pub fn selects_entry(&self, path: &Path, name: &Path, metadata: &Metadata) -> bool {
+        let hidden = self.skip_hidden && sys::is_hidden(path, metadata);
+        !hidden && !self.filter.is_excluded(name) && self.filter.matches(path, name, metadata)
+    }
+}
+
+impl Tree {
+    #[inline]
+    /// Prepares to walk the tree beneath a path.
+    pub fn new<P: AsRef<Path>>(
+        root: P,
+        follow_symlinks: bool,
+        junctions: Junctions,
+        options: &WalkOptions,
+    ) -> Self {
+        let root = root.as_ref().to_path_buf();
+        let root_device = if options.one_file_system {
+            sys::file_id(&root, junctions.follows(&root, follow_symlinks))
+                .ok()
+                .map(|id| id.device())
+        } else {
+            None
+        };
+        Self {
+            root,
+            root_device,
+            follow_symlinks,
+            junctions,
+            options: options.clone(),
+        }
+    }
+
+    #[inline]
+    /// Returns the root of the tree.
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Visits a path, deciding whether to yield it and whether to descend into it.
+    ///
+    /// `ancestors` yields information about the directories containing the path, innermost
+    /// first.
+    pub fn visit<'a, I>(&self, path: PathBuf, depth: usize, ancestors: I) -> Visit
+    where
+        I: Iterator<Item = &'a DirInfo> + Clone,
+    {
+        if depth > 0 && self.is_excluded(&path) {
+            return Visit::Skip;
+        }
+        let link_metadata = match fs::symlink_metadata(&path) {
+            Ok(m) => m,
+            Err(e) => return Visit::Failed(path, e),
+        };
+        // Following only makes a difference for symbolic links and junctions.
+        let follow = link_metadata.file_type().is_symlink() &&
+            self.junctions.follows(&path, self.follow_symlinks);
+        let metadata = if follow {
+            // A dangling symbolic link is still updated, so fall back to the link itself.
+            fs::metadata(&path).unwrap_or(link_metadata)
+        } else {
+            link_metadata
+        };
+        if depth > 0 && self.is_ignored(&path, metadata.is_dir(), ancestors.clone()) {
+            return Visit::Skip;
+        }
+        if depth > 0 && self.options.skip_hidden && sys::is_hidden(&path, &metadata) {
+            return Visit::Skip;
+        }
+        if depth > 0 && self.options.one_file_system {
+            // Paths on another filesystem are mount points, or are beneath one.
+            let device = sys::file_id(&path, follow)
+                .ok()
+                .map(|id| id.device());
+            if device.is_some() && device != self.root_device {
+                return Visit::Skip;
+            }
+        }
+        let entry = Entry {
+            path,
+            depth,
+            metadata,
+        };
+        if !entry.metadata.is_dir() || depth >= self.options.max_depth {
+            return Visit::Leaf(entry);
+        }
+        let id = sys::file_id(&entry.path, follow).ok();
+        // A followed symbolic link may lead back to a directory that is already being visited.
+        if follow && id.is_some() && ancestors.clone().any(|d| d.id == id) {
+            return Visit::Leaf(entry);
+        }
+        match fs::read_dir(&entry.path) {
+            Ok(contents) => {
+                let info = DirInfo {
+                    id,
+                    #[cfg(feature = "gitignore")]
+                    ignore: self.ignore_rules(&entry.path),
+                };
+                Visit::Descend(entry, Contents::new(contents, self.options.sorted), info)
+            }
+            Err(e) => Visit::Unreadable(entry, e),
+        }
+    }
+
+    #[inline]
+    /// Returns whether an entry should be yielded.
+    pub fn is_selected(&self, entry: &Entry) -> bool {
+        let relative = self.relative(&entry.path);
+        entry.depth >= self.options.min_depth &&
+            self.options.filter.matches(&entry.path, relative, &entry.metadata)
+    }
+
+    #[inline]
+    /// Returns a path relative to the root of the tree.
+    fn relative<'a>(&self, path: &'a Path) -> &'a Path {
+        path.strip_prefix(&self.root).unwrap_or(path)
+    }
+
+    #[inline]
+    /// Returns whether a path should be neither yielded nor descended into.
+    fn is_excluded(&self, path: &Path) -> bool {
+        self.options.filter.is_excluded(self.relative(path))
+    }
+
+    #[cfg(feature = "gitignore")]
+    /// Loads the rules from any ignore files in a directory, if ignore files are respected.
+    fn ignore_rules(&self, dir: &Path) -> Option<Gitignore> {
+        if !self.options.respect_ignore_files {
+            return None;
+        }
+        let mut builder = GitignoreBuilder::new(dir);
+        // Rules from `.ignore` files take precedence, so they are added last.
+        for name in &[".gitignore", ".ignore"] {
+            let path = dir.join(name);
+            if path.is_file() {
+                let _ = builder.add(path);
+            }
+        }
+        builder.build().ok().filter(|g| !g.is_empty())
+    }
+
+    #[cfg(feature = "gitignore")]
+    /// Returns whether a path is matched by the ignore files of the directories containing it.
+    fn is_ignored<'a, I>(&self, path: &Path, is_dir: bool, ancestors: I) -> bool
+    where
+        I: Iterator<Item = &'a DirInfo>,
+    {
+        // Rules in inner directories take precedence over rules in outer directories.
+        for dir in ancestors {
+            if let Some(ref ignore) = dir.ignore {
+                let matched = ignore.matched(path, is_dir);
+                if !matched.is_none() {
+                    return matched.is_ignore();
+                }
+            }
+        }
+        false
+    }
+
+    #[cfg(not(feature = "gitignore"))]
+    #[inline]
+    /// Returns whether a path is matched by the ignore files of the directories containing it.
+    fn is_ignored<'a, I>(&self, _: &Path, _: bool, _: I) -> bool
+    where
+        I: Iterator<Item = &'a DirInfo>,
+    {
+        false
+    }
+}
+
+impl Walk {
+    #[inline]
+    /// Starts walking the tree beneath a path.
+    pub fn new<P: AsRef<Path>>(
+        root: P,
+        follow_symlinks: bool,
+        junctions: Junctions,
+        options: &WalkOptions,
+    ) -> Self {
+        Self {
+            tree: Tree::new(root, follow_symlinks, junctions, options),
+            started: false,
+            stack: Vec::new(),
+            pending: None,
+        }
+    }
+
+    /// Visits a path, descending into it if it is a directory.
+    ///
+    /// Returns the entry for the path, unless it was skipped or pushed onto the stack.
+    fn visit(&mut self, path: PathBuf, depth: usize) -> Option<WalkResult> {
+        let visit = self
+            .tree
+            .visit(path, depth, self.stack.iter().rev().map(|d| &d.info));
+        match visit {
+            Visit::Skip => None,
+            Visit::Leaf(entry) => Some(Ok(entry)),
+            Visit::Descend(entry, contents, info) => {
+                self.stack.push(Dir {
+                    entry,
+                    contents,
+                    info,
+                });
+                None
+            }
+            Visit::Unreadable(entry, e) => {
+                // The directory itself can still be updated, even if its contents cannot be listed.
+                let path = entry.path.clone();
+                self.pending = Some(Ok(entry));
+                Some(Err((path, e)))
+            }
+            Visit::Failed(path, e) => Some(Err((path, e))),
+        }
+    }
+
+    /// Returns the next entry in the tree, regardless of whether it is selected.
+    fn next_entry(&mut self) -> Option<WalkResult> {
+        if let Some(item) = self.pending.take() {
+            return Some(item);
+        }
+        if !self.started {
+            self.started = true;
+            let root = self.tree.root().to_path_buf();
+            if let Some(item) = self.visit(root, 0) {
+                return Some(item);
+            }
+        }
+        loop {
+            let (next, depth) = match self.stack.last_mut() {
+                Some(dir) => (dir.contents.next(), dir.entry.depth() + 1),
+                None => return None,
+            };
+            match next {
+                Some(Ok(child)) => {
+                    if let Some(item) = self.visit(child, depth) {
+                        return Some(item);
+                    }
+                }
+                Some(Err(e)) => {
+                    let path = self.stack.last().map(|d| d.entry.path.clone());
+                    return Some(Err((path.unwrap_or_default(), e)));
+                }
+                None => {
+                    if let Some(dir) = self.stack.pop() {
+                        return Some(Ok(dir.entry));
+                    }
+                }
+            }
+        }
+    }
+}
+
+#[cfg(windows)]
+#[inline]
+/// Compares two file names by their UTF-16 code units, as Windows stores them.
+///
+/// This differs from comparing `OsStr`s directly, which compares their WTF-8 bytes and so puts
+/// characters outside the Basic Multilingual Plane after U+E000 to U+FFFF instead of before.
+fn compare_names(a: &OsStr, b: &OsStr) -> Ordering {
+    a.encode_wide().cmp(b.encode_wide())
+}
+
+#[cfg(not(windows))]
+#[inline]
+/// Compares two file names byte by byte.
+fn compare_names(a: &OsStr, b: &OsStr) -> Ordering {
+    a.cmp(b)
+}
+
+impl Contents {
+    /// Prepares the contents of a directory for visiting, sorting them if requested.
+    fn new(contents: ReadDir, sorted: bool) -> Self {
+        if !sorted {
+            return Contents::Unsorted(contents);
+        }
+        let mut entries: Vec<_> = contents.map(|r| r.map(|e| e.path())).collect();
+        entries.sort_by(|a, b| match (a, b) {
+            (Ok(a), Ok(b)) => match (a.file_name(), b.file_name()) {
+                (Some(a), Some(b)) => compare_names(a, b),
+                (a, b) => a.cmp(&b),
+            },
+            (a, b) => a.is_ok().cmp(&b.is_ok()),
+        });
+        Contents::Sorted(entries.into_iter())
+    }
+}
+
+impl Iterator for Contents {
+    type Item = io::Result<PathBuf>;
+
+    #[inline]
+    fn next(&mut self) -> Option<Self::Item> {
+        match *self {
+            Contents::Unsorted(ref mut c) => c.next().map(|r| r.map(|e| e.path())),
+            Contents::Sorted(ref mut c) => c.next(),
+        }
+    }
+}
+
+impl Default for WalkOptions {
+    #[inline]
+    fn default() -> Self {
+        Self {
+            min_depth: 0,
+            max_depth: usize::MAX,
+            filter: Filter::default(),
+            #[cfg(feature = "gitignore")]
+            respect_ignore_files: false,
+            one_file_system: false,
+            skip_hidden: false,
+            sorted: false,
+        }
+    }
+}
+
+impl Iterator for Walk {
+    type Item = WalkResult;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            match self.next_entry() {
+                Some(Ok(ref entry)) if !self.tree.is_selected(entry) => (),
+                item => return item,
+            }
+        }
+    }
+}